@@ -5,16 +5,31 @@ use cirmcut::{
         PrimitiveDiagram, SimOutputs,
     },
 };
-use egui::{CentralPanel, Color32, RichText, ScrollArea, TopBottomPanel, Ui};
+use egui::{
+    CentralPanel, Color32, Key, KeyboardShortcut, Modifiers, RichText, ScrollArea, TopBottomPanel,
+    Ui,
+};
 use egui_async::{Bind, EguiAsyncPlugin};
 use ndarray::Array4;
 
 use crate::{
+    checkpoint::{self, Checkpoint},
     circuit_editor::CircuitEditor,
+    circuit_history::{CircuitHistory, Signal},
+    common::IntPos3,
+    diagnostics::{
+        self, DivergenceMonitor, DivergenceSample, EnergyDiagnostics, EnergySample, StepQuantities,
+    },
     fdtd_editor::FdtdEditor,
+    field_quantity::FieldQuantity,
+    history::History,
     node_map::NodeMap,
+    npy,
+    particles::{ParticleSettings, Particles},
+    port_diagnostics::PortDiagnostic,
     sim::{FdtdSim, FdtdSimConfig},
-    wire_editor_3d::Wiring3D,
+    vtk,
+    wire_editor_3d::{WireId, Wiring3D, edge_axis},
 };
 
 #[derive(Clone, Copy)]
@@ -23,9 +38,14 @@ enum Pane {
     CircuitEditorCfg,
     CircuitEditorComponents,
     CircuitEditorEditComponent,
+    CircuitHistory,
     FdtdEditor,
     FdtdEditorCfg,
     FdtdEditorEditComponent,
+    PortOverview,
+    FieldSlice,
+    Particles,
+    Diagnostics,
     CommonCfg,
 }
 
@@ -38,10 +58,16 @@ impl Pane {
             Pane::CircuitEditorCfg => "Circuit configuration",
             Pane::CircuitEditorEditComponent => "Edit Circuit Component",
             Pane::CircuitEditorComponents => "Add Circuit Component",
+            Pane::CircuitHistory => "Circuit history",
 
             Pane::FdtdEditor => "FDTD simulation",
             Pane::FdtdEditorCfg => "FDTD configuration",
             Pane::FdtdEditorEditComponent => "Edit FDTD component",
+
+            Pane::PortOverview => "Ports",
+            Pane::FieldSlice => "Field slice",
+            Pane::Particles => "Particles",
+            Pane::Diagnostics => "Diagnostics",
         }
     }
 }
@@ -53,10 +79,39 @@ struct TreeBehavior {
     editor: SimulationEditor,
     /// Any error information from the simulation step is stored here.
     error_shown: Option<String>,
+    /// Why the last export or checkpoint failed, shown until dismissed
+    export_error: Option<String>,
     needs_rebuild: bool,
     file_dialog_bind: egui_async::Bind<SimulationParameters, ()>,
+    history: DocumentHistory,
+    energy: EnergyDiagnostics,
+    divergence: DivergenceMonitor,
+    /// Directory chosen for a VTK time series
+    vtk_dir_bind: egui_async::Bind<std::path::PathBuf, ()>,
+    /// Field snapshots being written every few steps, if any
+    vtk_series: Option<vtk::TimeSeries>,
+    circuit_history: CircuitHistory,
+    checkpoint_bind: egui_async::Bind<Vec<u8>, ()>,
 }
 
+/// Maximum number of undo steps kept
+const HISTORY_LEN: usize = 100;
+
+/// Undo/redo over the whole `SimulationParameters` document, fed by edits from every pane.
+struct DocumentHistory {
+    history: History<SimulationParameters>,
+    /// The parameters as of the last recorded edit
+    committed: SimulationParameters,
+    /// Serialized schematic and solver configuration of `committed`. cirmcut's types can't
+    /// be compared directly, so these are only compared when the circuit editor reports an edit.
+    committed_circuit: [String; 2],
+    /// Time of the last recorded edit, and the widget which had keyboard focus then
+    last_edit: (f64, Option<egui::Id>),
+}
+
+/// Edits further apart than this (seconds) are separate history entries
+const COALESCE_TIMEOUT: f64 = 1.0;
+
 /// Every parameter needed for a simulation to proceed, including
 /// all wires, components, configuration options, etc.
 /// The output of the simulation is a pure function of this struct.
@@ -70,11 +125,95 @@ pub struct SimulationParameters {
     circuit_solver_cfg: SolverConfig,
 }
 
-/// Controls for the simulation step (play, pause, single-step).
+/// Inputs of the coupled step which are not part of the document
+#[derive(Clone, Copy, PartialEq, Default, Debug, serde::Serialize, serde::Deserialize)]
+struct StepInputs {
+    /// Circuit time step
+    dt: f64,
+    deposit_current: bool,
+    trail_len: usize,
+}
+
+/// Quantities of a coupled step used by the diagnostics
+struct StepOutputs {
+    /// Power taken out of the field by the imposed currents (W)
+    source_power: f64,
+    /// EMF per solution vector entry
+    external_params: Vec<f64>,
+    /// Field energy removed by clearing E on the wire edges before the step (J)
+    zeroed_energy: f64,
+}
+
+/// A change to the inputs of a run, which must be replayed to reproduce it
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+enum RunEvent {
+    Parameters(Box<SimulationParameters>),
+    Inputs(StepInputs),
+    Particles(Particles),
+}
+
+/// Everything needed to reproduce the current run from the moment it was built.
+/// The circuit solver's state cannot be serialized, so checkpoints replay this instead.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct RunJournal {
+    /// Parameters the simulation was built from
+    start: SimulationParameters,
+    /// Changes taking effect before the given step
+    events: Vec<(usize, RunEvent)>,
+    last_inputs: Option<StepInputs>,
+}
+
+/// Serialized part of a checkpoint; the fields are stored in binary after it
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CheckpointHeader {
+    params: SimulationParameters,
+    journal: RunJournal,
+}
+
+/// Controls for the simulation step (play, pause, single-step, run until).
 pub struct SimulationControls {
     dt: f64,
     paused: bool,
     single_step: bool,
+    /// Steps taken per repaint while playing
+    steps_per_frame: usize,
+    /// Condition chosen for the next run
+    run_until: RunUntil,
+    probe: Probe,
+    threshold: f64,
+    crossing: Crossing,
+    /// The run in progress, which pauses the simulation once its condition is met
+    run: Option<ActiveRun>,
+}
+
+/// When a run started from the controls stops
+#[derive(Clone, Copy, PartialEq)]
+enum RunUntil {
+    Steps(usize),
+    Time(f64),
+    ProbeCrossing,
+}
+
+/// A value watched by "run until probe crosses threshold"
+#[derive(Clone, PartialEq)]
+enum Probe {
+    Field(FieldQuantity, IntPos3),
+    Circuit(Signal),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Crossing {
+    Rising,
+    Falling,
+    Either,
+}
+
+enum ActiveRun {
+    /// Until this many steps have been taken in total
+    Steps(usize),
+    Time(f64),
+    /// The probe's last value, if it could be sampled
+    ProbeCrossing(Option<f64>),
 }
 
 /// The current, transient state of the simulation and
@@ -87,12 +226,19 @@ pub struct SimulationState {
     diagram_state: DiagramState,
     nodemap: NodeMap,
     outputs: SimOutputs,
+    particles: Particles,
+    /// Elapsed simulated time (seconds)
+    time: f64,
+    /// Wiring revision and `dx` the thin-wire correction was last computed for
+    thin_wires_for: Option<(u64, f64)>,
+    journal: RunJournal,
 }
 
 /// Current state of the simulation editor.
 pub struct SimulationEditor {
     circuit: CircuitEditor,
     fdtd: FdtdEditor,
+    particles: ParticleSettings,
 }
 
 /// Application
@@ -107,6 +253,44 @@ fn load_example_save() -> Option<SimulationParameters> {
     Some(params)
 }
 
+/// Runs a saved `.emf` document for `steps` steps without a window, writing the
+/// fields every `every_n_steps` steps as a VTK time series into `out_dir`.
+///
+/// The circuit is stepped with the default time step of the controls, and no particles
+/// are simulated.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_headless(
+    emf: &std::path::Path,
+    steps: usize,
+    out_dir: &std::path::Path,
+    every_n_steps: usize,
+) -> Result<(), String> {
+    let text = std::fs::read(emf).map_err(|e| format!("Reading {}: {e}", emf.display()))?;
+    let params: SimulationParameters =
+        ron::de::from_bytes(&text).map_err(|e| format!("Parsing {}: {e}", emf.display()))?;
+    std::fs::create_dir_all(out_dir).map_err(|e| format!("Creating {}: {e}", out_dir.display()))?;
+
+    let inputs = StepInputs {
+        dt: SimulationControls::default().dt,
+        ..Default::default()
+    };
+    let mut state = SimulationState::new(&params);
+    state.rewire(&params, 0);
+    let mut series = vtk::TimeSeries::new(out_dir, "fields", every_n_steps);
+
+    let record = |series: &mut vtk::TimeSeries, state: &SimulationState| {
+        series
+            .record(&state.fdtd, &params.fdtd_config, state.time)
+            .map_err(|e| format!("Writing VTK output: {e}"))
+    };
+    record(&mut series, &state)?;
+    for _ in 0..steps {
+        state.step(&params, &inputs)?;
+        record(&mut series, &state)?;
+    }
+    Ok(())
+}
+
 impl FdtdApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         let params: SimulationParameters = cc
@@ -119,15 +303,24 @@ impl FdtdApp {
         let controls = SimulationControls::default();
         let error_shown = None;
         let editor = SimulationEditor::new(&params);
+        let history = DocumentHistory::new(&params);
 
         let behavior = TreeBehavior {
+            history,
             params,
             state,
             controls,
             editor,
             error_shown,
+            export_error: None,
             needs_rebuild: false,
             file_dialog_bind: Bind::new(true),
+            energy: EnergyDiagnostics::default(),
+            divergence: DivergenceMonitor::default(),
+            vtk_dir_bind: Bind::new(true),
+            vtk_series: None,
+            circuit_history: CircuitHistory::default(),
+            checkpoint_bind: Bind::new(true),
         };
 
         Self {
@@ -148,8 +341,38 @@ impl eframe::App for FdtdApp {
         ctx.plugin_or_default::<EguiAsyncPlugin>(); // <-- REQUIRED
         if let Some(Ok(file)) = self.behavior.file_dialog_bind.take() {
             self.behavior.params = file;
+            self.behavior.commit_edit("Open file");
             self.behavior.rebuild();
         }
+        if let Some(Ok(bytes)) = self.behavior.checkpoint_bind.take()
+            && let Err(e) = self.behavior.load_checkpoint(&bytes)
+        {
+            self.behavior.export_error = Some(format!("Loading the checkpoint failed: {e}"));
+        }
+        if let Some(Ok(dir)) = self.behavior.vtk_dir_bind.take() {
+            self.behavior.vtk_series = Some(vtk::TimeSeries::new(dir, "fields", 10));
+        }
+
+        // Undo/redo are consumed here so the individual editors never see them,
+        // except while a widget has keyboard focus, e.g. to undo typing in a text field
+        let focused = ctx.memory(|m| m.focused().is_some());
+        let (undo, redo) = ctx.input_mut(|i| {
+            if focused {
+                return (false, false);
+            }
+            let redo = i.consume_shortcut(&KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::Z,
+            )) || i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Y));
+            let undo = i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Z));
+            (undo, redo)
+        });
+        if undo {
+            self.behavior.undo();
+        }
+        if redo {
+            self.behavior.redo();
+        }
 
         TopBottomPanel::top("menu").show(ctx, |ui| {
             egui::containers::menu::MenuBar::new().ui(ui, |ui| {
@@ -168,14 +391,37 @@ impl eframe::App for FdtdApp {
                     }
                     if ui.button("New").clicked() {
                         self.behavior.params = SimulationParameters::default();
+                        self.behavior.commit_edit("New document");
                         self.behavior.rebuild();
                     }
                     if ui.button("Load example").clicked() {
                         if let Some(params) = load_example_save() {
                             self.behavior.params = params;
+                            self.behavior.commit_edit("Load example");
                         }
                         self.behavior.rebuild();
                     }
+                    ui.separator();
+                    if ui.button("Save checkpoint").clicked() {
+                        self.behavior.save_checkpoint();
+                    }
+                    if ui.button("Resume from checkpoint").clicked() {
+                        self.behavior.checkpoint_bind.request(async {
+                            let file = rfd::AsyncFileDialog::new()
+                                .add_filter("fdtdckpt", &["fdtdckpt"])
+                                .pick_file()
+                                .await
+                                .ok_or(())?;
+                            Ok(file.read().await)
+                        });
+                    }
+                    ui.separator();
+                    ui.menu_button("Export VTK", |ui| {
+                        self.behavior.show_vtk_menu(ui);
+                    });
+                    ui.menu_button("Export NumPy", |ui| {
+                        self.behavior.show_numpy_menu(ui);
+                    });
                 });
                 ui.menu_button("Preferences", |ui| {
                     if ui.button("Reset layout").clicked() {
@@ -191,6 +437,8 @@ impl eframe::App for FdtdApp {
             self.tree.ui(&mut self.behavior, ui);
         });
 
+        self.behavior.record_history(ctx);
+
         let ret = self.behavior.step();
         if let Err(e) = ret {
             self.behavior.error_shown = Some(e);
@@ -201,8 +449,269 @@ impl eframe::App for FdtdApp {
 }
 
 impl TreeBehavior {
+    /// Records any edit the panes reported this frame. Continuous edits
+    /// (drags, typing) are merged until the pointer is released.
+    fn record_history(&mut self, ctx: &egui::Context) {
+        let wiring_action = self.editor.fdtd.take_last_action();
+        let circuit_edited = self.editor.circuit.take_edited();
+
+        let label = if let Some(action) = wiring_action {
+            Some(action)
+        } else if self.params.fdtd_config != self.history.committed.fdtd_config {
+            Some("Edit FDTD configuration".to_string())
+        } else if circuit_edited {
+            let circuit = circuit_fingerprint(&self.params);
+            let committed = &self.history.committed_circuit;
+            if circuit[0] != committed[0] {
+                Some("Edit circuit".to_string())
+            } else if circuit[1] != committed[1] {
+                Some("Edit solver configuration".to_string())
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let now = ctx.input(|i| i.time);
+        let focused = ctx.memory(|m| m.focused());
+        if let Some(label) = label {
+            // Typing into another field, or pausing, starts a new entry
+            let (last_time, last_focused) = self.history.last_edit;
+            if now - last_time > COALESCE_TIMEOUT || focused != last_focused {
+                self.history.history.end_coalescing();
+            }
+            self.history.last_edit = (now, focused);
+
+            let before = std::mem::replace(&mut self.history.committed, self.params.clone());
+            self.history.history.push_coalesced(label, before);
+            self.history.committed_circuit = circuit_fingerprint(&self.params);
+            self.state.parameters_changed(&self.params);
+        }
+
+        // Each key press outside a text field, e.g. moving the selection, is an entry of its own
+        let key_pressed = ctx.input(|i| {
+            i.events
+                .iter()
+                .any(|e| matches!(e, egui::Event::Key { pressed: true, .. }))
+        });
+        if ctx.input(|i| i.pointer.any_released()) || (key_pressed && focused.is_none()) {
+            self.history.history.end_coalescing();
+        }
+    }
+
+    /// Records a whole-document edit which has just been applied to `params`
+    fn commit_edit(&mut self, label: &str) {
+        self.editor.fdtd.wiring_changed();
+        let before = std::mem::replace(&mut self.history.committed, self.params.clone());
+        self.history.history.push(label, before);
+        self.history.committed_circuit = circuit_fingerprint(&self.params);
+    }
+
+    fn undo(&mut self) {
+        let before = self.params.clone();
+        if self.history.history.undo(&mut self.params) {
+            self.history_moved(&before);
+        }
+    }
+
+    fn redo(&mut self) {
+        let before = self.params.clone();
+        if self.history.history.redo(&mut self.params) {
+            self.history_moved(&before);
+        }
+    }
+
+    fn show_history(&mut self, ui: &mut Ui) {
+        ui.label("Ctrl+Z to undo, Ctrl+Shift+Z or Ctrl+Y to redo.");
+        let before = self.params.clone();
+        if self.history.history.show_ui(ui, &mut self.params) {
+            self.history_moved(&before);
+        }
+    }
+
+    /// Takes up parameters restored from the history. The simulation is only rebuilt if
+    /// what it is built from changed; otherwise the wiring revision lets `rewire` pick up
+    /// the rest, as for edits made in the editors.
+    fn history_moved(&mut self, before: &SimulationParameters) {
+        self.history.sync(&self.params);
+        self.editor.fdtd.wiring_changed();
+        if structure(before) != structure(&self.params) {
+            self.needs_rebuild = true;
+        } else {
+            self.state.parameters_changed(&self.params);
+        }
+    }
+
+    fn step_inputs(&self) -> StepInputs {
+        StepInputs {
+            dt: self.controls.dt,
+            deposit_current: self.editor.particles.deposit_current,
+            trail_len: self.editor.particles.trail_len,
+        }
+    }
+
+    fn save_checkpoint(&mut self) {
+        let mut bytes = vec![];
+        if let Err(e) = self.state.write_checkpoint(&mut bytes, &self.params) {
+            self.export_error = Some(format!("Writing the checkpoint failed: {e}"));
+            return;
+        }
+        self.export_error = None;
+        self.file_dialog_bind.request(async move {
+            save_bytes(&bytes, "fdtdckpt", "checkpoint.fdtdckpt").await;
+            Err(())
+        });
+    }
+
+    /// Restores a checkpoint by replaying its run, so stepping continues exactly as it would have
+    fn load_checkpoint(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let (state, params) = SimulationState::resume(bytes)?;
+
+        let inputs = state.journal.last_inputs.unwrap_or(self.step_inputs());
+        self.controls.dt = inputs.dt;
+        self.editor.particles.deposit_current = inputs.deposit_current;
+        self.editor.particles.trail_len = inputs.trail_len;
+
+        self.params = params;
+        self.commit_edit("Load checkpoint");
+        self.state = state;
+        self.energy.clear();
+        self.divergence.clear();
+        self.circuit_history.clear();
+        self.needs_rebuild = false;
+        self.export_error = None;
+        Ok(())
+    }
+
+    /// Offers the written bytes for saving, or shows why they could not be written
+    fn save_export(
+        &mut self,
+        what: &str,
+        bytes: std::io::Result<Vec<u8>>,
+        extension: &'static str,
+        file_name: &'static str,
+    ) {
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.export_error = Some(format!("Exporting {what} failed: {e}"));
+                return;
+            }
+        };
+        self.export_error = None;
+        self.file_dialog_bind.request(async move {
+            save_bytes(&bytes, extension, file_name).await;
+            Err(())
+        });
+    }
+
+    fn show_vtk_menu(&mut self, ui: &mut Ui) {
+        if ui.button("Fields (.vti)").clicked() {
+            let bytes =
+                write_bytes(|w| vtk::write_fields(w, &self.state.fdtd, &self.params.fdtd_config));
+            self.save_export("fields", bytes, "vti", "fields.vti");
+        }
+        if ui.button("Wiring (.vtp)").clicked() {
+            let bytes = write_bytes(|w| {
+                vtk::write_wiring(w, &self.params.fdtd_wiring, self.params.fdtd_config.dx)
+            });
+            self.save_export("wiring", bytes, "vtp", "wiring.vtp");
+        }
+
+        // Time series are written straight to disk, which the web has no access to
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            ui.separator();
+            match &mut self.vtk_series {
+                None => {
+                    if ui.button("Record time series (.pvd)...").clicked() {
+                        self.vtk_dir_bind.request(async {
+                            let dir = rfd::AsyncFileDialog::new().pick_folder().await.ok_or(())?;
+                            Ok(dir.path().to_path_buf())
+                        });
+                    }
+                }
+                Some(series) => {
+                    ui.label(format!(
+                        "Writing to {} ({} snapshots)",
+                        series.dir().display(),
+                        series.len()
+                    ));
+                    ui.add(
+                        egui::DragValue::new(&mut series.every_n_steps)
+                            .prefix("Every ")
+                            .suffix(" steps")
+                            .range(1..=usize::MAX),
+                    );
+                    if ui.button("Stop recording").clicked() {
+                        self.vtk_series = None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn show_numpy_menu(&mut self, ui: &mut Ui) {
+        if ui.button("Fields (.npz)").clicked() {
+            let bytes =
+                write_bytes(|w| npy::write_fields(w, &self.state.fdtd, &self.params.fdtd_config));
+            self.save_export("fields", bytes, "npz", "fields.npz");
+        }
+
+        let energy = self.energy.samples();
+        if ui
+            .add_enabled(
+                !energy.is_empty(),
+                egui::Button::new("Energy balance (.npz)"),
+            )
+            .clicked()
+        {
+            let time: Vec<f64> = energy.iter().map(|s| s.time).collect();
+            let trace =
+                |f: fn(&EnergySample) -> f64| -> Vec<f64> { energy.iter().map(f).collect() };
+            let traces = [
+                ("field_energy", trace(|s| s.field_energy)),
+                ("field_power", trace(|s| s.field_power)),
+                ("boundary_flux", trace(|s| s.boundary_flux)),
+                ("source_power", trace(|s| s.source_power)),
+                ("wire_zeroing", trace(|s| s.wire_zeroing)),
+                ("residual", trace(|s| s.residual())),
+                ("exchange_power", trace(|s| s.exchange_power)),
+                ("wire_dissipation", trace(|s| s.wire_dissipation)),
+                ("component_power", trace(|s| s.component_power)),
+            ];
+            let bytes = write_bytes(|w| npy::write_traces(w, &time, &traces));
+            self.save_export("the energy balance", bytes, "npz", "energy.npz");
+        }
+
+        let divergence = self.divergence.samples();
+        if ui
+            .add_enabled(
+                !divergence.is_empty(),
+                egui::Button::new("Divergence (.npz)"),
+            )
+            .clicked()
+        {
+            let time: Vec<f64> = divergence.iter().map(|s| s.time).collect();
+            let trace = |f: fn(&DivergenceSample) -> f64| -> Vec<f64> {
+                divergence.iter().map(f).collect()
+            };
+            let traces = [
+                ("max_div_e", trace(|s| s.max_div_e)),
+                ("max_div_b", trace(|s| s.max_div_b)),
+                ("total_charge", trace(|s| s.total_charge)),
+            ];
+            let bytes = write_bytes(|w| npy::write_traces(w, &time, &traces));
+            self.save_export("the divergence", bytes, "npz", "divergence.npz");
+        }
+    }
+
     fn rebuild(&mut self) {
         self.state = SimulationState::new(&self.params);
+        self.energy.clear();
+        self.divergence.clear();
+        self.circuit_history.clear();
         self.needs_rebuild = false;
     }
 
@@ -214,49 +723,67 @@ impl TreeBehavior {
 
         // Unconditionally rebuild the primitive diagram from the diagram;
         // this allows operating the switches at runtime.
-        self.state.rewire(&self.params);
-
-        if self.controls.do_step() {
-            // Create E field from wires
-            let width = self.state.fdtd.width();
-            let elec = generate_efield(
-                &mut self.state.fdtd,
-                &self.state.nodemap,
-                &self.params.fdtd_wiring,
-                &self.state.outputs,
-            );
-            let magnetization = Array4::<f64>::zeros((width, width, width, 3));
-
-            // Step FDTD
-            let current = self
-                .state
-                .fdtd
-                .step(&self.params.fdtd_config, &magnetization, &elec);
-
-            // Copy the fdtd e-field into the soln vector
-            let external_params = readback_efield(
-                //self.state.fdtd.e_field(),
-                &current,
-                &self.state.nodemap,
-                &self.params.fdtd_wiring,
-                &self.state.circuit_solver,
-            );
-
-            // Step circuit
-            self.state.circuit_solver.step(
-                self.controls.dt,
-                &self.state.primitive_diagram,
-                &self.params.circuit_solver_cfg,
-                Some(&external_params),
-            )?;
-
-            self.state.outputs = self
+        self.state
+            .rewire(&self.params, self.editor.fdtd.wiring_revision());
+
+        for _ in 0..self.controls.steps_this_frame() {
+            self.step_once()?;
+            if self.controls.after_step(&self.params, &self.state) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes one coupled step and records it in the diagnostics and exports
+    fn step_once(&mut self) -> Result<(), String> {
+        let inputs = self.step_inputs();
+        let StepOutputs {
+            source_power,
+            external_params,
+            zeroed_energy,
+        } = self.state.step(&self.params, &inputs)?;
+
+        self.divergence
+            .record(&self.state.fdtd, &self.params.fdtd_config, self.state.time);
+
+        self.circuit_history.record(
+            self.state.time,
+            &self.state.outputs,
+            &self.state.primitive_diagram,
+            &self.state.nodemap,
+        );
+
+        let recorded = self.vtk_series.as_mut().map(|series| {
+            series.record(&self.state.fdtd, &self.params.fdtd_config, self.state.time)
+        });
+        if let Some(Err(e)) = recorded {
+            self.vtk_series = None;
+            self.export_error = Some(format!("VTK export stopped: {e}"));
+        }
+
+        if self.energy.enabled {
+            let component_params: Vec<usize> = self
                 .state
                 .circuit_solver
-                .state(&self.state.primitive_diagram);
-
-            self.state.diagram_state =
-                DiagramState::new(&self.state.outputs, &self.state.primitive_diagram);
+                .map()
+                .param_map
+                .components()
+                .collect();
+            self.energy.record(&StepQuantities {
+                sim: &self.state.fdtd,
+                cfg: &self.params.fdtd_config,
+                time: self.state.time,
+                source_power,
+                zeroed_energy,
+                external_params: &external_params,
+                component_params: &component_params,
+                nodemap: &self.state.nodemap,
+                wiring: &self.params.fdtd_wiring,
+                diagram: &self.state.primitive_diagram,
+                outputs: &self.state.outputs,
+            });
         }
 
         Ok(())
@@ -264,6 +791,220 @@ impl TreeBehavior {
 }
 
 impl SimulationState {
+    /// Advances the FDTD grid, particles and circuit together by one step
+    fn step(
+        &mut self,
+        params: &SimulationParameters,
+        inputs: &StepInputs,
+    ) -> Result<StepOutputs, String> {
+        self.journal.set_inputs(self.fdtd.steps(), *inputs);
+
+        // Create E field from wires
+        let width = self.fdtd.width();
+        let (mut elec, zeroed) = generate_efield(&mut self.fdtd, &self.nodemap, &self.outputs);
+        let cfg = &params.fdtd_config;
+        let zeroed_energy = 0.5 * cfg.eps * zeroed * cfg.dx.powi(3);
+        if inputs.deposit_current {
+            self.particles
+                .deposit_current(&params.fdtd_config, &mut elec);
+        }
+        let source_power = diagnostics::source_power(&self.fdtd, cfg, &elec);
+        let magnetization = Array4::<f64>::zeros((width, width, width, 3));
+
+        // Step FDTD
+        let current = self.fdtd.step(&params.fdtd_config, &magnetization, &elec);
+
+        // Push particles through the updated fields
+        self.particles
+            .step(&self.fdtd, &params.fdtd_config, inputs.trail_len);
+
+        // Copy the fdtd e-field into the soln vector
+        let external_params = readback_efield(
+            //self.fdtd.e_field(),
+            &current,
+            &self.nodemap,
+            &params.fdtd_wiring,
+            &self.circuit_solver,
+        );
+
+        // Step circuit
+        self.circuit_solver.step(
+            inputs.dt,
+            &self.primitive_diagram,
+            &params.circuit_solver_cfg,
+            Some(&external_params),
+        )?;
+
+        self.outputs = self.circuit_solver.state(&self.primitive_diagram);
+        self.diagram_state = DiagramState::new(&self.outputs, &self.primitive_diagram);
+        self.time += inputs.dt;
+
+        Ok(StepOutputs {
+            source_power,
+            external_params,
+            zeroed_energy,
+        })
+    }
+}
+
+impl RunJournal {
+    fn new(start: &SimulationParameters) -> Self {
+        Self {
+            start: start.clone(),
+            events: vec![],
+            last_inputs: None,
+        }
+    }
+
+    /// Records a change taking effect before the given step,
+    /// replacing a change of the same kind already made before it
+    fn push(&mut self, step: usize, event: RunEvent) {
+        if let Some((last_step, last)) = self.events.last_mut()
+            && *last_step == step
+            && std::mem::discriminant(last) == std::mem::discriminant(&event)
+        {
+            *last = event;
+            return;
+        }
+        self.events.push((step, event));
+    }
+
+    /// Records the inputs of the next step, if they differ from the last step's
+    fn set_inputs(&mut self, step: usize, inputs: StepInputs) {
+        if self.last_inputs != Some(inputs) {
+            self.last_inputs = Some(inputs);
+            self.push(step, RunEvent::Inputs(inputs));
+        }
+    }
+}
+
+impl DocumentHistory {
+    fn new(params: &SimulationParameters) -> Self {
+        Self {
+            history: History::new(HISTORY_LEN),
+            committed: params.clone(),
+            committed_circuit: circuit_fingerprint(params),
+            last_edit: (f64::NEG_INFINITY, None),
+        }
+    }
+
+    /// Accepts the parameters as they are without recording an edit
+    fn sync(&mut self, params: &SimulationParameters) {
+        self.committed = params.clone();
+        self.committed_circuit = circuit_fingerprint(params);
+    }
+}
+
+/// Serializes the schematic and the solver configuration, so circuit edits can be detected
+fn circuit_fingerprint(params: &SimulationParameters) -> [String; 2] {
+    fn ser(value: &impl serde::Serialize) -> String {
+        ron::to_string(value).unwrap_or_default()
+    }
+
+    [
+        ser(&params.circuit_diagram),
+        ser(&params.circuit_solver_cfg),
+    ]
+}
+
+/// The parts of the document the simulation is built from, so changing them needs a rebuild.
+/// Wire resistances and radii, component values and the configurations are taken up
+/// by `rewire` and `step` instead.
+fn structure(params: &SimulationParameters) -> (usize, Vec<String>, String) {
+    let wiring = &params.fdtd_wiring;
+    let mut parts = vec![];
+    for id in wiring.wires.keys() {
+        parts.push(format!("wire {id:?}"));
+    }
+    for (id, component) in &wiring.components {
+        parts.push(format!("component {id:?} {}", component.reversed));
+    }
+    for (pos, port) in &wiring.ports {
+        parts.push(format!("port {pos:?} {}", port.0));
+    }
+    parts.sort();
+
+    let [circuit, _] = circuit_fingerprint(params);
+    (params.fdtd_width, parts, circuit)
+}
+
+impl SimulationState {
+    /// Writes a checkpoint from which `resume` continues bit-identically
+    fn write_checkpoint(
+        &self,
+        w: &mut impl std::io::Write,
+        params: &SimulationParameters,
+    ) -> std::io::Result<()> {
+        let header = CheckpointHeader {
+            params: params.clone(),
+            journal: self.journal.clone(),
+        };
+        checkpoint::write(w, &header, &self.fdtd)
+    }
+
+    /// Rebuilds the state saved by `write_checkpoint`,
+    /// returning it with the document it was saved with
+    fn resume(bytes: &[u8]) -> Result<(Self, SimulationParameters), String> {
+        let checkpoint: Checkpoint<CheckpointHeader> = checkpoint::read(bytes)?;
+        let state = Self::replay(&checkpoint)?;
+        Ok((state, checkpoint.header.params))
+    }
+
+    /// Rebuilds the simulation from the start of its journal and steps it up to
+    /// `checkpoint`, whose fields must then match bit for bit.
+    fn replay(checkpoint: &Checkpoint<CheckpointHeader>) -> Result<Self, String> {
+        let journal = &checkpoint.header.journal;
+        let mut params = journal.start.clone();
+        let mut inputs = StepInputs::default();
+        let mut state = Self::new(&params);
+        // Stands in for the editor's wiring revision, so the thin-wire correction
+        // is recomputed whenever the parameters change
+        let mut revision = 0;
+
+        let mut events = journal.events.iter().peekable();
+        for step in 0..=checkpoint.steps {
+            while let Some((_, event)) = events.next_if(|(at, _)| *at == step) {
+                match event {
+                    RunEvent::Parameters(p) => {
+                        params = (**p).clone();
+                        revision += 1;
+                    }
+                    RunEvent::Inputs(i) => inputs = *i,
+                    RunEvent::Particles(p) => state.particles = p.clone(),
+                }
+            }
+            if step == checkpoint.steps {
+                break;
+            }
+            state.rewire(&params, revision);
+            state.step(&params, &inputs)?;
+        }
+
+        let identical = |a: &Array4<f64>, b: &Array4<f64>| {
+            a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
+        };
+        if !identical(state.fdtd.e_field(), &checkpoint.e_field)
+            || !identical(state.fdtd.h_field(), &checkpoint.h_field)
+        {
+            return Err("Replaying the checkpoint did not reproduce its fields".into());
+        }
+
+        state.journal = journal.clone();
+        Ok(state)
+    }
+
+    /// Records parameters edited during the run, so a checkpoint can replay them
+    fn parameters_changed(&mut self, params: &SimulationParameters) {
+        let event = RunEvent::Parameters(Box::new(params.clone()));
+        self.journal.push(self.fdtd.steps(), event);
+    }
+
+    /// Records particles injected or cleared during the run, so a checkpoint can replay them
+    fn particles_changed(&mut self) {
+        let event = RunEvent::Particles(self.particles.clone());
+        self.journal.push(self.fdtd.steps(), event);
+    }
+
     fn new(params: &SimulationParameters) -> Self {
         let mut rich = params.circuit_diagram.to_primitive_diagram();
 
@@ -273,21 +1014,43 @@ impl SimulationState {
         let outputs = circuit_solver.state(&rich.primitive);
         let diagram_state = DiagramState::new(&outputs, &rich.primitive);
 
-        Self {
+        let mut state = Self {
             fdtd: FdtdSim::new(params.fdtd_width),
             circuit_solver,
             primitive_diagram: rich.primitive,
             diagram_state,
             nodemap,
             outputs,
-        }
+            particles: Particles::default(),
+            time: 0.0,
+            thin_wires_for: None,
+            journal: RunJournal::new(params),
+        };
+        state.update_thin_wires(params, 0);
+        state
     }
 
-    fn rewire(&mut self, params: &SimulationParameters) {
+    /// `wiring_revision` must change whenever the 3D wiring is edited,
+    /// see `FdtdEditor::wiring_revision`
+    fn rewire(&mut self, params: &SimulationParameters, wiring_revision: u64) {
         let mut rich = params.circuit_diagram.to_primitive_diagram();
 
         self.nodemap = NodeMap::new(&mut rich, &params.fdtd_wiring);
         self.primitive_diagram = rich.primitive;
+        self.update_thin_wires(params, wiring_revision);
+    }
+
+    /// Keeps the thin-wire correction in step with the wire radii and grid spacing,
+    /// recomputing it only when the wiring or `dx` changed
+    fn update_thin_wires(&mut self, params: &SimulationParameters, wiring_revision: u64) {
+        let cfg = &params.fdtd_config;
+        let key = (wiring_revision, cfg.dx);
+        if self.thin_wires_for == Some(key) {
+            return;
+        }
+        self.fdtd
+            .set_thin_wires(cfg, params.fdtd_wiring.thin_wire_edges(cfg.dx));
+        self.thin_wires_for = Some(key);
     }
 }
 
@@ -334,7 +1097,8 @@ impl SimulationEditor {
         params: &mut SimulationParameters,
         state: &SimulationState,
     ) -> bool {
-        self.fdtd.show_cfg(ui, &mut params.fdtd_config)
+        self.fdtd
+            .show_cfg(ui, &mut params.fdtd_config, state.fdtd.width())
     }
 
     pub fn show_fdtd_edit_wire(
@@ -344,7 +1108,77 @@ impl SimulationEditor {
         state: &SimulationState,
     ) -> bool {
         self.fdtd
-            .show_edit_wire(ui, &state.fdtd, &mut params.fdtd_wiring)
+            .show_edit_wire(ui, &state.fdtd, &mut params.fdtd_wiring, &state.nodemap)
+    }
+
+    pub fn show_port_overview(
+        &mut self,
+        ui: &mut Ui,
+        params: &SimulationParameters,
+        state: &SimulationState,
+    ) {
+        let diagnostics = PortDiagnostic::collect(&state.nodemap, &params.fdtd_wiring);
+
+        if diagnostics.is_empty() {
+            ui.label("No ports. Add ports in the schematic or on a 3D wire node to connect them.");
+            return;
+        }
+
+        egui::Grid::new("port_overview")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                ui.strong("Name");
+                ui.strong("Schematic");
+                ui.strong("3D");
+                ui.strong("Nodes");
+                ui.strong("Status");
+                ui.end_row();
+
+                for diag in &diagnostics {
+                    let name = ui
+                        .selectable_label(false, &diag.name)
+                        .on_hover_text("Click to select the port in the schematic and 3D editors");
+                    if name.clicked() {
+                        self.circuit
+                            .select_port(&params.circuit_diagram, &diag.name);
+                    }
+                    if name.clicked()
+                        && let Some(&pos) = diag.fdtd_positions.first()
+                    {
+                        self.fdtd.select_position(pos);
+                    }
+
+                    ui.label(diag.schematic_nodes.to_string());
+                    ui.label(format!(
+                        "{}/{}",
+                        diag.fdtd_connected,
+                        diag.fdtd_positions.len()
+                    ))
+                    .on_hover_text("Connected / placed");
+                    ui.label(diag.connected_nodes().to_string());
+
+                    if diag.warnings.is_empty() {
+                        ui.label(RichText::new("OK").color(Color32::GREEN));
+                    } else {
+                        ui.vertical(|ui| {
+                            for warning in &diag.warnings {
+                                ui.label(RichText::new(warning).color(Color32::YELLOW));
+                            }
+                        });
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    pub fn show_field_slice(
+        &mut self,
+        ui: &mut Ui,
+        params: &SimulationParameters,
+        state: &SimulationState,
+    ) {
+        self.fdtd.show_slice(ui, &state.fdtd, &params.fdtd_config);
     }
 
     pub fn show_circuit_editor(
@@ -353,6 +1187,7 @@ impl SimulationEditor {
         params: &mut SimulationParameters,
         state: &SimulationState,
     ) -> bool {
+        self.circuit.highlight_ports(self.fdtd.hovered_ports());
         self.circuit
             .show_circuit_editor(ui, &mut params.circuit_diagram, &state.diagram_state)
     }
@@ -366,12 +1201,33 @@ impl SimulationEditor {
         self.fdtd.show_editor(
             ui,
             &state.fdtd,
+            &params.fdtd_config,
             &mut params.fdtd_wiring,
             &state.nodemap,
             &state.outputs,
             &self.circuit.vis_opt,
+            &state.particles,
         )
     }
+
+    pub fn show_particles(&mut self, ui: &mut Ui, state: &mut SimulationState) {
+        let mut changed = false;
+        if self.particles.show_ui(ui, state.fdtd.width()) {
+            state.particles.inject(&self.particles);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("{} particles", state.particles.particles.len()));
+            if ui.button("Clear").clicked() {
+                state.particles.clear();
+                changed = true;
+            }
+        });
+
+        if changed {
+            state.particles_changed();
+        }
+    }
 }
 
 impl Default for SimulationControls {
@@ -380,6 +1236,12 @@ impl Default for SimulationControls {
             paused: true,
             single_step: false,
             dt: 5e-3,
+            steps_per_frame: 1,
+            run_until: RunUntil::Steps(100),
+            probe: Probe::Field(FieldQuantity::EMagnitude, (0, 0, 0)),
+            threshold: 1.0,
+            crossing: Crossing::Rising,
+            run: None,
         }
     }
 }
@@ -389,6 +1251,7 @@ impl SimulationEditor {
         Self {
             circuit: CircuitEditor::default(),
             fdtd: FdtdEditor::new(cfg.fdtd_width),
+            particles: ParticleSettings::new(cfg.fdtd_width),
         }
     }
 }
@@ -406,7 +1269,12 @@ impl Default for SimulationParameters {
 }
 
 impl SimulationControls {
-    fn show_ui(&mut self, ui: &mut Ui) -> bool {
+    fn show_ui(
+        &mut self,
+        ui: &mut Ui,
+        params: &SimulationParameters,
+        state: &SimulationState,
+    ) -> bool {
         ui.horizontal(|ui| {
             play_pause_button(ui, &mut self.paused);
             if single_step_button(ui).clicked() {
@@ -414,61 +1282,309 @@ impl SimulationControls {
             }
         });
 
+        ui.label(
+            RichText::new(format!("t = {:.6e} s", state.time))
+                .monospace()
+                .size(16.0),
+        );
+        ui.label(format!("Step {}", state.fdtd.steps()));
+
         ui.horizontal(|ui| {
             ui.label("Time step: ");
             ui.add(egui::DragValue::new(&mut self.dt).speed(1e-7).suffix(" s"));
         });
+        ui.add(
+            egui::DragValue::new(&mut self.steps_per_frame)
+                .range(1..=10_000)
+                .prefix("Steps per frame: "),
+        );
+
+        ui.separator();
+        self.show_run_ui(ui, params, state);
+        ui.separator();
 
         ui.button("Reset Simulation").clicked()
     }
 
-    fn is_step_this_frame(&mut self) -> bool {
-        !self.paused || self.single_step
+    fn show_run_ui(&mut self, ui: &mut Ui, params: &SimulationParameters, state: &SimulationState) {
+        ui.strong("Run until");
+        ui.horizontal(|ui| {
+            let modes = [
+                ("Steps", RunUntil::Steps(100)),
+                ("Time", RunUntil::Time(state.time + 100.0 * self.dt)),
+                ("Probe", RunUntil::ProbeCrossing),
+            ];
+            for (name, mode) in modes {
+                let selected =
+                    std::mem::discriminant(&self.run_until) == std::mem::discriminant(&mode);
+                if ui.selectable_label(selected, name).clicked() && !selected {
+                    self.run_until = mode;
+                }
+            }
+        });
+
+        match &mut self.run_until {
+            RunUntil::Steps(n) => {
+                ui.add(
+                    egui::DragValue::new(n)
+                        .range(1..=usize::MAX)
+                        .prefix("Run ")
+                        .suffix(" steps"),
+                );
+            }
+            RunUntil::Time(t) => {
+                ui.add(
+                    egui::DragValue::new(t)
+                        .speed(1e-4)
+                        .prefix("Until t = ")
+                        .suffix(" s"),
+                );
+            }
+            RunUntil::ProbeCrossing => {
+                self.show_probe_ui(ui, params, state);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Run").clicked() {
+                let probe = self.probe.sample(params, state);
+                self.start_run(state.fdtd.steps(), probe);
+            }
+            if let Some(run) = &self.run {
+                let status = match run {
+                    ActiveRun::Steps(target) => {
+                        format!("{} steps left", target.saturating_sub(state.fdtd.steps()))
+                    }
+                    ActiveRun::Time(t) => format!("until t = {t:.4e} s"),
+                    ActiveRun::ProbeCrossing(_) => "waiting for probe".to_string(),
+                };
+                ui.label(status);
+                if ui.button("Cancel").clicked() {
+                    self.run = None;
+                    self.paused = true;
+                }
+            }
+        });
     }
 
-    fn do_step(&mut self) -> bool {
-        let ret = self.is_step_this_frame();
+    fn show_probe_ui(
+        &mut self,
+        ui: &mut Ui,
+        params: &SimulationParameters,
+        state: &SimulationState,
+    ) {
+        let width = state.fdtd.width();
+        let center = (width / 2, width / 2, width / 2);
+        let kinds = [
+            ("Field", Probe::Field(FieldQuantity::EMagnitude, center)),
+            (
+                "Port voltage",
+                Probe::Circuit(Signal::PortVoltage(String::new())),
+            ),
+            ("Node voltage", Probe::Circuit(Signal::NodeVoltage(0))),
+            (
+                "Component current",
+                Probe::Circuit(Signal::ComponentCurrent(0)),
+            ),
+        ];
+        egui::ComboBox::from_label("Probe")
+            .selected_text(self.probe.label(state))
+            .show_ui(ui, |ui| {
+                for (name, probe) in kinds {
+                    if ui.selectable_label(false, name).clicked() {
+                        self.probe = probe;
+                    }
+                }
+            });
+
+        match &mut self.probe {
+            Probe::Field(quantity, (x, y, z)) => {
+                egui::ComboBox::from_label("Quantity")
+                    .selected_text(quantity.name())
+                    .show_ui(ui, |ui| {
+                        for q in FieldQuantity::ALL {
+                            ui.selectable_value(quantity, q, q.name());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    let max = width.saturating_sub(1);
+                    for (c, name) in [x, y, z].into_iter().zip(["x: ", "y: ", "z: "]) {
+                        ui.add(egui::DragValue::new(c).range(0..=max).prefix(name));
+                    }
+                });
+            }
+            Probe::Circuit(Signal::PortVoltage(name)) => {
+                let mut ports: Vec<&String> = state.nodemap.port_nodes.keys().collect();
+                ports.sort();
+                egui::ComboBox::from_label("Port")
+                    .selected_text(name.as_str())
+                    .show_ui(ui, |ui| {
+                        for port in ports {
+                            ui.selectable_value(name, port.clone(), port);
+                        }
+                    });
+            }
+            Probe::Circuit(Signal::NodeVoltage(idx)) => {
+                let max = state.primitive_diagram.num_nodes.saturating_sub(1);
+                ui.add(egui::DragValue::new(idx).range(0..=max).prefix("Node "));
+            }
+            Probe::Circuit(Signal::ComponentCurrent(idx)) => {
+                let max = state.primitive_diagram.two_terminal.len().saturating_sub(1);
+                ui.add(
+                    egui::DragValue::new(idx)
+                        .range(0..=max)
+                        .prefix("Component "),
+                );
+            }
+            Probe::Circuit(signal) => {
+                ui.label(signal.label(&state.primitive_diagram, &state.nodemap));
+            }
+        }
+
+        ui.add(
+            egui::DragValue::new(&mut self.threshold)
+                .speed(1e-3)
+                .prefix("Threshold: "),
+        );
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.crossing, Crossing::Rising, "Rising");
+            ui.selectable_value(&mut self.crossing, Crossing::Falling, "Falling");
+            ui.selectable_value(&mut self.crossing, Crossing::Either, "Either");
+        });
+        match self.probe.sample(params, state) {
+            Some(value) => ui.label(format!("Value: {value:.4e}")),
+            None => ui.label(RichText::new("Probe is not in the simulation").color(Color32::RED)),
+        };
+    }
 
+    /// Number of steps to take this frame
+    fn steps_this_frame(&mut self) -> usize {
         if self.single_step {
             self.single_step = false;
+            return 1;
         }
 
-        ret
+        if self.paused {
+            // Pausing cancels a run in progress
+            self.run = None;
+            0
+        } else {
+            self.steps_per_frame.max(1)
+        }
+    }
+
+    /// Starts a run with the chosen condition, from the given step count and probe value
+    fn start_run(&mut self, steps: usize, probe: Option<f64>) {
+        self.run = Some(match self.run_until {
+            RunUntil::Steps(n) => ActiveRun::Steps(steps.saturating_add(n)),
+            RunUntil::Time(t) => ActiveRun::Time(t),
+            RunUntil::ProbeCrossing => ActiveRun::ProbeCrossing(probe),
+        });
+        self.paused = false;
+    }
+
+    /// Checks the run in progress after a step, pausing if it is complete.
+    /// Returns true if no more steps should be taken this frame.
+    fn after_step(&mut self, params: &SimulationParameters, state: &SimulationState) -> bool {
+        let probe = match self.run {
+            Some(ActiveRun::ProbeCrossing(_)) => self.probe.sample(params, state),
+            _ => None,
+        };
+        self.run_complete(state.fdtd.steps(), state.time, probe)
+    }
+
+    /// Like `after_step`, given the step count, clock and probe value after the step
+    fn run_complete(&mut self, steps: usize, time: f64, probe: Option<f64>) -> bool {
+        let done = match &mut self.run {
+            None => false,
+            Some(ActiveRun::Steps(target)) => steps >= *target,
+            // The clock is a sum of time steps and so inexact; stop within half a step
+            Some(ActiveRun::Time(t)) => time >= *t - 0.5 * self.dt,
+            Some(ActiveRun::ProbeCrossing(prev)) => {
+                let crossed = match (*prev, probe) {
+                    (Some(a), Some(b)) => {
+                        let rising = a < self.threshold && b >= self.threshold;
+                        let falling = a > self.threshold && b <= self.threshold;
+                        match self.crossing {
+                            Crossing::Rising => rising,
+                            Crossing::Falling => falling,
+                            Crossing::Either => rising || falling,
+                        }
+                    }
+                    _ => false,
+                };
+                *prev = probe;
+                crossed
+            }
+        };
+
+        if done {
+            self.run = None;
+            self.paused = true;
+        }
+        done
     }
 }
 
-fn generate_efield(
-    fdtd: &mut FdtdSim,
-    nodemap: &NodeMap,
-    wiring: &Wiring3D,
-    outs: &SimOutputs,
-) -> Array4<f64> {
-    let width = fdtd.width();
-    let mut external_field = Array4::<f64>::zeros((width, width, width, 3));
+impl Probe {
+    fn label(&self, state: &SimulationState) -> String {
+        match self {
+            Probe::Field(quantity, pos) => format!("{} at {pos:?}", quantity.name()),
+            Probe::Circuit(signal) => signal.label(&state.primitive_diagram, &state.nodemap),
+        }
+    }
 
-    for wire_id @ (a, b) in wiring.wires.keys() {
-        let (x, y, z) = *a;
-        let (bx, by, bz) = *b;
+    fn sample(&self, params: &SimulationParameters, state: &SimulationState) -> Option<f64> {
+        match self {
+            &Probe::Field(quantity, pos @ (x, y, z)) => {
+                let width = state.fdtd.width();
+                (x < width && y < width && z < width)
+                    .then(|| quantity.sample(&state.fdtd, &params.fdtd_config, pos))
+            }
+            Probe::Circuit(signal) => signal.sample(&state.outputs, &state.nodemap),
+        }
+    }
+}
 
-        assert!(bx > x || by > y || bz > z);
+/// Returns the imposed current per edge, and the sum of |E|² on the wire edges,
+/// whose E is cleared
+/// Index of the field component along a normalized unit edge, or `None` if `edge` is not
+/// such an edge inside the grid, as can happen with wiring loaded from a file
+fn edge_index(width: usize, edge @ (a, b): WireId) -> Option<(usize, usize, usize, usize)> {
+    let axis = edge_axis(edge);
+    let mut end = [a.0, a.1, a.2];
+    end[axis] += 1;
+    let inside = [b.0, b.1, b.2].iter().all(|&c| c < width);
+    ((end[0], end[1], end[2]) == b && inside).then_some((a.0, a.1, a.2, axis))
+}
 
-        let idx = nodemap.component_idx_map[wire_id];
-        let current = outs.two_terminal_current[idx];
+fn generate_efield(fdtd: &mut FdtdSim, nodemap: &NodeMap, outs: &SimOutputs) -> (Array4<f64>, f64) {
+    let width = fdtd.width();
+    let mut external_field = Array4::<f64>::zeros((width, width, width, 3));
+    let mut zeroed = 0.0;
 
-        let dim = if bx > x {
-            0
-        } else if by > y {
-            1
-        } else {
-            2
+    for (&edge, &idx) in &nodemap.component_idx_map {
+        let Some(coord) = edge_index(width, edge) else {
+            continue;
         };
+        let current = outs.two_terminal_current[idx];
 
-        let coord = (x, y, z, dim);
         external_field[coord] = current;
+        zeroed += fdtd.e_field[coord].powi(2);
         fdtd.e_field[coord] = 0.0;
     }
 
-    external_field
+    // Lumped components only inject their current
+    for (&edge, &(idx, reversed)) in &nodemap.lumped_idx_map {
+        let Some(coord) = edge_index(width, edge) else {
+            continue;
+        };
+        let current = outs.two_terminal_current[idx];
+        let current = if reversed { -current } else { current };
+        external_field[coord] += current;
+    }
+
+    (external_field, zeroed)
 }
 
 fn readback_efield(
@@ -480,19 +1596,12 @@ fn readback_efield(
     let n = outs.map().vector_size();
     let mut external_params = vec![0_f64; n];
 
-    for (wire_id @ (a, b), wire) in wiring.wires.iter() {
-        let (x, y, z) = *a;
-        let (bx, by, bz) = *b;
-
-        assert!(bx > x || by > y || bz > z);
-
-        let current = if bx > x {
-            field[(x, y, z, 0)]
-        } else if by > y {
-            field[(x, y, z, 1)]
-        } else {
-            field[(x, y, z, 2)]
+    for (wire_id, segment) in &nodemap.edge_segment_map {
+        let wire = &wiring.wires[segment];
+        let Some(coord) = edge_index(field.dim().0, *wire_id) else {
+            continue;
         };
+        let current = field[coord];
 
         let component_idx = nodemap.component_idx_map.get(wire_id).unwrap();
         let soln_vec_idx = outs.map.param_map.components().nth(*component_idx).unwrap();
@@ -507,24 +1616,45 @@ fn create_tree() -> egui_tiles::Tree<Pane> {
     let mut tiles = egui_tiles::Tiles::default();
 
     //let [common, fdtd, circuit, fdtd_cfg, cricuit_cfg] = [Pane::CommonCfg, Pane::FdtdEditor, Pane::CircuitEditor, Pane::FdtdEditorCfg, Pane::CircuitEditorCfg].map(|pane| tiles.insert_tab_tile(vec![tiles.insert_pane(pane)]));
-    let [common, circuit, circuit_cfg, circuit_components, circuit_edit_component, fdtd, fdtd_cfg, fdtd_component] =
-        [
-            Pane::CommonCfg,
-            Pane::CircuitEditor,
-            Pane::CircuitEditorCfg,
-            Pane::CircuitEditorComponents,
-            Pane::CircuitEditorEditComponent,
-            Pane::FdtdEditor,
-            Pane::FdtdEditorCfg,
-            Pane::FdtdEditorEditComponent,
-        ]
-        .map(|pane| tiles.insert_pane(pane));
-
-    let left_bar = tiles.insert_vertical_tile(vec![common, fdtd_cfg]);
-    let fdtdstuff = tiles.insert_horizontal_tile(vec![left_bar, fdtd, fdtd_component]);
-
-    let circuithoriz =
-        tiles.insert_horizontal_tile(vec![circuit_cfg, circuit, circuit_edit_component]);
+    let [
+        common,
+        circuit,
+        circuit_cfg,
+        circuit_components,
+        circuit_edit_component,
+        circuit_history,
+        fdtd,
+        fdtd_cfg,
+        fdtd_component,
+        ports,
+        slice,
+        particles,
+        diagnostics,
+    ] = [
+        Pane::CommonCfg,
+        Pane::CircuitEditor,
+        Pane::CircuitEditorCfg,
+        Pane::CircuitEditorComponents,
+        Pane::CircuitEditorEditComponent,
+        Pane::CircuitHistory,
+        Pane::FdtdEditor,
+        Pane::FdtdEditorCfg,
+        Pane::FdtdEditorEditComponent,
+        Pane::PortOverview,
+        Pane::FieldSlice,
+        Pane::Particles,
+        Pane::Diagnostics,
+    ]
+    .map(|pane| tiles.insert_pane(pane));
+
+    let fdtd_tabs = tiles.insert_tab_tile(vec![fdtd_cfg, particles]);
+    let left_bar = tiles.insert_vertical_tile(vec![common, fdtd_tabs]);
+    let diagnostics_tabs = tiles.insert_tab_tile(vec![ports, diagnostics]);
+    let right_bar = tiles.insert_vertical_tile(vec![fdtd_component, slice, diagnostics_tabs]);
+    let fdtdstuff = tiles.insert_horizontal_tile(vec![left_bar, fdtd, right_bar]);
+
+    let circuit_tabs = tiles.insert_tab_tile(vec![circuit_edit_component, circuit_history]);
+    let circuithoriz = tiles.insert_horizontal_tile(vec![circuit_cfg, circuit, circuit_tabs]);
     let circuitstuff = tiles.insert_vertical_tile(vec![circuithoriz, circuit_components]);
 
     let root = tiles.insert_vertical_tile(vec![fdtdstuff, circuitstuff]);
@@ -553,12 +1683,28 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
         match pane {
             Pane::CommonCfg => {
                 ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
-                    self.needs_rebuild |= self.controls.show_ui(ui);
+                    self.needs_rebuild |= self.controls.show_ui(ui, &self.params, &self.state);
                     ui.separator();
 
                     if let Some(error) = &self.error_shown {
                         ui.label(RichText::new(error).color(Color32::RED));
                     }
+                    if let Some(error) = &self.export_error {
+                        let dismiss = ui
+                            .horizontal(|ui| {
+                                ui.label(RichText::new(error).color(Color32::RED));
+                                ui.small_button("Dismiss").clicked()
+                            })
+                            .inner;
+                        if dismiss {
+                            self.export_error = None;
+                        }
+                    }
+
+                    ui.separator();
+                    ui.collapsing("History", |ui| {
+                        self.show_history(ui);
+                    });
                 });
             }
             Pane::CircuitEditorCfg => {
@@ -576,6 +1722,21 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
                             self.editor.show_circuit_components(ui, &mut self.params);
                     });
             }
+            Pane::CircuitHistory => {
+                ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
+                    let export = self.circuit_history.show_ui(
+                        ui,
+                        &self.state.primitive_diagram,
+                        &self.state.nodemap,
+                        self.editor.fdtd.selected_wire(),
+                    );
+                    if export {
+                        let bytes = write_bytes(|w| self.circuit_history.write_csv(w));
+                        let name = "circuit_history.csv";
+                        self.save_export("the circuit history", bytes, "csv", name);
+                    }
+                });
+            }
             Pane::CircuitEditorEditComponent => {
                 ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
                     self.needs_rebuild |=
@@ -604,8 +1765,37 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
                     self.needs_rebuild |=
                         self.editor
                             .show_fdtd_edit_wire(ui, &mut self.params, &self.state);
+
+                    // The wire editor's actions are entries of the document history
+                    ui.collapsing("History", |ui| {
+                        self.show_history(ui);
+                    });
+                });
+            }
+            Pane::PortOverview => {
+                ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
+                    self.editor
+                        .show_port_overview(ui, &self.params, &self.state);
+                });
+            }
+            Pane::Particles => {
+                ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
+                    self.editor.show_particles(ui, &mut self.state);
                 });
             }
+            Pane::Diagnostics => {
+                ScrollArea::vertical().id_salt(pane.name()).show(ui, |ui| {
+                    ui.collapsing("Energy balance", |ui| {
+                        self.energy.show_ui(ui);
+                    });
+                    ui.collapsing("Gauss's law", |ui| {
+                        self.divergence.show_ui(ui);
+                    });
+                });
+            }
+            Pane::FieldSlice => {
+                self.editor.show_field_slice(ui, &self.params, &self.state);
+            }
         }
 
         egui_tiles::UiResponse::None
@@ -631,6 +1821,30 @@ async fn save_file(params: &SimulationParameters) {
         }
 }
 
+/// Collects what a writer produces, for offering it as a download
+fn write_bytes(
+    write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    write(&mut bytes)?;
+    Ok(bytes)
+}
+
+async fn save_bytes(bytes: &[u8], extension: &str, file_name: &str) {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .add_filter(extension, &[extension])
+        .set_file_name(file_name)
+        .save_file()
+        .await
+    else {
+        return;
+    };
+
+    if let Err(e) = file.write(bytes).await {
+        log::error!("Error writing file: {e}");
+    }
+}
+
 async fn open_file() -> Option<SimulationParameters> {
     let file = rfd::AsyncFileDialog::new()
         .add_filter("emf", &["emf"])
@@ -671,3 +1885,167 @@ fn single_step_button(ui: &mut Ui) -> egui::Response {
 
     ui.add(button)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controls(run_until: RunUntil) -> SimulationControls {
+        SimulationControls {
+            run_until,
+            ..Default::default()
+        }
+    }
+
+    /// Steps the clock of the controls until the run completes, returning the step count
+    fn run(controls: &mut SimulationControls, probe: impl Fn(usize) -> Option<f64>) -> usize {
+        controls.start_run(0, probe(0));
+        let mut time = 0.0;
+        for steps in 1..1000 {
+            time += controls.dt;
+            if controls.run_complete(steps, time, probe(steps)) {
+                assert!(controls.paused);
+                return steps;
+            }
+        }
+        panic!("run never completed");
+    }
+
+    #[test]
+    fn run_steps() {
+        let mut controls = controls(RunUntil::Steps(7));
+        assert_eq!(run(&mut controls, |_| None), 7);
+        assert!(controls.run.is_none());
+    }
+
+    #[test]
+    fn run_steps_saturates() {
+        let mut controls = controls(RunUntil::Steps(usize::MAX));
+        controls.start_run(10, None);
+        assert!(matches!(controls.run, Some(ActiveRun::Steps(usize::MAX))));
+    }
+
+    #[test]
+    fn run_until_time_tolerates_rounding() {
+        // Ten steps of 0.1 add up to just under 1
+        let mut controls = controls(RunUntil::Time(1.0));
+        controls.dt = 0.1;
+        assert_eq!(run(&mut controls, |_| None), 10);
+
+        // Three add up to just over 0.3
+        let mut controls = SimulationControls {
+            run_until: RunUntil::Time(0.3),
+            ..controls
+        };
+        assert_eq!(run(&mut controls, |_| None), 3);
+    }
+
+    #[test]
+    fn run_until_probe_crosses() {
+        // Rises through 1 between steps 4 and 5, falls back between 8 and 9
+        let probe = |steps: usize| Some(if (5..=8).contains(&steps) { 2.0 } else { 0.0 });
+
+        let mut rising = controls(RunUntil::ProbeCrossing);
+        assert_eq!(run(&mut rising, probe), 5);
+
+        let mut falling = controls(RunUntil::ProbeCrossing);
+        falling.crossing = Crossing::Falling;
+        assert_eq!(run(&mut falling, probe), 9);
+
+        // Starting above the threshold, either direction stops at the first crossing
+        let mut either = controls(RunUntil::ProbeCrossing);
+        either.crossing = Crossing::Either;
+        assert_eq!(run(&mut either, |steps| probe(steps + 6)), 3);
+    }
+
+    #[test]
+    fn missing_probe_never_crosses() {
+        let mut controls = controls(RunUntil::ProbeCrossing);
+        controls.start_run(0, None);
+        assert!(!(1..100).any(|steps| controls.run_complete(steps, 0.0, None)));
+    }
+
+    #[test]
+    fn edges_from_files_outside_the_grid_are_skipped() {
+        assert_eq!(edge_index(4, ((1, 2, 2), (1, 3, 2))), Some((1, 2, 2, 1)));
+        // Reversed, degenerate, multi-cell and out of bounds
+        assert_eq!(edge_index(4, ((1, 3, 2), (1, 2, 2))), None);
+        assert_eq!(edge_index(4, ((1, 2, 2), (1, 2, 2))), None);
+        assert_eq!(edge_index(4, ((0, 0, 0), (2, 0, 0))), None);
+        assert_eq!(edge_index(4, ((3, 0, 0), (4, 0, 0))), None);
+    }
+
+    #[test]
+    fn checkpoints_resume_bit_identically() {
+        let params = SimulationParameters {
+            fdtd_width: 8,
+            fdtd_wiring: ron::from_str(
+                "(wires: {((2, 2, 3), (2, 5, 3)): (resistance: 1.0), \
+                ((2, 5, 3), (5, 5, 3)): (resistance: 2.0)}, ports: {})",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let inputs = StepInputs {
+            dt: 1e-3,
+            deposit_current: true,
+            trail_len: 5,
+        };
+
+        let mut edited = params.clone();
+        edited.fdtd_config.dx *= 2.0;
+
+        // Particles and an edit during the run must be replayed from the journal
+        let mut run = SimulationState::new(&params);
+        run.particles.inject(&ParticleSettings::new(8));
+        run.particles_changed();
+        let step = |state: &mut SimulationState, params: &SimulationParameters, revision| {
+            state.rewire(params, revision);
+            state.step(params, &inputs).unwrap();
+        };
+
+        for _ in 0..2 {
+            step(&mut run, &params, 0);
+        }
+        run.parameters_changed(&edited);
+        for _ in 0..2 {
+            step(&mut run, &edited, 1);
+        }
+        let mut bytes = vec![];
+        run.write_checkpoint(&mut bytes, &edited).unwrap();
+        let (mut resumed, saved) = SimulationState::resume(&bytes).unwrap();
+        assert_eq!(resumed.journal.last_inputs, Some(inputs));
+        assert_eq!(saved.fdtd_config.dx, edited.fdtd_config.dx);
+
+        for _ in 0..3 {
+            step(&mut run, &edited, 1);
+            step(&mut resumed, &edited, 1);
+        }
+
+        let bits = |values: &mut dyn Iterator<Item = &f64>| -> Vec<u64> {
+            values.map(|v| v.to_bits()).collect()
+        };
+        assert_eq!(resumed.fdtd.steps(), run.fdtd.steps());
+        assert_eq!(resumed.time.to_bits(), run.time.to_bits());
+        assert_eq!(
+            bits(&mut resumed.fdtd.e_field.iter()),
+            bits(&mut run.fdtd.e_field.iter())
+        );
+        assert_eq!(
+            bits(&mut resumed.fdtd.h_field.iter()),
+            bits(&mut run.fdtd.h_field.iter())
+        );
+        assert_eq!(
+            bits(&mut resumed.outputs.two_terminal_current.iter()),
+            bits(&mut run.outputs.two_terminal_current.iter())
+        );
+        let positions = |state: &SimulationState| -> Vec<[u32; 3]> {
+            let particles = &state.particles.particles;
+            particles
+                .iter()
+                .map(|p| p.pos.to_array().map(f32::to_bits))
+                .collect()
+        };
+        assert_eq!(positions(&resumed), positions(&run));
+    }
+}
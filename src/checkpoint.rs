@@ -0,0 +1,156 @@
+//! Binary checkpoint files: a serialized header followed by the raw FDTD fields.
+//!
+//! Layout: the magic bytes, a little-endian `u32` format version, the `u64` length of
+//! the RON encoded header, the header, the step count and grid width as `u64`s, then E and H
+//! as little-endian `f64` in C order. Floats are stored bit for bit.
+
+use std::io::{self, Write};
+
+use ndarray::Array4;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::sim::FdtdSim;
+
+const MAGIC: &[u8; 8] = b"FDTDCKPT";
+const VERSION: u32 = 1;
+
+/// Contents of a checkpoint file
+pub struct Checkpoint<T> {
+    pub header: T,
+    /// Number of FDTD steps taken when the checkpoint was saved
+    pub steps: usize,
+    pub e_field: Array4<f64>,
+    pub h_field: Array4<f64>,
+}
+
+pub fn write<T: Serialize>(w: &mut impl Write, header: &T, sim: &FdtdSim) -> io::Result<()> {
+    let header = ron::to_string(header).map_err(io::Error::other)?;
+
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(header.len() as u64).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    w.write_all(&(sim.steps() as u64).to_le_bytes())?;
+    w.write_all(&(sim.width() as u64).to_le_bytes())?;
+    for field in [sim.e_field(), sim.h_field()] {
+        for v in field.iter() {
+            w.write_all(&v.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read<T: DeserializeOwned>(bytes: &[u8]) -> Result<Checkpoint<T>, String> {
+    let mut reader = Reader(bytes);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a checkpoint file".into());
+    }
+    let version = u32::from_le_bytes(reader.array()?);
+    if version != VERSION {
+        return Err(format!("Unsupported checkpoint version {version}"));
+    }
+
+    let header_len = reader.usize()?;
+    let header = std::str::from_utf8(reader.take(header_len)?).map_err(|e| e.to_string())?;
+    let header: T = ron::from_str(header).map_err(|e| e.to_string())?;
+
+    let steps = reader.usize()?;
+    let width = reader.usize()?;
+    let mut field = || -> Result<Array4<f64>, String> {
+        let len = width
+            .checked_pow(3)
+            .and_then(|n| n.checked_mul(3))
+            .ok_or("Grid too large")?;
+        let values = (0..len)
+            .map(|_| Ok(f64::from_le_bytes(reader.array()?)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        Array4::from_shape_vec((width, width, width, 3), values).map_err(|e| e.to_string())
+    };
+    let e_field = field()?;
+    let h_field = field()?;
+
+    Ok(Checkpoint {
+        header,
+        steps,
+        e_field,
+        h_field,
+    })
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("Checkpoint file is truncated".into());
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        usize::try_from(u64::from_le_bytes(self.array()?)).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::FdtdSimConfig;
+
+    #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+    struct Header {
+        name: String,
+        time: f64,
+    }
+
+    /// A grid with some non-trivial fields after a few steps
+    fn stepped_sim() -> FdtdSim {
+        let cfg = FdtdSimConfig::default();
+        let mut sim = FdtdSim::new(5);
+        let magnetization = Array4::zeros((5, 5, 5, 3));
+        let mut current = Array4::zeros((5, 5, 5, 3));
+        current[(2, 2, 2, 2)] = 1.0;
+        for _ in 0..3 {
+            sim.step(&cfg, &magnetization, &current);
+        }
+        sim
+    }
+
+    #[test]
+    fn round_trip_is_bit_exact() {
+        let sim = stepped_sim();
+        let header = Header {
+            name: "run".into(),
+            time: 0.1 + 0.2,
+        };
+        let mut bytes = vec![];
+        write(&mut bytes, &header, &sim).unwrap();
+
+        let checkpoint: Checkpoint<Header> = read(&bytes).unwrap();
+        assert_eq!(checkpoint.header, header);
+        assert_eq!(checkpoint.steps, 3);
+        let bits = |a: &Array4<f64>| a.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&checkpoint.e_field), bits(sim.e_field()));
+        assert_eq!(bits(&checkpoint.h_field), bits(sim.h_field()));
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let mut bytes = vec![];
+        write(&mut bytes, &(), &stepped_sim()).unwrap();
+
+        assert!(read::<()>(b"not a checkpoint").is_err());
+        assert!(read::<()>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[MAGIC.len()] = 99;
+        assert!(read::<()>(&wrong_version).is_err());
+    }
+}
@@ -2,12 +2,10 @@ use cirmcut::cirmcut_sim::{
     ThreeTerminalComponent, TwoTerminalComponent,
     solver::{SolverConfig, SolverMode},
 };
-use egui::{
-    Color32, DragValue, Key, Pos2, Rect, RichText, Ui, Vec2,
-};
+use egui::{Color32, DragValue, Key, Pos2, Rect, RichText, Stroke, Ui, Vec2};
 
 use cirmcut::circuit_widget::{
-    Diagram, DiagramEditor, DiagramState, VisualizationOptions, draw_grid,
+    Diagram, DiagramEditor, DiagramState, VisualizationOptions, cellpos_to_egui, draw_grid,
     egui_to_cellpos,
 };
 
@@ -18,6 +16,12 @@ pub struct CircuitEditor {
 
     pub vis_opt: VisualizationOptions,
     error: Option<String>,
+    /// Port picked in the port overview, outlined in the schematic until the next click
+    selected_port: Option<String>,
+    /// Ports on the net hovered in the 3D editor and the net's colour, outlined while hovered
+    highlighted_ports: Option<(Vec<String>, Color32)>,
+    /// Whether the diagram or solver configuration was edited since `take_edited`
+    edited: bool,
 }
 
 /*
@@ -37,11 +41,34 @@ impl Default for CircuitEditor {
             editor: DiagramEditor::new(),
             view_rect: Rect::from_center_size(Pos2::ZERO, Vec2::splat(1000.0)),
             debug_draw: false,
+            selected_port: None,
+            highlighted_ports: None,
+            edited: false,
         }
     }
 }
 
 impl CircuitEditor {
+    /// Outlines the schematic ports with this name and centers the view on the first.
+    /// The diagram editor has no API for selecting a port, so its own selection is cleared.
+    pub fn select_port(&mut self, diagram: &Diagram, name: &str) {
+        self.editor.reset_selection();
+        self.selected_port = Some(name.to_string());
+        if let Some((pos, _)) = diagram.ports.iter().find(|(_, port)| port == name) {
+            self.view_rect = Rect::from_center_size(cellpos_to_egui(*pos), self.view_rect.size());
+        }
+    }
+
+    /// Whether the diagram or solver configuration may have been edited since this was last called
+    pub fn take_edited(&mut self) -> bool {
+        std::mem::take(&mut self.edited)
+    }
+
+    /// Outlines the schematic ports with these names, e.g. those on the hovered 3D net
+    pub fn highlight_ports(&mut self, ports: Option<(&[String], Color32)>) {
+        self.highlighted_ports = ports.map(|(names, color)| (names.to_vec(), color));
+    }
+
     /// Returns true if the sim should be rebuilt
     pub fn show_cfg(
         &mut self,
@@ -65,35 +92,52 @@ impl CircuitEditor {
             ui.label(RichText::new(error).color(Color32::RED));
         }
 
-        ui.add(DragValue::new(&mut cfg.max_nr_iters).prefix("Max NR iters: "));
+        let mut changed = false;
+        changed |= ui
+            .add(DragValue::new(&mut cfg.max_nr_iters).prefix("Max NR iters: "))
+            .changed();
         ui.horizontal(|ui| {
-            ui.add(
-                DragValue::new(&mut cfg.nr_step_size)
-                    .speed(1e-6)
-                    .prefix("Initial NR step size: "),
-            );
-            ui.checkbox(&mut cfg.adaptive_step_size, "Adaptive");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut cfg.nr_step_size)
+                        .speed(1e-6)
+                        .prefix("Initial NR step size: "),
+                )
+                .changed();
+            changed |= ui
+                .checkbox(&mut cfg.adaptive_step_size, "Adaptive")
+                .changed();
         });
 
-        ui.add(
-            DragValue::new(&mut cfg.nr_tolerance)
-                .speed(1e-6)
-                .prefix("NR tolerance: "),
-        );
-        ui.add(
-            DragValue::new(&mut cfg.dx_soln_tolerance)
-                .speed(1e-6)
-                .prefix("Matrix solve tol: "),
-        );
+        changed |= ui
+            .add(
+                DragValue::new(&mut cfg.nr_tolerance)
+                    .speed(1e-6)
+                    .prefix("NR tolerance: "),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                DragValue::new(&mut cfg.dx_soln_tolerance)
+                    .speed(1e-6)
+                    .prefix("Matrix solve tol: "),
+            )
+            .changed();
 
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut cfg.mode, SolverMode::NewtonRaphson, "Newton-Raphson");
-            ui.selectable_value(&mut cfg.mode, SolverMode::Linear, "Linear");
+            changed |= ui
+                .selectable_value(&mut cfg.mode, SolverMode::NewtonRaphson, "Newton-Raphson")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut cfg.mode, SolverMode::Linear, "Linear")
+                .changed();
         });
 
         if ui.button("Default cfg").clicked() {
             *cfg = Default::default();
+            changed = true;
         }
+        self.edited |= changed;
 
         ui.separator();
 
@@ -187,6 +231,7 @@ impl CircuitEditor {
             rebuild_sim = true;
             self.editor.new_port(diagram, pos, "New port".into());
         }
+        self.edited |= rebuild_sim;
         /*
         if ui.button("Delete").clicked() {
             self.editor.delete();
@@ -203,7 +248,9 @@ impl CircuitEditor {
         diagram: &mut Diagram,
         state: &DiagramState,
     ) -> bool {
-        self.editor.edit_component(ui, diagram, state)
+        let changed = self.editor.edit_component(ui, diagram, state);
+        self.edited |= changed;
+        changed
     }
 
     /// Returns true if the sim should be rebuilt
@@ -222,6 +269,25 @@ impl CircuitEditor {
                 rebuild_sim |=
                     self.editor
                         .edit(ui, diagram, &state, self.debug_draw, &self.vis_opt);
+
+                if let Some((names, color)) = &self.highlighted_ports {
+                    let stroke = Stroke::new(3.0, *color);
+                    for (pos, _) in diagram
+                        .ports
+                        .iter()
+                        .filter(|(_, name)| names.contains(name))
+                    {
+                        ui.painter()
+                            .circle_stroke(cellpos_to_egui(*pos), 16.0, stroke);
+                    }
+                }
+                if let Some(selected) = &self.selected_port {
+                    let stroke = Stroke::new(3.0, Color32::YELLOW);
+                    for (pos, _) in diagram.ports.iter().filter(|(_, name)| name == selected) {
+                        ui.painter()
+                            .circle_stroke(cellpos_to_egui(*pos), 12.0, stroke);
+                    }
+                }
             });
 
             if ui.input(|r| r.key_pressed(Key::Delete)) {
@@ -231,9 +297,11 @@ impl CircuitEditor {
 
             if resp.response.clicked() || ui.input(|r| r.key_pressed(Key::Escape)) {
                 self.editor.reset_selection();
+                self.selected_port = None;
             }
         });
 
+        self.edited |= rebuild_sim;
         rebuild_sim
     }
 }
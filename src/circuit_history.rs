@@ -0,0 +1,396 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+use cirmcut::cirmcut_sim::{PrimitiveDiagram, SimOutputs, TwoTerminalComponent};
+use egui::{Color32, DragValue, Ui};
+
+use crate::{
+    node_map::NodeMap,
+    plot::{Series, line_plot},
+    wire_editor_3d::WireId,
+};
+
+/// A circuit quantity recorded each step
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Signal {
+    /// Voltage of the net carrying a port name, which survives edits to the circuit
+    PortVoltage(String),
+    /// Voltage of a node of the primitive diagram
+    NodeVoltage(usize),
+    /// Current through a two-terminal component of the primitive diagram
+    ComponentCurrent(usize),
+    /// Current through a 3D wire segment, along the direction of its key
+    WireCurrent(WireId),
+    /// Current through a lumped component on a 3D edge, along the edge
+    LumpedCurrent(WireId),
+}
+
+impl Signal {
+    /// SPICE-style label naming nets by their ports where possible,
+    /// e.g. "V(out)" or "I(R3 in→n5)"
+    pub fn label(&self, diagram: &PrimitiveDiagram, nodemap: &NodeMap) -> String {
+        match self {
+            Self::PortVoltage(name) => format!("V({name})"),
+            Self::NodeVoltage(idx) => format!("V({})", nodemap.net_name(*idx)),
+            Self::ComponentCurrent(idx) => match diagram.two_terminal.get(*idx) {
+                Some(([a, b], component)) => format!(
+                    "I({}{idx} {}→{})",
+                    spice_prefix(component),
+                    nodemap.net_name(*a),
+                    nodemap.net_name(*b)
+                ),
+                None => format!("I(c{idx})"),
+            },
+            Self::WireCurrent((a, b)) => format!("I(wire {a:?}-{b:?})"),
+            Self::LumpedCurrent((a, b)) => format!("I(lumped {a:?}-{b:?})"),
+        }
+    }
+
+    /// The value in this step's solution, if the signal still exists in the circuit
+    pub fn sample(&self, outputs: &SimOutputs, nodemap: &NodeMap) -> Option<f64> {
+        let current = |idx: usize| outputs.two_terminal_current.get(idx).copied();
+        match self {
+            Self::PortVoltage(name) => {
+                let idx = *nodemap.port_nodes.get(name)?.first()?;
+                outputs.voltages.get(idx).copied()
+            }
+            Self::NodeVoltage(idx) => outputs.voltages.get(*idx).copied(),
+            Self::ComponentCurrent(idx) => current(*idx),
+            Self::WireCurrent(segment) => {
+                let edge = nodemap.segment_edge_map.get(segment)?;
+                current(*nodemap.component_idx_map.get(edge)?)
+            }
+            Self::LumpedCurrent(edge) => {
+                let &(idx, reversed) = nodemap.lumped_idx_map.get(edge)?;
+                current(idx).map(|i| if reversed { -i } else { i })
+            }
+        }
+    }
+}
+
+/// Letter of the SPICE element for a component
+fn spice_prefix(component: &TwoTerminalComponent) -> &'static str {
+    match component {
+        TwoTerminalComponent::Resistor(_) => "R",
+        TwoTerminalComponent::Inductor(..) => "L",
+        TwoTerminalComponent::Capacitor(_) => "C",
+        TwoTerminalComponent::Battery(_) => "V",
+        TwoTerminalComponent::CurrentSource(_) => "I",
+        TwoTerminalComponent::Switch(_) => "S",
+        TwoTerminalComponent::Diode => "D",
+        TwoTerminalComponent::Wire => "W",
+    }
+}
+
+/// Records selected node voltages and component currents over time, for export as CSV
+pub struct CircuitHistory {
+    pub enabled: bool,
+    /// Number of rows kept
+    pub capacity: usize,
+    signals: Vec<Signal>,
+    /// Labels of the signals in the circuit the recorded rows started in
+    labels: Vec<String>,
+    /// Simulated time and the value of each signal, NaN if it was missing
+    rows: VecDeque<(f64, Vec<f64>)>,
+    /// Inputs for adding signals
+    add_port: String,
+    add_node: usize,
+    add_component: usize,
+}
+
+impl Default for CircuitHistory {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 100_000,
+            signals: vec![],
+            labels: vec![],
+            rows: VecDeque::new(),
+            add_port: String::new(),
+            add_node: 0,
+            add_component: 0,
+        }
+    }
+}
+
+impl CircuitHistory {
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    pub fn add_signal(&mut self, signal: Signal) {
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+            self.clear();
+        }
+    }
+
+    pub fn remove_signal(&mut self, signal: &Signal) {
+        self.signals.retain(|s| s != signal);
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    /// Call after each step with the elapsed simulated time
+    pub fn record(
+        &mut self,
+        time: f64,
+        outputs: &SimOutputs,
+        diagram: &PrimitiveDiagram,
+        nodemap: &NodeMap,
+    ) {
+        if !self.enabled || self.signals.is_empty() {
+            return;
+        }
+
+        // Primitive indices shift when the circuit is edited, so label them as they were
+        if self.rows.is_empty() {
+            self.labels = self
+                .signals
+                .iter()
+                .map(|s| s.label(diagram, nodemap))
+                .collect();
+        }
+
+        let values = self
+            .signals
+            .iter()
+            .map(|s| s.sample(outputs, nodemap).unwrap_or(f64::NAN))
+            .collect();
+        self.rows.push_back((time, values));
+        while self.rows.len() > self.capacity.max(1) {
+            self.rows.pop_front();
+        }
+    }
+
+    /// Writes a header of signal labels after a `time` column, then one row per step
+    pub fn write_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        write!(w, "time")?;
+        for label in &self.labels {
+            write!(w, ",\"{}\"", label.replace('"', "\"\""))?;
+        }
+        writeln!(w)?;
+
+        for (time, values) in &self.rows {
+            write!(w, "{time:e}")?;
+            for v in values {
+                write!(w, ",{v:e}")?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the history should be exported
+    pub fn show_ui(
+        &mut self,
+        ui: &mut Ui,
+        diagram: &PrimitiveDiagram,
+        nodemap: &NodeMap,
+        selected_wire: Option<WireId>,
+    ) -> bool {
+        let mut export = false;
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "Record");
+            if ui.button("Clear").clicked() {
+                self.clear();
+            }
+            export = ui
+                .add_enabled(!self.rows.is_empty(), egui::Button::new("Export CSV"))
+                .clicked();
+        });
+        ui.add(
+            DragValue::new(&mut self.capacity)
+                .prefix("History: ")
+                .range(2..=10_000_000),
+        );
+        ui.label(format!("{} rows", self.rows.len()));
+
+        ui.separator();
+        ui.strong("Signals");
+        let mut remove = None;
+        for signal in &self.signals {
+            ui.horizontal(|ui| {
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(signal.clone());
+                }
+                ui.label(signal.label(diagram, nodemap));
+            });
+        }
+        if let Some(signal) = remove {
+            self.remove_signal(&signal);
+        }
+
+        ui.horizontal(|ui| {
+            let mut ports: Vec<&String> = nodemap.port_nodes.keys().collect();
+            ports.sort();
+            egui::ComboBox::from_id_salt("history_port")
+                .selected_text(format!("Port {}", self.add_port))
+                .show_ui(ui, |ui| {
+                    for port in ports {
+                        ui.selectable_value(&mut self.add_port, port.clone(), port);
+                    }
+                });
+            let add = ui.add_enabled(
+                nodemap.port_nodes.contains_key(&self.add_port),
+                egui::Button::new("Add voltage"),
+            );
+            if add.clicked() {
+                self.add_signal(Signal::PortVoltage(self.add_port.clone()));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut self.add_node)
+                    .prefix("Node ")
+                    .range(0..=diagram.num_nodes.saturating_sub(1)),
+            );
+            if ui.button("Add voltage").clicked() {
+                self.add_signal(Signal::NodeVoltage(self.add_node));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut self.add_component)
+                    .prefix("Component ")
+                    .range(0..=diagram.two_terminal.len().saturating_sub(1)),
+            );
+            ui.label(Signal::ComponentCurrent(self.add_component).label(diagram, nodemap));
+            if ui.button("Add current").clicked() {
+                self.add_signal(Signal::ComponentCurrent(self.add_component));
+            }
+        });
+        ui.horizontal(|ui| {
+            let add_selected = ui
+                .add_enabled(
+                    selected_wire.is_some(),
+                    egui::Button::new("Add selected 3D wire"),
+                )
+                .on_hover_text("Select a wire in the 3D editor first")
+                .clicked();
+            if let Some(wire) = selected_wire.filter(|_| add_selected) {
+                if nodemap.lumped_idx_map.contains_key(&wire) {
+                    self.add_signal(Signal::LumpedCurrent(wire));
+                } else {
+                    self.add_signal(Signal::WireCurrent(wire));
+                }
+            }
+            if ui.button("Add all 3D wires").clicked() {
+                let mut segments: Vec<WireId> = nodemap.segment_edge_map.keys().copied().collect();
+                segments.sort();
+                for segment in segments {
+                    self.add_signal(Signal::WireCurrent(segment));
+                }
+            }
+        });
+
+        if !self.rows.is_empty() {
+            ui.separator();
+            let series: Vec<Series<'_>> = self
+                .labels
+                .iter()
+                .enumerate()
+                .map(|(i, name)| Series {
+                    name,
+                    color: palette(i),
+                    values: self.rows.iter().map(|(_, v)| v[i]).collect(),
+                })
+                .collect();
+            line_plot(ui, 150.0, &series, |i| {
+                format!("t = {:.4e} s", self.rows[i].0)
+            });
+        }
+
+        export
+    }
+}
+
+fn palette(i: usize) -> Color32 {
+    const COLORS: [Color32; 6] = [
+        Color32::YELLOW,
+        Color32::LIGHT_BLUE,
+        Color32::LIGHT_GREEN,
+        Color32::LIGHT_RED,
+        Color32::from_rgb(255, 128, 0),
+        Color32::from_rgb(200, 128, 255),
+    ];
+    COLORS[i % COLORS.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csv(history: &CircuitHistory) -> String {
+        let mut bytes = vec![];
+        history.write_csv(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn csv_quotes_labels_after_the_time_column() {
+        let history = CircuitHistory {
+            signals: vec![Signal::PortVoltage("out".into()), Signal::NodeVoltage(2)],
+            labels: vec!["V(out)".into(), "V(\"in\", n2)".into()],
+            ..Default::default()
+        };
+        assert_eq!(csv(&history), "time,\"V(out)\",\"V(\"\"in\"\", n2)\"\n");
+    }
+
+    #[test]
+    fn csv_writes_a_row_per_step_with_missing_values_as_nan() {
+        let history = CircuitHistory {
+            signals: vec![Signal::NodeVoltage(0), Signal::ComponentCurrent(7)],
+            labels: vec!["V(n0)".into(), "I(c7)".into()],
+            rows: [(0.0, vec![1.5, f64::NAN]), (0.25, vec![-2.0, 1e-3])].into(),
+            ..Default::default()
+        };
+        let text = csv(&history);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "time,\"V(n0)\",\"I(c7)\"",
+                "0e0,1.5e0,NaN",
+                "2.5e-1,-2e0,1e-3"
+            ]
+        );
+    }
+
+    #[test]
+    fn recording_keeps_the_most_recent_rows() {
+        let mut history = CircuitHistory {
+            enabled: true,
+            capacity: 2,
+            signals: vec![Signal::NodeVoltage(0)],
+            ..Default::default()
+        };
+        let outputs = SimOutputs {
+            voltages: vec![3.0],
+            ..Default::default()
+        };
+        let diagram = PrimitiveDiagram::default();
+        let nodemap = NodeMap {
+            pos_map: Default::default(),
+            component_idx_map: Default::default(),
+            edge_segment_map: Default::default(),
+            segment_edge_map: Default::default(),
+            lumped_idx_map: Default::default(),
+            schematic_ports: Default::default(),
+            port_nodes: [("out".to_string(), vec![0])].into(),
+        };
+        for step in 0..3 {
+            history.record(step as f64, &outputs, &diagram, &nodemap);
+        }
+
+        assert_eq!(history.labels, ["V(out)"]);
+        let times: Vec<f64> = history.rows.iter().map(|(t, _)| *t).collect();
+        assert_eq!(times, [1.0, 2.0]);
+    }
+}
@@ -0,0 +1,418 @@
+use egui::{Color32, DragValue, Painter, Rect, Stroke, Ui, Vec2};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Colormap {
+    Viridis,
+    Inferno,
+    /// Blue through white to red, for signed quantities
+    Diverging,
+}
+
+impl Colormap {
+    pub const ALL: [Self; 3] = [Self::Viridis, Self::Inferno, Self::Diverging];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Viridis => "Viridis",
+            Self::Inferno => "Inferno",
+            Self::Diverging => "Diverging",
+        }
+    }
+
+    fn stops(&self) -> &'static [u32] {
+        match self {
+            Self::Viridis => &[
+                0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30,
+                0xfde725,
+            ],
+            Self::Inferno => &[
+                0x000004, 0x1f0c48, 0x550f6d, 0x88226a, 0xba3655, 0xe35933, 0xf98e09, 0xf9cb35,
+                0xfcffa4,
+            ],
+            Self::Diverging => &[
+                0x2166ac, 0x4393c3, 0x92c5de, 0xd1e5f0, 0xf7f7f7, 0xfddbc7, 0xf4a582, 0xd6604d,
+                0xb2182b,
+            ],
+        }
+    }
+
+    /// Colour at `t`, from 0 to 1
+    pub fn sample(&self, t: f32) -> Color32 {
+        let stops = self.stops();
+        let rgb = |c: u32| Color32::from_rgb((c >> 16) as u8, (c >> 8) as u8, c as u8);
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (x as usize).min(stops.len() - 2);
+        rgb(stops[i]).lerp_to_gamma(rgb(stops[i + 1]), x - i as f32)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scaling {
+    Linear,
+    Log,
+    /// Linear within `linthresh` of zero and logarithmic beyond, keeping the sign
+    SymLog,
+}
+
+impl Scaling {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::Log, Self::SymLog];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Log => "Log",
+            Self::SymLog => "Symlog",
+        }
+    }
+}
+
+/// Decades shown below the maximum when a log scale is auto-ranged
+const AUTO_LOG_DECADES: f64 = 3.0;
+
+/// Maps values to colours: a colormap, a scaling, and a fixed or automatic range
+#[derive(Clone, Debug)]
+pub struct ColorScale {
+    pub colormap: Colormap,
+    pub scaling: Scaling,
+    /// Fit the range to the data every frame
+    pub auto_range: bool,
+    pub min: f64,
+    pub max: f64,
+    /// Half-width of the linear region of symlog scaling.
+    /// When auto-ranging, this is a fraction of the largest magnitude instead.
+    pub linthresh: f64,
+}
+
+impl ColorScale {
+    pub fn new(colormap: Colormap) -> Self {
+        Self {
+            colormap,
+            scaling: Scaling::Linear,
+            auto_range: true,
+            min: 0.0,
+            max: 1.0,
+            linthresh: 1e-2,
+        }
+    }
+
+    /// Returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut Ui, id_salt: &str) -> bool {
+        let mut changed = false;
+        ui.horizontal_wrapped(|ui| {
+            egui::ComboBox::from_id_salt((id_salt, "colormap"))
+                .selected_text(self.colormap.name())
+                .show_ui(ui, |ui| {
+                    for colormap in Colormap::ALL {
+                        changed |= ui
+                            .selectable_value(&mut self.colormap, colormap, colormap.name())
+                            .changed();
+                    }
+                });
+            for scaling in Scaling::ALL {
+                changed |= ui
+                    .selectable_value(&mut self.scaling, scaling, scaling.name())
+                    .changed();
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            changed |= ui.checkbox(&mut self.auto_range, "Auto range").changed();
+            let fixed = !self.auto_range;
+            changed |= ui
+                .add_enabled(
+                    fixed,
+                    DragValue::new(&mut self.min).prefix("Min: ").speed(1e-3),
+                )
+                .changed();
+            changed |= ui
+                .add_enabled(
+                    fixed,
+                    DragValue::new(&mut self.max).prefix("Max: ").speed(1e-3),
+                )
+                .changed();
+        });
+
+        if self.scaling == Scaling::SymLog {
+            let label = if self.auto_range {
+                "Linear region (fraction of max): "
+            } else {
+                "Linear region: "
+            };
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.linthresh)
+                        .range(1e-12..=f64::MAX)
+                        .prefix(label)
+                        .speed(1e-4),
+                )
+                .changed();
+        }
+
+        changed
+    }
+
+    /// Range to map onto the colormap, given the extremes of the data.
+    /// Automatic ranges are symmetric about zero if the data has negative values.
+    pub fn range(&self, data_min: f64, data_max: f64) -> ScaleRange {
+        let (lo, hi) = if self.auto_range {
+            let extent = data_min.abs().max(data_max.abs());
+            if data_min < 0.0 {
+                (-extent, extent)
+            } else {
+                (0.0, data_max)
+            }
+        } else {
+            (self.min, self.max)
+        };
+
+        let largest = lo.abs().max(hi.abs());
+        let lo = match self.scaling {
+            Scaling::Log if lo <= 0.0 => hi * 10_f64.powf(-AUTO_LOG_DECADES),
+            _ => lo,
+        };
+        let linthresh = if self.auto_range {
+            self.linthresh * largest
+        } else {
+            self.linthresh
+        };
+
+        ScaleRange {
+            lo,
+            hi,
+            linthresh: linthresh.max(f64::MIN_POSITIVE),
+        }
+    }
+
+    fn transform(&self, range: &ScaleRange, x: f64) -> f64 {
+        match self.scaling {
+            Scaling::Linear => x,
+            Scaling::Log => x.max(range.lo.max(f64::MIN_POSITIVE)).ln(),
+            Scaling::SymLog => x.signum() * (1.0 + x.abs() / range.linthresh).ln(),
+        }
+    }
+
+    fn inverse(&self, range: &ScaleRange, y: f64) -> f64 {
+        match self.scaling {
+            Scaling::Linear => y,
+            Scaling::Log => y.exp(),
+            Scaling::SymLog => y.signum() * (y.abs().exp() - 1.0) * range.linthresh,
+        }
+    }
+
+    /// Position of a value along the colormap, from 0 to 1
+    pub fn normalize(&self, range: &ScaleRange, value: f64) -> f32 {
+        let lo = self.transform(range, range.lo);
+        let hi = self.transform(range, range.hi);
+        if hi > lo {
+            ((self.transform(range, value) - lo) / (hi - lo)).clamp(0.0, 1.0) as f32
+        } else {
+            0.5
+        }
+    }
+
+    pub fn color(&self, range: &ScaleRange, value: f64) -> Color32 {
+        self.colormap.sample(self.normalize(range, value))
+    }
+
+    /// Values spaced evenly along the colormap, with their positions from 0 to 1
+    pub fn ticks(&self, range: &ScaleRange, n: usize) -> Vec<(f32, f64)> {
+        let lo = self.transform(range, range.lo);
+        let hi = self.transform(range, range.hi);
+        (0..n)
+            .map(|i| {
+                let t = i as f64 / (n - 1).max(1) as f64;
+                (t as f32, self.inverse(range, lo + (hi - lo) * t))
+            })
+            .collect()
+    }
+}
+
+/// Resolved range of a `ColorScale`
+#[derive(Clone, Copy, Debug)]
+pub struct ScaleRange {
+    pub lo: f64,
+    pub hi: f64,
+    pub linthresh: f64,
+}
+
+/// Extremes of some values, or (0, 0) if there are none
+pub fn data_range(values: impl IntoIterator<Item = f64>) -> (f64, f64) {
+    let (lo, hi) = values
+        .into_iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(x), hi.max(x))
+        });
+    if lo <= hi { (lo, hi) } else { (0.0, 0.0) }
+}
+
+/// Vertical colour bar with numeric ticks to its right and a label above
+pub fn draw_legend(
+    painter: &Painter,
+    rect: Rect,
+    scale: &ColorScale,
+    range: &ScaleRange,
+    label: &str,
+) {
+    let steps = 48;
+    for i in 0..steps {
+        let t = (i as f32 + 0.5) / steps as f32;
+        let y0 = rect.bottom() - rect.height() * i as f32 / steps as f32;
+        let y1 = rect.bottom() - rect.height() * (i + 1) as f32 / steps as f32;
+        painter.rect_filled(
+            Rect::from_x_y_ranges(rect.x_range(), y1..=y0),
+            0.0,
+            scale.colormap.sample(t),
+        );
+    }
+    painter.rect_stroke(
+        rect,
+        0.0,
+        Stroke::new(1.0, Color32::GRAY),
+        egui::StrokeKind::Outside,
+    );
+
+    let font = egui::FontId::monospace(10.0);
+    let text_color = Color32::LIGHT_GRAY;
+    let n_ticks = ((rect.height() / 30.0) as usize).clamp(2, 6);
+    for (t, value) in scale.ticks(range, n_ticks) {
+        let y = rect.bottom() - rect.height() * t;
+        painter.line_segment(
+            [
+                egui::pos2(rect.right(), y),
+                egui::pos2(rect.right() + 3.0, y),
+            ],
+            Stroke::new(1.0, text_color),
+        );
+        painter.text(
+            egui::pos2(rect.right() + 5.0, y),
+            egui::Align2::LEFT_CENTER,
+            format!("{value:.2e}"),
+            font.clone(),
+            text_color,
+        );
+    }
+    painter.text(
+        rect.center_top() - Vec2::new(0.0, 4.0),
+        egui::Align2::CENTER_BOTTOM,
+        label,
+        font,
+        text_color,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(scaling: Scaling, auto_range: bool) -> ColorScale {
+        ColorScale {
+            scaling,
+            auto_range,
+            ..ColorScale::new(Colormap::Viridis)
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!(
+            (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0),
+            "{a} != {b}"
+        );
+    }
+
+    #[test]
+    fn colormap_endpoints_and_clamping() {
+        let map = Colormap::Viridis;
+        assert_eq!(map.sample(0.0), Color32::from_rgb(0x44, 0x01, 0x54));
+        assert_eq!(map.sample(1.0), Color32::from_rgb(0xfd, 0xe7, 0x25));
+        assert_eq!(map.sample(-1.0), map.sample(0.0));
+        assert_eq!(map.sample(2.0), map.sample(1.0));
+    }
+
+    #[test]
+    fn linear_normalize() {
+        let scale = scale(Scaling::Linear, false);
+        let range = ScaleRange {
+            lo: -2.0,
+            hi: 2.0,
+            linthresh: 1.0,
+        };
+        assert_eq!(scale.normalize(&range, -2.0), 0.0);
+        assert_eq!(scale.normalize(&range, 0.0), 0.5);
+        assert_eq!(scale.normalize(&range, 1.0), 0.75);
+        assert_eq!(scale.normalize(&range, 10.0), 1.0);
+    }
+
+    #[test]
+    fn log_normalize_is_even_per_decade() {
+        let scale = scale(Scaling::Log, false);
+        let range = ScaleRange {
+            lo: 1e-3,
+            hi: 1.0,
+            linthresh: 1.0,
+        };
+        assert!((scale.normalize(&range, 1e-2) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((scale.normalize(&range, 1e-1) - 2.0 / 3.0).abs() < 1e-6);
+        // Zero and negative values go to the bottom of the scale
+        assert_eq!(scale.normalize(&range, 0.0), 0.0);
+        assert_eq!(scale.normalize(&range, -1.0), 0.0);
+    }
+
+    #[test]
+    fn symlog_is_odd_and_linear_near_zero() {
+        let scale = scale(Scaling::SymLog, false);
+        let range = ScaleRange {
+            lo: -100.0,
+            hi: 100.0,
+            linthresh: 1.0,
+        };
+        assert_eq!(scale.normalize(&range, 0.0), 0.5);
+        for x in [0.01, 0.5, 3.0, 50.0] {
+            let (pos, neg) = (scale.normalize(&range, x), scale.normalize(&range, -x));
+            assert!((pos - 0.5 + (neg - 0.5)).abs() < 1e-6);
+        }
+        // Well inside the linear region, doubling the value doubles the offset
+        let small = scale.normalize(&range, 1e-3) - 0.5;
+        let double = scale.normalize(&range, 2e-3) - 0.5;
+        assert!((double / small - 2.0).abs() < 5e-3);
+    }
+
+    #[test]
+    fn ticks_invert_the_transform() {
+        for scaling in Scaling::ALL {
+            let scale = scale(scaling, true);
+            let range = scale.range(-5.0, 20.0);
+            for (t, value) in scale.ticks(&range, 5) {
+                assert!(
+                    (scale.normalize(&range, value) - t).abs() < 1e-5,
+                    "{scaling:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn auto_range() {
+        // Symmetric about zero for signed data
+        let range = scale(Scaling::Linear, true).range(-1.0, 4.0);
+        assert_close(range.lo, -4.0);
+        assert_close(range.hi, 4.0);
+
+        // From zero for positive data
+        let range = scale(Scaling::Linear, true).range(2.0, 4.0);
+        assert_close(range.lo, 0.0);
+
+        // A few decades below the maximum on a log scale
+        let range = scale(Scaling::Log, true).range(0.0, 10.0);
+        assert_close(range.lo, 10.0 * 10_f64.powf(-AUTO_LOG_DECADES));
+
+        // linthresh is a fraction of the largest magnitude
+        let range = scale(Scaling::SymLog, true).range(-50.0, 10.0);
+        assert_close(range.linthresh, 0.5);
+    }
+
+    #[test]
+    fn data_range_of_nothing_is_zero() {
+        assert_eq!(data_range([]), (0.0, 0.0));
+        assert_eq!(data_range([3.0, -1.0, 2.0]), (-1.0, 3.0));
+    }
+}
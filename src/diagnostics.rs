@@ -0,0 +1,596 @@
+use std::collections::{HashSet, VecDeque};
+
+use cirmcut::cirmcut_sim::{PrimitiveDiagram, SimOutputs};
+use egui::{Color32, DragValue, RichText, Ui};
+use ndarray::Array4;
+
+use crate::{
+    common::IntPos3,
+    field_quantity::{divergence_fields, energy_density_field, poynting_field},
+    node_map::NodeMap,
+    plot::{Series, line_plot},
+    sim::{FdtdSim, FdtdSimConfig},
+    wire_editor_3d::Wiring3D,
+};
+
+/// Energy and power terms of the coupled simulation after one step
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EnergySample {
+    /// FDTD step this sample was taken after
+    pub step: usize,
+    /// Simulated time after the step (s), on the clock of the controls
+    pub time: f64,
+    /// Total electromagnetic energy in the grid (J)
+    pub field_energy: f64,
+    /// Rate of change of the field energy over the last step (W)
+    pub field_power: f64,
+    /// Power leaving through the six faces of the domain (W)
+    pub boundary_flux: f64,
+    /// Power taken out of the field by the imposed currents, about ε μ Σ J·E dx³ (W)
+    pub source_power: f64,
+    /// Power removed by clearing E on the 3D wire edges before the step (W)
+    pub wire_zeroing: f64,
+    /// Power delivered to the circuit by the EMFs from `readback_efield` (W)
+    pub exchange_power: f64,
+    /// Power dissipated in the resistance of the 3D wires (W)
+    pub wire_dissipation: f64,
+    /// Power absorbed by the remaining circuit components (W)
+    pub component_power: f64,
+    /// Residual energy accumulated since recording started (J)
+    pub drift: f64,
+    /// Energy moved by the flux, the sources and the wire edges since recording started (J)
+    pub exchanged: f64,
+}
+
+impl EnergySample {
+    /// Field energy change unaccounted for by the flux, the sources and the wire edges (W)
+    ///
+    /// The field energy assumes a uniform permittivity, so the cells whose
+    /// coefficients the thin-wire correction scales are not weighted for it;
+    /// wires with a radius far from the one-cell default leave a residual.
+    pub fn residual(&self) -> f64 {
+        self.field_power + self.boundary_flux + self.source_power + self.wire_zeroing
+    }
+
+    /// Energy moved by the terms of the balance over a step of `dt` (J)
+    fn exchanged_over(&self, dt: f64) -> f64 {
+        (self.boundary_flux.abs() + self.source_power.abs() + self.wire_zeroing.abs()) * dt
+    }
+}
+
+/// Inputs of one coupled step needed to compute an `EnergySample`
+pub struct StepQuantities<'a> {
+    pub sim: &'a FdtdSim,
+    pub cfg: &'a FdtdSimConfig,
+    /// Simulated time after the step (s)
+    pub time: f64,
+    /// Power taken out of the field by the imposed currents, from [`source_power`]
+    pub source_power: f64,
+    /// Field energy removed by clearing E on the wire edges before the step (J)
+    pub zeroed_energy: f64,
+    /// EMF per solution vector entry, as returned by `readback_efield`
+    pub external_params: &'a [f64],
+    /// Solution vector entry of each two-terminal component
+    pub component_params: &'a [usize],
+    pub nodemap: &'a NodeMap,
+    pub wiring: &'a Wiring3D,
+    pub diagram: &'a PrimitiveDiagram,
+    pub outputs: &'a SimOutputs,
+}
+
+/// History of the energy balance, with drift detection
+pub struct EnergyDiagnostics {
+    pub enabled: bool,
+    /// Number of samples kept
+    pub capacity: usize,
+    /// Accumulated residual, relative to the field energy or to the energy exchanged
+    /// if that is larger, above which the balance is flagged
+    pub tolerance: f64,
+    samples: VecDeque<EnergySample>,
+    prev_energy: Option<f64>,
+    /// First step whose accumulated residual exceeded the tolerance
+    flagged: Option<usize>,
+}
+
+impl Default for EnergyDiagnostics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 1000,
+            tolerance: 0.05,
+            samples: VecDeque::new(),
+            prev_energy: None,
+            flagged: None,
+        }
+    }
+}
+
+impl EnergyDiagnostics {
+    pub fn samples(&self) -> &VecDeque<EnergySample> {
+        &self.samples
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.prev_energy = None;
+        self.flagged = None;
+    }
+
+    pub fn record(&mut self, q: &StepQuantities<'_>) {
+        if !self.enabled {
+            return;
+        }
+
+        self.push(compute_sample(q), q.cfg.dt);
+    }
+
+    /// Adds a sample, filling in its rate of change of the field energy
+    fn push(&mut self, mut sample: EnergySample, dt: f64) {
+        // The first sample has no energy change to balance
+        if let Some(prev) = self.prev_energy.replace(sample.field_energy) {
+            sample.field_power = (sample.field_energy - prev) / dt;
+
+            let last = self.samples.back();
+            sample.drift = last.map_or(0.0, |s| s.drift) + sample.residual() * dt;
+            sample.exchanged = last.map_or(0.0, |s| s.exchanged) + sample.exchanged_over(dt);
+
+            if self.flagged.is_none() && self.exceeds_tolerance(&sample) {
+                self.flagged = Some(sample.step);
+            }
+        }
+
+        self.samples.push_back(sample);
+        while self.samples.len() > self.capacity.max(1) {
+            self.samples.pop_front();
+        }
+    }
+
+    fn exceeds_tolerance(&self, sample: &EnergySample) -> bool {
+        // Per step, the residual of a free wave swings with the interplay of E and H,
+        // but it sums to little; a missing term or an instability accumulates
+        let scale = sample.field_energy.max(sample.exchanged).max(1e-30);
+        !sample.drift.is_finite() || sample.drift.abs() > self.tolerance * scale
+    }
+
+    pub fn show_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "Record");
+            if ui.button("Clear").clicked() {
+                self.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut self.tolerance)
+                    .prefix("Tolerance: ")
+                    .range(0.0..=10.0)
+                    .speed(1e-3),
+            );
+            ui.add(
+                DragValue::new(&mut self.capacity)
+                    .prefix("History: ")
+                    .range(2..=100_000),
+            );
+        });
+
+        if let Some(step) = self.flagged {
+            ui.label(
+                RichText::new(format!(
+                    "Energy balance drifted beyond tolerance at step {step}"
+                ))
+                .color(Color32::RED),
+            )
+            .on_hover_text(
+                "Residual = dW/dt + boundary flux + source power + wire zeroing, \
+                accumulated over the recording. See TODO.md on the large-amplitude instability.",
+            );
+        }
+
+        let Some(last) = self.samples.back().copied() else {
+            ui.label("No samples recorded");
+            return;
+        };
+
+        egui::Grid::new("energy_terms")
+            .striped(true)
+            .show(ui, |ui| {
+                let mut row = |name: &str, value: f64, unit: &str| {
+                    ui.label(name);
+                    ui.monospace(format!("{value:+.4e} {unit}"));
+                    ui.end_row();
+                };
+                row("Field energy", last.field_energy, "J");
+                row("dW/dt", last.field_power, "W");
+                row("Boundary flux", last.boundary_flux, "W");
+                row("Source power (εμ J·E)", last.source_power, "W");
+                row("Wire zeroing", last.wire_zeroing, "W");
+                row("Residual", last.residual(), "W");
+                row("Accumulated residual", last.drift, "J");
+                row("Field → circuit", last.exchange_power, "W");
+                row("Wire dissipation", last.wire_dissipation, "W");
+                row("Component power", last.component_power, "W");
+            });
+
+        let series = |name, color, f: fn(&EnergySample) -> f64| Series {
+            name,
+            color,
+            values: self.samples.iter().map(f).collect(),
+        };
+
+        let steps: Vec<usize> = self.samples.iter().map(|s| s.step).collect();
+        let label = |i: usize| format!("step {}", steps[i]);
+
+        ui.separator();
+        ui.strong("Field energy");
+        line_plot(
+            ui,
+            80.0,
+            &[series("W", Color32::LIGHT_BLUE, |s| s.field_energy)],
+            label,
+        );
+
+        ui.strong("Power balance");
+        line_plot(
+            ui,
+            120.0,
+            &[
+                series("dW/dt", Color32::LIGHT_BLUE, |s| s.field_power),
+                series("flux", Color32::LIGHT_GREEN, |s| s.boundary_flux),
+                series("source", Color32::YELLOW, |s| s.source_power),
+                series("zeroing", Color32::from_rgb(200, 128, 255), |s| {
+                    s.wire_zeroing
+                }),
+                series("residual", Color32::RED, |s| s.residual()),
+            ],
+            label,
+        );
+
+        ui.strong("Circuit");
+        line_plot(
+            ui,
+            100.0,
+            &[
+                series("exchange", Color32::YELLOW, |s| s.exchange_power),
+                series("wires", Color32::from_rgb(255, 128, 0), |s| {
+                    s.wire_dissipation
+                }),
+                series("components", Color32::LIGHT_GREEN, |s| s.component_power),
+            ],
+            label,
+        );
+    }
+}
+
+/// Energy taken out of the field by the current term of the E update, `E -= dt μ J`,
+/// per unit time: (½ ε |E|² - ½ ε |E - dt μ J|²) dx³ / dt, summed over the grid.
+/// Must be called with the fields before `FdtdSim::step`.
+pub fn source_power(sim: &FdtdSim, cfg: &FdtdSimConfig, current: &Array4<f64>) -> f64 {
+    let e = sim.e_field();
+    let per_edge = cfg.mu * current * e - 0.5 * cfg.dt * cfg.mu.powi(2) * (current * current);
+    cfg.eps * per_edge.sum() * cfg.dx.powi(3)
+}
+
+/// The field terms of the balance after a step, with the circuit terms left at zero
+fn field_sample(
+    sim: &FdtdSim,
+    cfg: &FdtdSimConfig,
+    time: f64,
+    source_power: f64,
+    zeroed_energy: f64,
+) -> EnergySample {
+    let width = sim.width();
+    let cell_volume = cfg.dx.powi(3);
+    let face_area = cfg.dx.powi(2);
+
+    let field_energy = energy_density_field(sim, cfg).sum() * cell_volume;
+
+    // Outward flux through each face of the domain
+    let poynting = poynting_field(sim, cfg);
+    let mut boundary_flux = 0.0;
+    for i in 0..width {
+        for j in 0..width {
+            for axis in 0..3 {
+                let at = |layer: usize| {
+                    let mut pos = [0; 3];
+                    pos[axis] = layer;
+                    pos[(axis + 1) % 3] = i;
+                    pos[(axis + 2) % 3] = j;
+                    poynting[(pos[0], pos[1], pos[2], axis)]
+                };
+                boundary_flux += (at(width - 1) - at(0)) * face_area;
+            }
+        }
+    }
+
+    EnergySample {
+        step: sim.steps(),
+        time,
+        field_energy,
+        boundary_flux,
+        source_power,
+        wire_zeroing: zeroed_energy / cfg.dt,
+        ..Default::default()
+    }
+}
+
+fn compute_sample(q: &StepQuantities<'_>) -> EnergySample {
+    let sample = field_sample(q.sim, q.cfg, q.time, q.source_power, q.zeroed_energy);
+
+    let current = |idx: usize| {
+        q.outputs
+            .two_terminal_current
+            .get(idx)
+            .copied()
+            .unwrap_or(0.0)
+    };
+
+    let mut exchange_power = 0.0;
+    let mut wire_dissipation = 0.0;
+    for (edge, segment) in &q.nodemap.edge_segment_map {
+        let Some(&idx) = q.nodemap.component_idx_map.get(edge) else {
+            continue;
+        };
+        let i = current(idx);
+        let emf = q
+            .component_params
+            .get(idx)
+            .and_then(|&param| q.external_params.get(param))
+            .copied()
+            .unwrap_or(0.0);
+        exchange_power += emf * i;
+        wire_dissipation += i * i * q.wiring.wires[segment].resistance;
+    }
+
+    let wire_components: HashSet<usize> = q.nodemap.component_idx_map.values().copied().collect();
+    let voltage = |node: usize| q.outputs.voltages.get(node).copied().unwrap_or(0.0);
+    let component_power = q
+        .diagram
+        .two_terminal
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !wire_components.contains(idx))
+        .map(|(idx, ([a, b], _))| (voltage(*a) - voltage(*b)) * current(idx))
+        .sum();
+
+    EnergySample {
+        exchange_power,
+        wire_dissipation,
+        component_power,
+        ..sample
+    }
+}
+
+/// Gauss's law violation after one step
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DivergenceSample {
+    pub step: usize,
+    /// Simulated time after the step (s)
+    pub time: f64,
+    /// max |∇·E|
+    pub max_div_e: f64,
+    /// Where `max_div_e` occurs
+    pub max_div_e_pos: IntPos3,
+    /// max |∇·B|
+    pub max_div_b: f64,
+    /// Where `max_div_b` occurs
+    pub max_div_b_pos: IntPos3,
+    /// Net charge ε Σ ∇·E dx³ in the interior (C)
+    pub total_charge: f64,
+}
+
+/// History of the divergence max-norms, for spotting charge conservation errors
+pub struct DivergenceMonitor {
+    pub enabled: bool,
+    /// Number of samples kept
+    pub capacity: usize,
+    samples: VecDeque<DivergenceSample>,
+}
+
+impl Default for DivergenceMonitor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 1000,
+            samples: VecDeque::new(),
+        }
+    }
+}
+
+impl DivergenceMonitor {
+    pub fn samples(&self) -> &VecDeque<DivergenceSample> {
+        &self.samples
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Call after each step with the elapsed simulated time
+    pub fn record(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, time: f64) {
+        if !self.enabled {
+            return;
+        }
+
+        let (div_e, div_b) = divergence_fields(sim, cfg);
+        let (max_div_e, max_div_e_pos) = max_norm(div_e.indexed_iter().map(|(p, &v)| (p, v)));
+        let (max_div_b, max_div_b_pos) = max_norm(div_b.indexed_iter().map(|(p, &v)| (p, v)));
+
+        self.samples.push_back(DivergenceSample {
+            step: sim.steps(),
+            time,
+            max_div_e,
+            max_div_e_pos,
+            max_div_b,
+            max_div_b_pos,
+            total_charge: cfg.eps * div_e.sum() * cfg.dx.powi(3),
+        });
+        while self.samples.len() > self.capacity.max(1) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn show_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "Record");
+            if ui.button("Clear").clicked() {
+                self.clear();
+            }
+            ui.add(
+                DragValue::new(&mut self.capacity)
+                    .prefix("History: ")
+                    .range(2..=100_000),
+            );
+        });
+
+        let Some(last) = self.samples.back().copied() else {
+            ui.label("No samples recorded");
+            return;
+        };
+
+        egui::Grid::new("divergence_terms")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("max |∇·E|");
+                ui.monospace(format!("{:.4e}", last.max_div_e));
+                ui.label(format!("at {:?}", last.max_div_e_pos));
+                ui.end_row();
+
+                ui.label("max |∇·B|");
+                ui.monospace(format!("{:.4e}", last.max_div_b));
+                ui.label(format!("at {:?}", last.max_div_b_pos));
+                ui.end_row();
+
+                ui.label("Net charge");
+                ui.monospace(format!("{:+.4e} C", last.total_charge));
+                ui.end_row();
+            });
+
+        let steps: Vec<usize> = self.samples.iter().map(|s| s.step).collect();
+        let series = |name, color, f: fn(&DivergenceSample) -> f64| Series {
+            name,
+            color,
+            values: self.samples.iter().map(f).collect(),
+        };
+
+        line_plot(
+            ui,
+            100.0,
+            &[
+                series("max |∇·E|", Color32::YELLOW, |s| s.max_div_e),
+                series("max |∇·B|", Color32::LIGHT_BLUE, |s| s.max_div_b),
+            ],
+            |i| format!("step {}", steps[i]),
+        );
+        line_plot(
+            ui,
+            80.0,
+            &[series("net charge", Color32::LIGHT_GREEN, |s| {
+                s.total_charge
+            })],
+            |i| format!("step {}", steps[i]),
+        );
+    }
+}
+
+/// Largest absolute value and where it occurs
+fn max_norm(values: impl Iterator<Item = (IntPos3, f64)>) -> (f64, IntPos3) {
+    values
+        .map(|(pos, v)| (v.abs(), pos))
+        .fold((0.0, (0, 0, 0)), |best, cur| {
+            // Report non-finite values rather than hiding them
+            if cur.0 > best.0 || (cur.0.is_nan() && !best.0.is_nan()) {
+                cur
+            } else {
+                best
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Gaussian pulse of Ez in the middle of a closed box
+    fn pulse(width: usize) -> FdtdSim {
+        let mut sim = FdtdSim::new(width);
+        let mid = (width - 1) as f64 / 2.0;
+        for ((i, j, k, c), e) in sim.e_field.indexed_iter_mut() {
+            let r2: f64 = [i, j, k].iter().map(|&x| (x as f64 - mid).powi(2)).sum();
+            if c == 2 {
+                *e = (-r2 / 4.0).exp();
+            }
+        }
+        sim
+    }
+
+    fn recording() -> EnergyDiagnostics {
+        EnergyDiagnostics {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn free_wave_in_a_closed_box_stays_balanced() {
+        // A large time step, so the pulse spreads over the run
+        let cfg = FdtdSimConfig {
+            dt: 1.0,
+            ..Default::default()
+        };
+        let width = 10;
+        let mut sim = pulse(width);
+        let zero = Array4::zeros((width, width, width, 3));
+
+        let mut diagnostics = recording();
+        diagnostics.push(field_sample(&sim, &cfg, 0.0, 0.0, 0.0), cfg.dt);
+        for _ in 0..100 {
+            sim.step(&cfg, &zero, &zero);
+            diagnostics.push(field_sample(&sim, &cfg, 0.0, 0.0, 0.0), cfg.dt);
+        }
+
+        let last = diagnostics.samples().back().unwrap();
+        assert_eq!(diagnostics.flagged, None);
+        assert!(last.drift.abs() < diagnostics.tolerance * last.field_energy);
+    }
+
+    #[test]
+    fn imposed_current_matches_the_energy_drop() {
+        let cfg = FdtdSimConfig::default();
+        let width = 8;
+        let mut sim = FdtdSim::new(width);
+        let edge = (4, 4, 4, 2);
+        sim.e_field[edge] = 1.0;
+        let mut current = Array4::zeros((width, width, width, 3));
+        current[edge] = 3.0;
+        let zero = Array4::zeros((width, width, width, 3));
+
+        let before = field_sample(&sim, &cfg, 0.0, 0.0, 0.0).field_energy;
+        let source = source_power(&sim, &cfg, &current);
+        sim.step(&cfg, &zero, &current);
+        let after = field_sample(&sim, &cfg, 0.0, 0.0, 0.0).field_energy;
+
+        assert!(source > 0.0);
+        let drop = (before - after) / cfg.dt;
+        assert!((drop - source).abs() < 1e-3 * source, "{drop} vs {source}");
+    }
+
+    #[test]
+    fn unaccounted_source_is_flagged() {
+        let cfg = FdtdSimConfig::default();
+        let width = 8;
+        let mut sim = FdtdSim::new(width);
+        let mut current = Array4::zeros((width, width, width, 3));
+        current[(4, 4, 4, 2)] = 1.0;
+        let zero = Array4::zeros((width, width, width, 3));
+
+        let (mut balanced, mut missing) = (recording(), recording());
+        for _ in 0..20 {
+            let source = source_power(&sim, &cfg, &current);
+            sim.step(&cfg, &zero, &current);
+            balanced.push(field_sample(&sim, &cfg, 0.0, source, 0.0), cfg.dt);
+            missing.push(field_sample(&sim, &cfg, 0.0, 0.0, 0.0), cfg.dt);
+        }
+        assert_eq!(balanced.flagged, None);
+        assert!(missing.flagged.is_some());
+    }
+}
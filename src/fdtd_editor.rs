@@ -1,12 +1,15 @@
 use cirmcut::{circuit_widget::VisualizationOptions, cirmcut_sim::SimOutputs};
-use egui::{DragValue, Ui};
+use egui::{Color32, DragValue, Ui};
 
 use crate::{
+    common::IntPos3,
     field_vis::GridVisualizationConfig,
     node_map::NodeMap,
+    particles::Particles,
     sim::{FdtdSim, FdtdSimConfig},
-    streamers::{Streamers, StreamersMode},
-    wire_editor_3d::{WireEditor3D, Wiring3D},
+    slice_vis::SliceVisualization,
+    streamers::Streamers,
+    wire_editor_3d::{WireEditor3D, WireId, Wiring3D},
 };
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -14,9 +17,8 @@ use crate::{
 //#[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct FdtdEditor {
     grid_vis: GridVisualizationConfig,
+    slice: SliceVisualization,
     streamers: Streamers,
-    streamer_step: f32,
-    enable_streamers: StreamersMode,
     wire_editor_3d: WireEditor3D,
 }
 
@@ -25,33 +27,61 @@ impl FdtdEditor {
         Self {
             wire_editor_3d: WireEditor3D::default(),
 
-            streamers: Streamers::new(width, 5000),
-            enable_streamers: StreamersMode::HField,
-            streamer_step: 0.01,
+            streamers: Streamers::default(),
 
             grid_vis: GridVisualizationConfig::default(),
+            slice: SliceVisualization::new(width),
         }
     }
 }
 
 impl FdtdEditor {
+    /// Description of the last wiring edit, for the document history
+    pub fn take_last_action(&mut self) -> Option<String> {
+        self.wire_editor_3d.take_last_action()
+    }
+
+    /// Must be called when the wiring is replaced or edited outside the 3D editor
+    pub fn wiring_changed(&mut self) {
+        self.wire_editor_3d.wiring_changed();
+    }
+
+    /// Changes whenever the wiring is edited
+    pub fn wiring_revision(&self) -> u64 {
+        self.wire_editor_3d.wiring_revision()
+    }
+
+    pub fn select_position(&mut self, pos: IntPos3) {
+        self.wire_editor_3d.select_position(pos);
+    }
+
+    pub fn selected_wire(&self) -> Option<WireId> {
+        self.wire_editor_3d.selected_wire()
+    }
+
+    /// Port names on the net under the cursor in the 3D view and the net's colour
+    pub fn hovered_ports(&self) -> Option<(&[String], Color32)> {
+        self.wire_editor_3d.hovered_ports()
+    }
+
     /// Returns true if the change would require an external update
     pub fn show_edit_wire(
         &mut self,
         ui: &mut Ui,
         sim: &FdtdSim,
         wires: &mut Wiring3D,
+        nodemap: &NodeMap,
     ) -> bool {
-        self.wire_editor_3d.show_ui(ui, sim.width(), wires)
+        self.wire_editor_3d.show_ui(ui, sim.width(), wires, nodemap)
+    }
+
+    pub fn show_slice(&mut self, ui: &mut Ui, sim: &FdtdSim, cfg: &FdtdSimConfig) {
+        self.slice.show_pane(ui, sim, cfg);
     }
 
 
     /// Returns true if the change would require an external update
-    pub fn show_cfg(
-        &mut self,
-        ui: &mut Ui,
-        cfg: &mut FdtdSimConfig,
-    ) -> bool {
+    pub fn show_cfg(&mut self, ui: &mut Ui, cfg: &mut FdtdSimConfig, width: usize) -> bool {
         let rebuild = false;
 
         ui.strong("Background grid");
@@ -62,16 +92,8 @@ impl FdtdEditor {
             self.grid_vis.show_ui(ui);
         });
 
-        ui.collapsing("Streamers (visualization)", |ui| {
-            //ui.checkbox(&mut self.enable_streamers, "Streamers");
-            ui.selectable_value(&mut self.enable_streamers, StreamersMode::Off, "Off");
-            ui.selectable_value(&mut self.enable_streamers, StreamersMode::HField, "H vects");
-            ui.selectable_value(&mut self.enable_streamers, StreamersMode::EField, "E vects");
-            ui.add(
-                DragValue::new(&mut self.streamer_step)
-                    .prefix("dt: ")
-                    .speed(1e-3),
-            );
+        ui.collapsing("Streamlines (visualization)", |ui| {
+            self.streamers.show_ui(ui, width);
         });
 
         ui.separator();
@@ -85,14 +107,17 @@ impl FdtdEditor {
     }
 
     /// Returns true if the simulation should be rebuilt
+    #[allow(clippy::too_many_arguments)]
     pub fn show_editor(
         &mut self,
         ui: &mut Ui,
         sim: &FdtdSim,
+        cfg: &FdtdSimConfig,
         wires: &mut Wiring3D,
         nodemap: &NodeMap,
         soln: &SimOutputs,
         vis: &VisualizationOptions,
+        particles: &Particles,
     ) -> bool {
         let mut rebuild_sim = false;
 
@@ -102,20 +127,15 @@ impl FdtdEditor {
                 .show(ui, |thr| {
                     let paint = thr.painter();
 
-                    // TODO: Make this configurable
-                    self.streamers.step(
-                        sim,
-                        paint,
-                        self.streamer_step,
-                        0.001,
-                        0.2,
-                        self.enable_streamers,
-                    );
+                    let selected_wire = self.wire_editor_3d.selected_wire();
+                    self.streamers.draw(sim, cfg, paint, selected_wire);
 
-                    self.grid_vis.draw(&sim, paint);
+                    self.slice.draw(sim, cfg, paint);
+                    self.grid_vis.draw(sim, cfg, paint);
+                    particles.draw(paint, sim.width());
 
                     self.wire_editor_3d
-                        .draw_current(thr, wires, nodemap, soln, sim.width(), vis);
+                        .draw_current(thr, nodemap, soln, sim.width(), vis);
                     rebuild_sim |= self.wire_editor_3d.edit(sim.width(), thr, wires);
                 });
         });
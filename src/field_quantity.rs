@@ -0,0 +1,272 @@
+use ndarray::{Array3, Array4};
+
+use crate::{
+    common::IntPos3,
+    sim::{FdtdSim, FdtdSimConfig},
+};
+
+/// A scalar derived from the fields at each grid point, for visualization
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FieldQuantity {
+    Ex,
+    Ey,
+    Ez,
+    EMagnitude,
+    Hx,
+    Hy,
+    Hz,
+    HMagnitude,
+    /// (eps |E|² + mu |H|²) / 2. The update is symmetric in E and H, so H is stored
+    /// scaled by sqrt(mu / eps) and both terms carry a factor of eps.
+    EnergyDensity,
+    Sx,
+    Sy,
+    Sz,
+    /// |S| = |E × H|, the power flow
+    SMagnitude,
+    /// ∇·E, nonzero wherever the coupling has deposited charge
+    DivE,
+    /// ε ∇·E
+    ChargeDensity,
+    /// ∇·B, which should stay zero
+    DivB,
+}
+
+impl FieldQuantity {
+    pub const ALL: [Self; 16] = [
+        Self::Ex,
+        Self::Ey,
+        Self::Ez,
+        Self::EMagnitude,
+        Self::Hx,
+        Self::Hy,
+        Self::Hz,
+        Self::HMagnitude,
+        Self::EnergyDensity,
+        Self::Sx,
+        Self::Sy,
+        Self::Sz,
+        Self::SMagnitude,
+        Self::DivE,
+        Self::ChargeDensity,
+        Self::DivB,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ex => "Ex",
+            Self::Ey => "Ey",
+            Self::Ez => "Ez",
+            Self::EMagnitude => "|E|",
+            Self::Hx => "Hx",
+            Self::Hy => "Hy",
+            Self::Hz => "Hz",
+            Self::HMagnitude => "|H|",
+            Self::EnergyDensity => "Energy density",
+            Self::Sx => "Sx",
+            Self::Sy => "Sy",
+            Self::Sz => "Sz",
+            Self::SMagnitude => "|S|",
+            Self::DivE => "∇·E",
+            Self::ChargeDensity => "Charge density",
+            Self::DivB => "∇·B",
+        }
+    }
+
+    /// Whether the quantity can be negative, in which case ranges are centered on zero
+    pub fn is_signed(&self) -> bool {
+        !matches!(
+            self,
+            Self::EMagnitude | Self::HMagnitude | Self::EnergyDensity | Self::SMagnitude
+        )
+    }
+
+    pub fn sample(&self, sim: &FdtdSim, cfg: &FdtdSimConfig, (i, j, k): IntPos3) -> f64 {
+        let e = |c| sim.e_field()[(i, j, k, c)];
+        let h = |c| sim.h_field()[(i, j, k, c)];
+        match self {
+            Self::Ex => e(0),
+            Self::Ey => e(1),
+            Self::Ez => e(2),
+            Self::EMagnitude => (e(0).powi(2) + e(1).powi(2) + e(2).powi(2)).sqrt(),
+            Self::Hx => h(0),
+            Self::Hy => h(1),
+            Self::Hz => h(2),
+            Self::HMagnitude => (h(0).powi(2) + h(1).powi(2) + h(2).powi(2)).sqrt(),
+            Self::EnergyDensity => {
+                let e2 = e(0).powi(2) + e(1).powi(2) + e(2).powi(2);
+                let h2 = h(0).powi(2) + h(1).powi(2) + h(2).powi(2);
+                0.5 * cfg.eps * (e2 + h2)
+            }
+            Self::Sx | Self::Sy | Self::Sz | Self::SMagnitude => {
+                let s = poynting_at(sim, cfg, (i, j, k));
+                match self {
+                    Self::Sx => s[0],
+                    Self::Sy => s[1],
+                    Self::Sz => s[2],
+                    _ => (s[0].powi(2) + s[1].powi(2) + s[2].powi(2)).sqrt(),
+                }
+            }
+            Self::DivE => divergence_at(sim.e_field(), cfg.dx, (i, j, k)),
+            Self::ChargeDensity => cfg.eps * divergence_at(sim.e_field(), cfg.dx, (i, j, k)),
+            Self::DivB => b_scale(cfg) * divergence_at(sim.h_field(), cfg.dx, (i, j, k)),
+        }
+    }
+}
+
+/// S = E × H at a grid point. H is stored scaled by sqrt(mu / eps), which is undone here.
+fn poynting_at(sim: &FdtdSim, cfg: &FdtdSimConfig, (i, j, k): IntPos3) -> [f64; 3] {
+    let e = |c| sim.e_field()[(i, j, k, c)];
+    let h_scale = (cfg.eps / cfg.mu).sqrt();
+    let h = |c| sim.h_field()[(i, j, k, c)] * h_scale;
+    [
+        e(1) * h(2) - e(2) * h(1),
+        e(2) * h(0) - e(0) * h(2),
+        e(0) * h(1) - e(1) * h(0),
+    ]
+}
+
+/// B = mu H_true = sqrt(mu eps) H_stored
+fn b_scale(cfg: &FdtdSimConfig) -> f64 {
+    (cfg.mu * cfg.eps).sqrt()
+}
+
+/// Central difference divergence, matching the stencil of the curl in the update.
+/// Zero on the boundary.
+///
+/// The divergence of a curl vanishes only away from the boundary and where the
+/// update's coefficients are 1. The curl is left at zero on the boundary layer,
+/// so points next to it see nonzero values, as do the cells around thin wires,
+/// whose `e_coeff`/`h_coeff` scale the curl per component.
+pub fn divergence_at(field: &Array4<f64>, dx: f64, (i, j, k): IntPos3) -> f64 {
+    let (wx, wy, wz, _) = field.dim();
+    if i == 0 || j == 0 || k == 0 || i + 1 >= wx || j + 1 >= wy || k + 1 >= wz {
+        return 0.0;
+    }
+
+    let d = (field[(i + 1, j, k, 0)] - field[(i - 1, j, k, 0)])
+        + (field[(i, j + 1, k, 1)] - field[(i, j - 1, k, 1)])
+        + (field[(i, j, k + 1, 2)] - field[(i, j, k - 1, 2)]);
+    d / (2.0 * dx)
+}
+
+/// ∇·E and ∇·B at every grid point
+pub fn divergence_fields(sim: &FdtdSim, cfg: &FdtdSimConfig) -> (Array3<f64>, Array3<f64>) {
+    let width = sim.width();
+    let div_e = Array3::from_shape_fn((width, width, width), |pos| {
+        divergence_at(sim.e_field(), cfg.dx, pos)
+    });
+    let div_b = Array3::from_shape_fn((width, width, width), |pos| {
+        b_scale(cfg) * divergence_at(sim.h_field(), cfg.dx, pos)
+    });
+    (div_e, div_b)
+}
+
+/// The Poynting vector at every grid point, laid out like the E and H fields
+pub fn poynting_field(sim: &FdtdSim, cfg: &FdtdSimConfig) -> Array4<f64> {
+    let width = sim.width();
+    Array4::from_shape_fn((width, width, width, 3), |(i, j, k, c)| {
+        poynting_at(sim, cfg, (i, j, k))[c]
+    })
+}
+
+/// Energy density at every grid point
+pub fn energy_density_field(sim: &FdtdSim, cfg: &FdtdSimConfig) -> Array3<f64> {
+    let width = sim.width();
+    Array3::from_shape_fn((width, width, width), |pos| {
+        FieldQuantity::EnergyDensity.sample(sim, cfg, pos)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A point where E is along y and the stored H along z
+    fn crossed_fields() -> (FdtdSim, FdtdSimConfig) {
+        let cfg = FdtdSimConfig {
+            mu: 4.0,
+            eps: 2.0,
+            ..Default::default()
+        };
+        let mut sim = FdtdSim::new(4);
+        sim.e_field[(1, 2, 3, 1)] = 3.0;
+        sim.h_field[(1, 2, 3, 2)] = 2.0;
+        (sim, cfg)
+    }
+
+    #[test]
+    fn poynting_vector_undoes_the_h_scaling() {
+        let (sim, cfg) = crossed_fields();
+        // True H is 2 * sqrt(eps / mu), so S = 3 * sqrt(2) along x
+        let expected = 3.0 * 2.0_f64.sqrt();
+        let sample = |q: FieldQuantity| q.sample(&sim, &cfg, (1, 2, 3));
+        assert!((sample(FieldQuantity::Sx) - expected).abs() < 1e-12);
+        assert_eq!(sample(FieldQuantity::Sy), 0.0);
+        assert_eq!(sample(FieldQuantity::Sz), 0.0);
+        assert_eq!(sample(FieldQuantity::SMagnitude), sample(FieldQuantity::Sx));
+
+        let field = poynting_field(&sim, &cfg);
+        assert_eq!(field[(1, 2, 3, 0)], sample(FieldQuantity::Sx));
+        assert_eq!(field.iter().filter(|&&s| s != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn energy_density_weighs_both_fields_by_eps() {
+        let (sim, cfg) = crossed_fields();
+        // eps (3² + 2²) / 2
+        let density = FieldQuantity::EnergyDensity.sample(&sim, &cfg, (1, 2, 3));
+        assert_eq!(density, 13.0);
+        assert_eq!(
+            FieldQuantity::EnergyDensity.sample(&sim, &cfg, (0, 0, 0)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn divergence_of_a_linear_field_is_its_slope() {
+        let mut field = Array4::zeros((5, 5, 5, 3));
+        for ((i, _, _, c), v) in field.indexed_iter_mut() {
+            if c == 0 {
+                *v = 2.0 * i as f64;
+            }
+        }
+        assert_eq!(divergence_at(&field, 0.5, (2, 1, 3)), 4.0);
+        assert_eq!(divergence_at(&field, 0.5, (0, 2, 2)), 0.0);
+        assert_eq!(divergence_at(&field, 0.5, (2, 2, 4)), 0.0);
+    }
+
+    #[test]
+    fn current_deposits_charge_but_no_magnetic_divergence() {
+        let cfg = FdtdSimConfig::default();
+        let width = 9;
+        let mut sim = FdtdSim::new(width);
+        let magnetization = Array4::zeros((width, width, width, 3));
+        let mut current = Array4::zeros((width, width, width, 3));
+        current[(4, 4, 4, 2)] = 1.0;
+        for _ in 0..5 {
+            sim.step(&cfg, &magnetization, &current);
+        }
+
+        let (div_e, div_b) = divergence_fields(&sim, &cfg);
+        // The current along +z leaves positive charge ahead of it and negative behind
+        assert!(div_e[(4, 4, 5)] > 0.0 && div_e[(4, 4, 3)] < 0.0);
+        let charge = FieldQuantity::ChargeDensity.sample(&sim, &cfg, (4, 4, 5));
+        assert_eq!(charge, cfg.eps * div_e[(4, 4, 5)]);
+
+        // The divergence of the discrete curl cancels away from the boundary layer
+        let interior = 2..width - 2;
+        for ((i, j, k), &d) in div_b.indexed_iter() {
+            if interior.contains(&i) && interior.contains(&j) && interior.contains(&k) {
+                assert!(d.abs() < 1e-12, "∇·B = {d} at {:?}", (i, j, k));
+            }
+        }
+
+        let density = energy_density_field(&sim, &cfg);
+        let at = (4, 5, 4);
+        assert_eq!(
+            density[at],
+            FieldQuantity::EnergyDensity.sample(&sim, &cfg, at)
+        );
+    }
+}
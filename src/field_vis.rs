@@ -1,10 +1,13 @@
-use egui::{Color32, DragValue, Stroke, Ui};
-use ndarray::Array4;
+use egui::{Color32, DragValue, Rect, Stroke, Ui, Vec2};
+use ndarray::{Array3, Array4};
 use threegui::{Painter3D, Vec3};
 
 use crate::{
+    colormap::{ColorScale, Colormap, ScaleRange, data_range, draw_legend},
     common::{espace, espacet, screenspace_arrow},
-    sim::FdtdSim,
+    field_quantity::{energy_density_field, poynting_field},
+    isosurface::Isosurfaces,
+    sim::{FdtdSim, FdtdSimConfig},
 };
 
 pub struct GridVisualizationConfig {
@@ -21,6 +24,23 @@ pub struct GridVisualizationConfig {
 
     pub show_e_mag: bool,
     pub show_h_mag: bool,
+
+    /// Poynting vector S = E × H
+    pub show_s_vect: bool,
+    pub show_s_mag: bool,
+    /// Energy density as dots
+    pub show_u_mag: bool,
+    /// Scale of the Poynting vector and energy density, which are quadratic in the fields
+    pub power_scale: f32,
+
+    /// Colour vectors and magnitude dots by field magnitude, instead of yellow (E) and red (H)
+    pub color_by_magnitude: bool,
+    pub e_colors: ColorScale,
+    pub h_colors: ColorScale,
+    pub s_colors: ColorScale,
+    pub u_colors: ColorScale,
+
+    pub isosurfaces: Isosurfaces,
 }
 
 impl Default for GridVisualizationConfig {
@@ -35,10 +55,23 @@ impl Default for GridVisualizationConfig {
             show_e_mag: false,
             show_h_mag: false,
 
+            show_s_vect: false,
+            show_s_mag: false,
+            show_u_mag: false,
+            power_scale: 1.0,
+
             show_grid: false,
             show_minimal_grid: true,
 
             vect_scale: 0.5,
+
+            color_by_magnitude: false,
+            e_colors: ColorScale::new(Colormap::Viridis),
+            h_colors: ColorScale::new(Colormap::Inferno),
+            s_colors: ColorScale::new(Colormap::Viridis),
+            u_colors: ColorScale::new(Colormap::Inferno),
+
+            isosurfaces: Isosurfaces::default(),
         }
     }
 }
@@ -63,9 +96,35 @@ impl GridVisualizationConfig {
                 .prefix("Scale: ")
                 .speed(1e-3),
         );
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_s_vect, "Show Poynting vects");
+            ui.checkbox(&mut self.show_s_mag, "mag");
+        });
+        ui.checkbox(&mut self.show_u_mag, "Show energy density");
+        ui.add(
+            DragValue::new(&mut self.power_scale)
+                .prefix("Power scale: ")
+                .speed(1e-3),
+        );
+
+        ui.checkbox(&mut self.color_by_magnitude, "Colour by magnitude");
+        if self.color_by_magnitude {
+            ui.label("E colours");
+            self.e_colors.show_ui(ui, "e_colors");
+            ui.label("H colours");
+            self.h_colors.show_ui(ui, "h_colors");
+            ui.label("S colours");
+            self.s_colors.show_ui(ui, "s_colors");
+            ui.label("Energy density colours");
+            self.u_colors.show_ui(ui, "u_colors");
+        }
+
+        ui.separator();
+        self.isosurfaces.show_ui(ui);
     }
 
-    pub fn draw(&self, sim: &FdtdSim, paint: &Painter3D) {
+    pub fn draw(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, paint: &Painter3D) {
         if self.show_grid {
             draw_grid(paint, sim.width(), Stroke::new(1., Color32::from_gray(36)));
         }
@@ -74,8 +133,59 @@ impl GridVisualizationConfig {
             draw_minimal_grid(paint, sim.width(), Color32::GRAY);
         }
 
+        self.isosurfaces.draw(sim, cfg, paint);
+
         let e_color = Stroke::new(1., Color32::YELLOW);
         let h_color = Stroke::new(1., Color32::RED);
+
+        let e_shown = self.show_e_vect || self.show_e_mag;
+        let h_shown = self.show_h_vect || self.show_h_mag;
+        let e_colors = (self.color_by_magnitude && e_shown).then(|| {
+            let range = magnitude_range(&self.e_colors, sim.e_field());
+            (&self.e_colors, range)
+        });
+        let h_colors = (self.color_by_magnitude && h_shown).then(|| {
+            let range = magnitude_range(&self.h_colors, sim.h_field());
+            (&self.h_colors, range)
+        });
+
+        // Derived fields, only computed when shown
+        let s_shown = self.show_s_vect || self.show_s_mag;
+        let poynting = s_shown.then(|| poynting_field(sim, cfg));
+        let energy = self.show_u_mag.then(|| energy_density_field(sim, cfg));
+
+        let s_colors = poynting
+            .as_ref()
+            .filter(|_| self.color_by_magnitude)
+            .map(|s| (&self.s_colors, magnitude_range(&self.s_colors, s)));
+        let u_colors = energy
+            .as_ref()
+            .filter(|_| self.color_by_magnitude)
+            .map(|u| {
+                let (lo, hi) = data_range(u.iter().copied());
+                (&self.u_colors, self.u_colors.range(lo, hi))
+            });
+
+        // Legends along the left edge of the view
+        let clip = paint.egui().clip_rect();
+        let mut legend_x = clip.left() + 10.0;
+        let legends = [
+            (&e_colors, "|E|"),
+            (&h_colors, "|H|"),
+            (&s_colors, "|S|"),
+            (&u_colors, "u"),
+        ];
+        for (colors, label) in legends {
+            if let Some((scale, range)) = colors {
+                let rect = Rect::from_min_size(
+                    egui::pos2(legend_x, clip.top() + 24.0),
+                    Vec2::new(14.0, 150.0),
+                );
+                draw_legend(paint.egui(), rect, scale, range, label);
+                legend_x += 80.0;
+            }
+        }
+
         if self.show_e_grid {
             draw_efield_grid(paint, &sim, e_color, self.vect_scale);
         }
@@ -84,18 +194,62 @@ impl GridVisualizationConfig {
         }
 
         if self.show_e_vect {
-            draw_efield_vect(paint, &sim, e_color, self.vect_scale);
+            draw_efield_vect(paint, &sim, e_color, self.vect_scale, e_colors);
         }
         if self.show_h_vect {
-            draw_hfield_vect(paint, &sim, h_color, self.vect_scale);
+            draw_hfield_vect(paint, &sim, h_color, self.vect_scale, h_colors);
         }
 
         if self.show_e_mag {
-            draw_efield_mag(paint, &sim, e_color.color, self.vect_scale * 10.);
+            draw_efield_mag(paint, &sim, e_color.color, self.vect_scale * 10., e_colors);
         }
         if self.show_h_mag {
-            draw_hfield_mag(paint, &sim, h_color.color, self.vect_scale * 10.);
+            draw_hfield_mag(paint, &sim, h_color.color, self.vect_scale * 10., h_colors);
         }
+
+        let s_color = Stroke::new(1., Color32::from_rgb(120, 255, 120));
+        if let Some(poynting) = &poynting {
+            let width = sim.width();
+            if self.show_s_vect {
+                draw_field_vect(paint, poynting, width, s_color, self.power_scale, s_colors);
+            }
+            if self.show_s_mag {
+                let scale = self.power_scale * 10.;
+                draw_field_magnitude(paint, poynting, width, s_color.color, scale, 0.0, s_colors);
+            }
+        }
+        if let Some(energy) = &energy {
+            let color = Color32::from_rgb(200, 120, 255);
+            draw_scalar_dots(paint, energy, color, self.power_scale * 10., u_colors);
+        }
+    }
+}
+
+/// A colour scale along with its range resolved for the current frame
+type MagnitudeColors<'a> = Option<(&'a ColorScale, ScaleRange)>;
+
+fn magnitude(field: &Array4<f64>, (i, j, k): (usize, usize, usize)) -> f64 {
+    (0..3)
+        .map(|c| field[(i, j, k, c)].powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Colour range for the magnitude of a vector field, auto-ranged from its current maximum
+fn magnitude_range(scale: &ColorScale, field: &Array4<f64>) -> ScaleRange {
+    let width = field.dim().0;
+    let magnitudes = (0..width).flat_map(|i| {
+        (0..width).flat_map(move |j| (0..width).map(move |k| magnitude(field, (i, j, k))))
+    });
+    let (lo, hi) = data_range(magnitudes);
+    scale.range(lo, hi)
+}
+
+/// Stroke colour for a vector of the given magnitude
+fn magnitude_color(colors: MagnitudeColors<'_>, default: Color32, magnitude: f64) -> Color32 {
+    match colors {
+        Some((scale, range)) => scale.color(&range, magnitude),
+        None => default,
     }
 }
 
@@ -164,12 +318,24 @@ fn draw_field_grid(
     }
 }
 
-fn draw_efield_vect(paint: &Painter3D, sim: &FdtdSim, stroke: Stroke, scale: f32) {
-    draw_field_vect(paint, sim.e_field(), sim.width(), stroke, scale);
+fn draw_efield_vect(
+    paint: &Painter3D,
+    sim: &FdtdSim,
+    stroke: Stroke,
+    scale: f32,
+    colors: MagnitudeColors<'_>,
+) {
+    draw_field_vect(paint, sim.e_field(), sim.width(), stroke, scale, colors);
 }
 
-fn draw_hfield_vect(paint: &Painter3D, sim: &FdtdSim, stroke: Stroke, scale: f32) {
-    draw_field_vect(paint, sim.h_field(), sim.width(), stroke, scale);
+fn draw_hfield_vect(
+    paint: &Painter3D,
+    sim: &FdtdSim,
+    stroke: Stroke,
+    scale: f32,
+    colors: MagnitudeColors<'_>,
+) {
+    draw_field_vect(paint, sim.h_field(), sim.width(), stroke, scale, colors);
 }
 
 fn draw_field_vect(
@@ -178,6 +344,7 @@ fn draw_field_vect(
     width: usize,
     stroke: Stroke,
     scale: f32,
+    colors: MagnitudeColors<'_>,
 ) {
     for i in 0..width {
         for j in 0..width {
@@ -190,7 +357,8 @@ fn draw_field_vect(
 
                 let pos = espacet(width, (i, j, k));
                 let end = pos + extent * scale;
-                screenspace_arrow(paint, pos, end, stroke)
+                let color = magnitude_color(colors, stroke.color, magnitude(field, (i, j, k)));
+                screenspace_arrow(paint, pos, end, Stroke::new(stroke.width, color))
             }
         }
     }
@@ -203,6 +371,7 @@ fn draw_field_magnitude(
     color: Color32,
     scale: f32,
     offset: f32,
+    colors: MagnitudeColors<'_>,
 ) {
     for i in 0..width {
         for j in 0..width {
@@ -215,16 +384,45 @@ fn draw_field_magnitude(
                 );
 
                 let pos = espace(width, base + offset);
+                let color = magnitude_color(colors, color, magnitude(field, (i, j, k)));
                 paint.circle_filled(pos, extent.length() * scale, color)
             }
         }
     }
 }
 
-fn draw_efield_mag(paint: &Painter3D, sim: &FdtdSim, color: Color32, scale: f32) {
-    draw_field_magnitude(paint, sim.e_field(), sim.width(), color, scale, 0.0);
+/// Dots with radius proportional to a non-negative scalar field
+fn draw_scalar_dots(
+    paint: &Painter3D,
+    values: &Array3<f64>,
+    color: Color32,
+    scale: f32,
+    colors: MagnitudeColors<'_>,
+) {
+    let width = values.dim().0;
+    for ((i, j, k), &value) in values.indexed_iter() {
+        let pos = espacet(width, (i, j, k));
+        let color = magnitude_color(colors, color, value);
+        paint.circle_filled(pos, value as f32 * scale, color);
+    }
+}
+
+fn draw_efield_mag(
+    paint: &Painter3D,
+    sim: &FdtdSim,
+    color: Color32,
+    scale: f32,
+    colors: MagnitudeColors<'_>,
+) {
+    draw_field_magnitude(paint, sim.e_field(), sim.width(), color, scale, 0.0, colors);
 }
 
-fn draw_hfield_mag(paint: &Painter3D, sim: &FdtdSim, color: Color32, scale: f32) {
-    draw_field_magnitude(paint, sim.h_field(), sim.width(), color, scale, 0.5);
+fn draw_hfield_mag(
+    paint: &Painter3D,
+    sim: &FdtdSim,
+    color: Color32,
+    scale: f32,
+    colors: MagnitudeColors<'_>,
+) {
+    draw_field_magnitude(paint, sim.h_field(), sim.width(), color, scale, 0.5, colors);
 }
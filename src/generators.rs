@@ -0,0 +1,529 @@
+use std::{f64::consts::TAU, ops::RangeInclusive};
+
+use egui::{DragValue, Ui};
+
+use crate::{
+    common::IntPos3,
+    wire_editor_3d::{Port, Wire, WireId, Wiring3D, normalize_wire_id, segment_edges},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StructureKind {
+    RectLoop,
+    CircularLoop,
+    Solenoid,
+    Spiral,
+    Dipole,
+    Helmholtz,
+}
+
+impl StructureKind {
+    const ALL: [Self; 6] = [
+        Self::RectLoop,
+        Self::CircularLoop,
+        Self::Solenoid,
+        Self::Spiral,
+        Self::Dipole,
+        Self::Helmholtz,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RectLoop => "Rectangular loop",
+            Self::CircularLoop => "Circular loop",
+            Self::Solenoid => "Solenoid",
+            Self::Spiral => "Spiral inductor",
+            Self::Dipole => "Dipole",
+            Self::Helmholtz => "Helmholtz pair",
+        }
+    }
+}
+
+/// Parameters for inserting a common structure into the 3D wiring.
+/// All lengths are in grid cells. Curves are approximated by straight segments
+/// between grid points, which the wiring routes as staircases.
+pub struct StructureGenerator {
+    pub kind: StructureKind,
+    pub center: IntPos3,
+    /// Loop normal, solenoid/spiral axis or dipole direction
+    pub axis: usize,
+    /// Loop, solenoid and Helmholtz radius; half-width of rectangular loops;
+    /// spiral inner half-width
+    pub radius: usize,
+    /// Half-height of rectangular loops
+    pub half_height: usize,
+    pub turns: usize,
+    /// Solenoid advance per turn, or spacing between spiral turns
+    pub pitch: usize,
+    /// Straight segments per turn of circular structures
+    pub segments_per_turn: usize,
+    /// Length of each dipole arm
+    pub arm_length: usize,
+    /// Dipole feed gap
+    pub gap: usize,
+    /// Leave a feed gap in loops, with ports at its ends
+    pub feed: bool,
+    pub port_names: [String; 2],
+    pub wire: Wire,
+}
+
+impl StructureGenerator {
+    pub fn new(width: usize, wire: Wire) -> Self {
+        let mid = width / 2;
+        Self {
+            kind: StructureKind::CircularLoop,
+            center: (mid, mid, mid),
+            axis: 2,
+            radius: (width / 4).max(1),
+            half_height: (width / 4).max(1),
+            turns: 3,
+            pitch: 2,
+            segments_per_turn: 16,
+            arm_length: (width / 3).max(1),
+            gap: 1,
+            feed: true,
+            port_names: ["A".into(), "B".into()],
+            wire,
+        }
+    }
+
+    /// Builds the structure, or explains why it does not fit in a grid of the given width
+    pub fn generate(&self, width: usize) -> Result<Wiring3D, String> {
+        let mut builder = Builder {
+            generator: self,
+            width,
+            wiring: Wiring3D::default(),
+            out_of_bounds: false,
+        };
+
+        let [a, b] = &self.port_names;
+        let r = self.radius as f64;
+
+        match self.kind {
+            StructureKind::RectLoop => {
+                let (hw, hh) = (self.radius as f64, self.half_height as f64);
+                // Start mid-way along one side, so the feed gap is the lower half of that side
+                let corners = [(hw, 0.0), (hw, hh), (-hw, hh), (-hw, -hh), (hw, -hh)];
+                let points: Vec<[f64; 3]> = corners.iter().map(|&(u, v)| [u, v, 0.0]).collect();
+                builder.closed_loop(&points, a, b);
+            }
+            StructureKind::CircularLoop => {
+                builder.closed_loop(&circle(r, self.segments(), 0.0), a, b);
+            }
+            StructureKind::Helmholtz => {
+                // Coils one radius apart, wound in the same direction
+                for (i, w) in [-r / 2.0, r / 2.0].into_iter().enumerate() {
+                    let (a, b) = (format!("{a} {}", i + 1), format!("{b} {}", i + 1));
+                    builder.closed_loop(&circle(r, self.segments(), w), &a, &b);
+                }
+            }
+            StructureKind::Solenoid => {
+                let n = self.segments() * self.turns.max(1);
+                let length = (self.pitch * self.turns.max(1)) as f64;
+                let points: Vec<[f64; 3]> = (0..=n)
+                    .map(|i| {
+                        let t = i as f64 / n as f64;
+                        let theta = TAU * self.turns.max(1) as f64 * t;
+                        [r * theta.cos(), r * theta.sin(), length * (t - 0.5)]
+                    })
+                    .collect();
+                builder.polyline(&points);
+                builder.port(points[0], a);
+                builder.port(points[n], b);
+            }
+            StructureKind::Spiral => {
+                // Square spiral: legs go up, left, down, right, growing by one pitch every two legs
+                let (r0, p) = (self.radius as f64, self.pitch.max(1) as f64);
+                let directions = [(0.0, 1.0), (-1.0, 0.0), (0.0, -1.0), (1.0, 0.0)];
+                let mut pos = [r0, -r0, 0.0];
+                let mut points = vec![pos];
+                for k in 0..4 * self.turns.max(1) {
+                    let len = 2.0 * r0 + p * k.div_ceil(2) as f64;
+                    let (du, dv) = directions[k % 4];
+                    pos = [pos[0] + du * len, pos[1] + dv * len, 0.0];
+                    points.push(pos);
+                }
+                builder.polyline(&points);
+                builder.port(points[0], a);
+                builder.port(*points.last().unwrap(), b);
+            }
+            StructureKind::Dipole => {
+                let gap = self.gap.max(1);
+                let lo = (gap / 2) as f64;
+                let hi = (gap - gap / 2) as f64;
+                let arm = self.arm_length.max(1) as f64;
+                builder.polyline(&[[0.0, 0.0, -lo - arm], [0.0, 0.0, -lo]]);
+                builder.polyline(&[[0.0, 0.0, hi], [0.0, 0.0, hi + arm]]);
+                builder.port([0.0, 0.0, -lo], a);
+                builder.port([0.0, 0.0, hi], b);
+            }
+        }
+
+        if builder.out_of_bounds {
+            return Err("Structure does not fit in the grid".into());
+        }
+        Ok(builder.wiring)
+    }
+
+    /// Straight segments per turn actually used: at least a triangle, and no more than the
+    /// unit edges around the staircase of the circle, beyond which grid points only repeat
+    fn segments(&self) -> usize {
+        self.segments_per_turn.clamp(3, self.max_segments())
+    }
+
+    fn max_segments(&self) -> usize {
+        (8 * self.radius).max(3)
+    }
+
+    /// Most turns that fit in a grid of the given width; turns without any spacing
+    /// between them would lie on top of each other
+    fn max_turns(&self, width: usize) -> usize {
+        let span = match self.kind {
+            StructureKind::Solenoid => width.saturating_sub(1),
+            // The spiral grows by one spacing on each side per turn
+            _ => (width.saturating_sub(1) / 2).saturating_sub(self.radius),
+        };
+        span.checked_div(self.pitch).unwrap_or(1).max(1)
+    }
+
+    /// Returns true if any parameter changed
+    pub fn show_ui(&mut self, ui: &mut Ui, width: usize) -> bool {
+        let mut changed = false;
+        let max = width.saturating_sub(1);
+        let (max_turns, max_segments) = (self.max_turns(width), self.max_segments());
+
+        egui::ComboBox::from_label("Structure")
+            .selected_text(self.kind.name())
+            .show_ui(ui, |ui| {
+                for kind in StructureKind::ALL {
+                    changed |= ui
+                        .selectable_value(&mut self.kind, kind, kind.name())
+                        .changed();
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Center: ");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.center.0)
+                        .range(0..=max)
+                        .prefix("x: "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.center.1)
+                        .range(0..=max)
+                        .prefix("y: "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.center.2)
+                        .range(0..=max)
+                        .prefix("z: "),
+                )
+                .changed();
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Axis: ");
+            for (axis, name) in ["X", "Y", "Z"].into_iter().enumerate() {
+                changed |= ui.selectable_value(&mut self.axis, axis, name).changed();
+            }
+        });
+
+        let mut drag =
+            |ui: &mut Ui, value: &mut usize, range: RangeInclusive<usize>, label: &str| {
+                changed |= ui
+                    .add(DragValue::new(value).range(range).prefix(label))
+                    .changed();
+            };
+
+        match self.kind {
+            StructureKind::RectLoop => {
+                drag(ui, &mut self.radius, 0..=max, "Half width: ");
+                drag(ui, &mut self.half_height, 0..=max, "Half height: ");
+            }
+            StructureKind::CircularLoop | StructureKind::Helmholtz => {
+                drag(ui, &mut self.radius, 0..=max, "Radius: ");
+                drag(
+                    ui,
+                    &mut self.segments_per_turn,
+                    3..=max_segments,
+                    "Segments: ",
+                );
+            }
+            StructureKind::Solenoid => {
+                drag(ui, &mut self.radius, 0..=max, "Radius: ");
+                drag(ui, &mut self.turns, 1..=max_turns, "Turns: ");
+                drag(ui, &mut self.pitch, 0..=max, "Pitch: ");
+                drag(
+                    ui,
+                    &mut self.segments_per_turn,
+                    3..=max_segments,
+                    "Segments per turn: ",
+                );
+            }
+            StructureKind::Spiral => {
+                drag(ui, &mut self.radius, 0..=max, "Inner half-width: ");
+                drag(ui, &mut self.turns, 1..=max_turns, "Turns: ");
+                drag(ui, &mut self.pitch, 0..=max, "Spacing: ");
+            }
+            StructureKind::Dipole => {
+                drag(ui, &mut self.arm_length, 0..=max, "Arm length: ");
+                drag(ui, &mut self.gap, 0..=max, "Feed gap: ");
+            }
+        }
+
+        if matches!(
+            self.kind,
+            StructureKind::RectLoop | StructureKind::CircularLoop | StructureKind::Helmholtz
+        ) {
+            changed |= ui.checkbox(&mut self.feed, "Feed gap with ports").changed();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Ports: ");
+            changed |= ui.text_edit_singleline(&mut self.port_names[0]).changed();
+            changed |= ui.text_edit_singleline(&mut self.port_names[1]).changed();
+        });
+
+        changed |= self.wire.show_ui(ui);
+
+        changed
+    }
+}
+
+/// Accumulates wires and ports given in coordinates local to the structure:
+/// (u, v) span the plane normal to the axis and w runs along it.
+struct Builder<'a> {
+    generator: &'a StructureGenerator,
+    width: usize,
+    wiring: Wiring3D,
+    /// Set if any point was clamped to the grid
+    out_of_bounds: bool,
+}
+
+impl Builder<'_> {
+    /// Rounds a local point to the nearest grid point
+    fn grid_point(&mut self, [u, v, w]: [f64; 3]) -> IntPos3 {
+        let axis = self.generator.axis;
+        let mut offset = [0.0; 3];
+        offset[(axis + 1) % 3] = u;
+        offset[(axis + 2) % 3] = v;
+        offset[axis] = w;
+
+        let (cx, cy, cz) = self.generator.center;
+        let max = self.width.saturating_sub(1) as isize;
+        let center = [cx, cy, cz].map(|c| c as isize);
+        let [x, y, z]: [isize; 3] = std::array::from_fn(|i| center[i] + offset[i].round() as isize);
+        let mut clamp = |p: isize| {
+            self.out_of_bounds |= p < 0 || p > max;
+            p.clamp(0, max) as usize
+        };
+        (clamp(x), clamp(y), clamp(z))
+    }
+
+    fn polyline(&mut self, points: &[[f64; 3]]) {
+        self.route(points, false);
+    }
+
+    /// Inserts the staircase through the points as wires. Where one straight piece starts by
+    /// retracing the end of the last, as diagonals meeting at a grid point can, the spur is
+    /// dropped, also across the start of a closed path. What remains is stored as the longest
+    /// straight segments which route along the same edges.
+    fn route(&mut self, points: &[[f64; 3]], closed: bool) {
+        let corners: Vec<IntPos3> = points.iter().map(|&p| self.grid_point(p)).collect();
+        let mut path = vec![corners[0]];
+        for pair in corners.windows(2) {
+            for (p, q) in segment_edges((pair[0], pair[1])) {
+                let cur = *path.last().unwrap();
+                let next = if p == cur { q } else { p };
+                if path.len() >= 2 && path[path.len() - 2] == next {
+                    path.pop();
+                } else {
+                    path.push(next);
+                }
+            }
+        }
+        if closed {
+            while path.len() > 3 && path[1] == path[path.len() - 2] {
+                path.remove(0);
+                path.pop();
+            }
+        }
+
+        let mut i = 0;
+        while i + 1 < path.len() {
+            let mut j = i + 1;
+            while j + 1 < path.len() && routes_along(&path[i..=j + 1]) {
+                j += 1;
+            }
+            self.wiring.insert((path[i], path[j]), self.generator.wire);
+            i = j;
+        }
+    }
+
+    /// Joins the points into a loop, leaving out the closing segment as a feed gap if requested
+    fn closed_loop(&mut self, points: &[[f64; 3]], a: &str, b: &str) {
+        if self.generator.feed {
+            self.polyline(points);
+            self.port(points[0], a);
+            self.port(*points.last().unwrap(), b);
+        } else {
+            let mut closed = points.to_vec();
+            closed.push(points[0]);
+            self.route(&closed, true);
+        }
+    }
+
+    fn port(&mut self, point: [f64; 3], name: &str) {
+        if !name.is_empty() {
+            let pos = self.grid_point(point);
+            self.wiring.ports.insert(pos, Port(name.to_string()));
+        }
+    }
+}
+
+/// Whether the straight segment between the ends of `path` routes along its edges
+fn routes_along(path: &[IntPos3]) -> bool {
+    let mut edges: Vec<WireId> = path
+        .windows(2)
+        .map(|pair| normalize_wire_id((pair[0], pair[1])))
+        .collect();
+    let mut direct = segment_edges((path[0], path[path.len() - 1]));
+    edges.sort();
+    direct.sort();
+    edges == direct
+}
+
+/// Points around a circle in the (u, v) plane at height w, not repeating the first point
+fn circle(radius: f64, segments: usize, w: f64) -> Vec<[f64; 3]> {
+    let n = segments.max(3);
+    (0..n)
+        .map(|i| {
+            let theta = TAU * i as f64 / n as f64;
+            [radius * theta.cos(), radius * theta.sin(), w]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const WIRE: Wire = Wire {
+        resistance: 1.0,
+        radius: None,
+    };
+
+    /// A small structure about (3, 3, 3) in a 7³ grid, normal to z
+    fn generator(kind: StructureKind) -> StructureGenerator {
+        StructureGenerator {
+            kind,
+            radius: 1,
+            half_height: 1,
+            turns: 1,
+            pitch: 1,
+            segments_per_turn: 4,
+            arm_length: 2,
+            ..StructureGenerator::new(7, WIRE)
+        }
+    }
+
+    fn edges(wiring: &Wiring3D) -> Vec<WireId> {
+        let mut edges: Vec<WireId> = wiring.unit_edges().into_iter().map(|(e, _)| e).collect();
+        edges.sort();
+        edges
+    }
+
+    /// The wiring is made of unbranched paths that end only at its ports
+    fn assert_unbranched(wiring: &Wiring3D) {
+        let mut degrees: HashMap<IntPos3, usize> = HashMap::new();
+        for (a, b) in edges(wiring) {
+            *degrees.entry(a).or_default() += 1;
+            *degrees.entry(b).or_default() += 1;
+        }
+        for (pos, degree) in degrees {
+            let expected = if wiring.ports.contains_key(&pos) {
+                1
+            } else {
+                2
+            };
+            assert_eq!(degree, expected, "at {pos:?}");
+        }
+    }
+
+    /// Unit edges around the square [2, 4]² in the plane z = 3
+    fn square() -> Vec<WireId> {
+        let mut edges = vec![];
+        for i in 2..4 {
+            edges.push(((i, 2, 3), (i + 1, 2, 3)));
+            edges.push(((i, 4, 3), (i + 1, 4, 3)));
+            edges.push(((2, i, 3), (2, i + 1, 3)));
+            edges.push(((4, i, 3), (4, i + 1, 3)));
+        }
+        edges.sort();
+        edges
+    }
+
+    #[test]
+    fn rect_loop_feed_gap_is_the_lower_half_of_one_side() {
+        let wiring = generator(StructureKind::RectLoop).generate(7).unwrap();
+        let gap = ((4, 2, 3), (4, 3, 3));
+        let expected: Vec<WireId> = square().into_iter().filter(|&e| e != gap).collect();
+        assert_eq!(edges(&wiring), expected);
+        let ports: HashMap<IntPos3, &str> = wiring
+            .ports
+            .iter()
+            .map(|(pos, port)| (*pos, port.0.as_str()))
+            .collect();
+        assert_eq!(ports, [((4, 3, 3), "A"), ((4, 2, 3), "B")].into());
+    }
+
+    #[test]
+    fn curved_structures_are_unbranched_paths_between_their_ports() {
+        for generator in [
+            StructureGenerator {
+                radius: 2,
+                ..generator(StructureKind::CircularLoop)
+            },
+            StructureGenerator {
+                radius: 2,
+                feed: false,
+                ..generator(StructureKind::CircularLoop)
+            },
+            StructureGenerator {
+                radius: 2,
+                ..generator(StructureKind::Helmholtz)
+            },
+            StructureGenerator {
+                pitch: 2,
+                ..generator(StructureKind::Solenoid)
+            },
+            generator(StructureKind::Spiral),
+        ] {
+            assert_unbranched(&generator.generate(7).unwrap());
+        }
+    }
+
+    #[test]
+    fn structures_leaving_the_grid_are_refused() {
+        let too_wide = StructureGenerator {
+            radius: 4,
+            ..generator(StructureKind::RectLoop)
+        };
+        assert!(too_wide.generate(7).is_err());
+
+        let too_long = StructureGenerator {
+            arm_length: 3,
+            ..generator(StructureKind::Dipole)
+        };
+        assert!(too_long.generate(7).is_err());
+        assert!(too_long.generate(8).is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use egui::{Color32, RichText, Ui};
+
+/// Bounded undo/redo stacks of snapshots, each labelled with the action which followed it.
+pub struct History<T> {
+    undo: VecDeque<(String, T)>,
+    redo: Vec<(String, T)>,
+    capacity: usize,
+    /// Whether the next push with the same label as the last one is merged into it
+    coalescing: bool,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            capacity,
+            coalescing: false,
+        }
+    }
+
+    /// Records `before`, the state prior to the action described by `label`.
+    pub fn push(&mut self, label: impl Into<String>, before: T) {
+        self.undo.push_back((label.into(), before));
+        if self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+        self.coalescing = false;
+    }
+
+    /// Like `push`, but continuous edits (e.g. dragging a value) with the same label
+    /// are merged into a single entry until `end_coalescing` is called.
+    pub fn push_coalesced(&mut self, label: impl Into<String>, before: T) {
+        let label = label.into();
+        let same_action = self.undo.back().is_some_and(|(last, _)| *last == label);
+        if !(self.coalescing && same_action) {
+            self.push(label, before);
+        }
+        self.coalescing = true;
+    }
+
+    pub fn end_coalescing(&mut self) {
+        self.coalescing = false;
+    }
+
+    /// Returns true if anything was undone
+    pub fn undo(&mut self, current: &mut T) -> bool {
+        let Some((label, before)) = self.undo.pop_back() else {
+            return false;
+        };
+        let after = std::mem::replace(current, before);
+        self.redo.push((label, after));
+        self.coalescing = false;
+        true
+    }
+
+    /// Returns true if anything was redone
+    pub fn redo(&mut self, current: &mut T) -> bool {
+        let Some((label, after)) = self.redo.pop() else {
+            return false;
+        };
+        let before = std::mem::replace(current, after);
+        self.undo.push_back((label, before));
+        self.coalescing = false;
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Shows undo/redo buttons and the list of actions; clicking an action
+    /// moves the history to just after it. Returns true if `current` changed.
+    pub fn show_ui(&mut self, ui: &mut Ui, current: &mut T) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.can_undo(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                changed |= self.undo(current);
+            }
+            if ui
+                .add_enabled(self.can_redo(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                changed |= self.redo(current);
+            }
+        });
+
+        let mut undo_to = None;
+        let mut redo_to = None;
+
+        if ui
+            .selectable_label(self.undo.is_empty(), "(start)")
+            .clicked()
+        {
+            undo_to = Some(self.undo.len());
+        }
+        for (i, (label, _)) in self.undo.iter().enumerate() {
+            let is_current = i + 1 == self.undo.len();
+            if ui.selectable_label(is_current, label).clicked() {
+                undo_to = Some(self.undo.len() - i - 1);
+            }
+        }
+        for (i, (label, _)) in self.redo.iter().rev().enumerate() {
+            let text = RichText::new(label).color(Color32::GRAY);
+            if ui.selectable_label(false, text).clicked() {
+                redo_to = Some(i + 1);
+            }
+        }
+
+        for _ in 0..undo_to.unwrap_or(0) {
+            changed |= self.undo(current);
+        }
+        for _ in 0..redo_to.unwrap_or(0) {
+            changed |= self.redo(current);
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_restore_snapshots() {
+        let mut history = History::new(10);
+        let mut doc = 0;
+        for value in 1..=3 {
+            history.push(format!("set {value}"), doc);
+            doc = value;
+        }
+
+        assert!(history.undo(&mut doc));
+        assert_eq!(doc, 2);
+        assert!(history.undo(&mut doc));
+        assert_eq!(doc, 1);
+        assert!(history.redo(&mut doc));
+        assert_eq!(doc, 2);
+        assert!(history.redo(&mut doc));
+        assert_eq!(doc, 3);
+        assert!(!history.redo(&mut doc));
+    }
+
+    #[test]
+    fn push_clears_redo() {
+        let mut history = History::new(10);
+        let mut doc = 0;
+        history.push("a", doc);
+        doc = 1;
+        history.undo(&mut doc);
+        assert!(history.can_redo());
+
+        history.push("b", doc);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn capacity_drops_oldest() {
+        let mut history = History::new(2);
+        let mut doc = 0;
+        for value in 1..=3 {
+            history.push("set", doc);
+            doc = value;
+        }
+
+        assert!(history.undo(&mut doc));
+        assert!(history.undo(&mut doc));
+        assert!(!history.undo(&mut doc));
+        assert_eq!(doc, 1);
+    }
+
+    #[test]
+    fn coalesced_edits_undo_together() {
+        let mut history = History::new(10);
+        let mut doc = 0;
+        for value in 1..=5 {
+            history.push_coalesced("drag", doc);
+            doc = value;
+        }
+        history.end_coalescing();
+        history.push_coalesced("drag", doc);
+        doc = 6;
+
+        assert!(history.undo(&mut doc));
+        assert_eq!(doc, 5);
+        assert!(history.undo(&mut doc));
+        assert_eq!(doc, 0);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn different_labels_are_not_coalesced() {
+        let mut history = History::new(10);
+        let mut doc = 0;
+        history.push_coalesced("a", doc);
+        doc = 1;
+        history.push_coalesced("b", doc);
+        doc = 2;
+
+        history.undo(&mut doc);
+        assert_eq!(doc, 1);
+    }
+}
@@ -0,0 +1,358 @@
+use egui::{Color32, DragValue, Mesh, Pos2, Shape, Ui};
+use threegui::{Painter3D, Vec3};
+
+use crate::{
+    colormap::Colormap,
+    common::espace,
+    field_quantity::FieldQuantity,
+    sim::{FdtdSim, FdtdSimConfig},
+};
+
+/// Quantities which isosurfaces can be drawn of
+const ISOSURFACE_QUANTITIES: [FieldQuantity; 4] = [
+    FieldQuantity::EMagnitude,
+    FieldQuantity::HMagnitude,
+    FieldQuantity::EnergyDensity,
+    FieldQuantity::SMagnitude,
+];
+
+/// The six tetrahedra around the main diagonal of a cube, by corner index.
+/// Corner `c` is at offset (c & 1, (c >> 1) & 1, (c >> 2) & 1).
+const CUBE_TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 3, 2, 7],
+    [0, 2, 6, 7],
+    [0, 6, 4, 7],
+    [0, 4, 5, 7],
+    [0, 5, 1, 7],
+];
+
+/// Surfaces of constant |E|, |H|, |S| or energy density, drawn as translucent shaded meshes
+pub struct Isosurfaces {
+    pub enabled: bool,
+    pub quantity: FieldQuantity,
+    pub levels: Vec<f64>,
+    pub colormap: Colormap,
+    pub opacity: f32,
+    /// Steps between recomputing the surfaces
+    pub every_n_steps: usize,
+    /// Triangles in grid coordinates, per level, and the key they were computed for
+    cache: Option<(CacheKey, Vec<Vec<[Vec3; 3]>>)>,
+}
+
+struct CacheKey {
+    steps: usize,
+    width: usize,
+    quantity: FieldQuantity,
+    levels: Vec<f64>,
+    eps: f64,
+    mu: f64,
+}
+
+impl Default for Isosurfaces {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            quantity: FieldQuantity::EMagnitude,
+            levels: vec![0.1],
+            colormap: Colormap::Viridis,
+            opacity: 0.5,
+            every_n_steps: 5,
+            cache: None,
+        }
+    }
+}
+
+impl Isosurfaces {
+    pub fn show_ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.enabled, "Show isosurfaces");
+        if !self.enabled {
+            return;
+        }
+
+        egui::ComboBox::from_id_salt("isosurface_quantity")
+            .selected_text(self.quantity.name())
+            .show_ui(ui, |ui| {
+                for quantity in ISOSURFACE_QUANTITIES {
+                    ui.selectable_value(&mut self.quantity, quantity, quantity.name());
+                }
+            });
+
+        let mut remove = None;
+        for (i, level) in self.levels.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(level).prefix("Level: ").speed(1e-3));
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.levels.remove(i);
+        }
+        if ui.button("Add level").clicked() {
+            let next = self.levels.last().map(|l| l * 2.0).unwrap_or(0.1);
+            self.levels.push(next);
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for colormap in Colormap::ALL {
+                ui.selectable_value(&mut self.colormap, colormap, colormap.name());
+            }
+        });
+        ui.add(
+            DragValue::new(&mut self.opacity)
+                .range(0.0..=1.0)
+                .prefix("Opacity: ")
+                .speed(1e-2),
+        );
+        ui.add(
+            DragValue::new(&mut self.every_n_steps)
+                .range(1..=usize::MAX)
+                .prefix("Recompute every ")
+                .suffix(" steps"),
+        );
+    }
+
+    pub fn draw(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, paint: &Painter3D) {
+        if !self.enabled {
+            return;
+        }
+
+        let key = CacheKey {
+            steps: sim.steps(),
+            width: sim.width(),
+            quantity: self.quantity,
+            levels: self.levels.clone(),
+            eps: cfg.eps,
+            mu: cfg.mu,
+        };
+        let stale = match &self.cache {
+            None => true,
+            Some((cached, _)) => {
+                let settings_changed = cached.width != key.width
+                    || cached.quantity != key.quantity
+                    || cached.levels != key.levels
+                    || cached.eps != key.eps
+                    || cached.mu != key.mu;
+                // The step count goes backwards when the simulation is rebuilt
+                settings_changed
+                    || key.steps < cached.steps
+                    || key.steps - cached.steps >= self.every_n_steps.max(1)
+            }
+        };
+        if stale {
+            let values = sample_grid(sim, cfg, self.quantity);
+            let surfaces = self
+                .levels
+                .iter()
+                .map(|&level| marching_tetrahedra(&values, sim.width(), level))
+                .collect();
+            self.cache = Some((key, surfaces));
+        }
+
+        let Some((_, surfaces)) = &self.cache else {
+            return;
+        };
+        let width = sim.width();
+        let light = Vec3::new(0.3, 0.5, 0.8).normalize();
+
+        for (i, triangles) in surfaces.iter().enumerate() {
+            let t = i as f32 / (surfaces.len() - 1).max(1) as f32;
+            let base = self.colormap.sample(t);
+
+            let mut mesh = Mesh::default();
+            for tri in triangles {
+                let Some(screen) = tri
+                    .iter()
+                    .map(|&p| paint.transform(espace(width, p)))
+                    .collect::<Option<Vec<Pos2>>>()
+                else {
+                    continue;
+                };
+
+                // Two-sided Lambertian shading with a fixed light
+                let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]).normalize_or_zero();
+                let brightness = 0.35 + 0.65 * normal.dot(light).abs();
+                let color = Color32::from_rgb(
+                    (base.r() as f32 * brightness) as u8,
+                    (base.g() as f32 * brightness) as u8,
+                    (base.b() as f32 * brightness) as u8,
+                )
+                .gamma_multiply(self.opacity);
+
+                let idx = mesh.vertices.len() as u32;
+                for pos in screen {
+                    mesh.colored_vertex(pos, color);
+                }
+                mesh.add_triangle(idx, idx + 1, idx + 2);
+            }
+            paint.egui().add(Shape::mesh(mesh));
+        }
+    }
+}
+
+/// The quantity at every grid point, indexed by `i + width * (j + width * k)`
+fn sample_grid(sim: &FdtdSim, cfg: &FdtdSimConfig, quantity: FieldQuantity) -> Vec<f64> {
+    let width = sim.width();
+    let mut values = Vec::with_capacity(width.pow(3));
+    for k in 0..width {
+        for j in 0..width {
+            for i in 0..width {
+                values.push(quantity.sample(sim, cfg, (i, j, k)));
+            }
+        }
+    }
+    values
+}
+
+/// Triangles approximating the surface where `values` equals `level`, in grid coordinates.
+/// Each cube of grid points is split into six tetrahedra, which have few enough cases
+/// to handle directly and don't suffer the ambiguities of marching cubes.
+fn marching_tetrahedra(values: &[f64], width: usize, level: f64) -> Vec<[Vec3; 3]> {
+    let mut triangles = vec![];
+    if width < 2 {
+        return triangles;
+    }
+
+    let index = |i: usize, j: usize, k: usize| i + width * (j + width * k);
+
+    for k in 0..width - 1 {
+        for j in 0..width - 1 {
+            for i in 0..width - 1 {
+                let corners: [(Vec3, f64); 8] = std::array::from_fn(|c| {
+                    let (di, dj, dk) = (c & 1, (c >> 1) & 1, (c >> 2) & 1);
+                    let pos = Vec3::new((i + di) as f32, (j + dj) as f32, (k + dk) as f32);
+                    (pos, values[index(i + di, j + dj, k + dk)])
+                });
+
+                for tet in CUBE_TETRAHEDRA {
+                    polygonize_tetrahedron(tet.map(|c| corners[c]), level, &mut triangles);
+                }
+            }
+        }
+    }
+
+    triangles
+}
+
+fn polygonize_tetrahedron(tet: [(Vec3, f64); 4], level: f64, out: &mut Vec<[Vec3; 3]>) {
+    let (inside, outside): (Vec<_>, Vec<_>) = tet.iter().partition(|(_, v)| *v >= level);
+
+    let crossing = |(pa, fa): &(Vec3, f64), (pb, fb): &(Vec3, f64)| {
+        let t = if fb != fa {
+            (level - fa) / (fb - fa)
+        } else {
+            0.5
+        };
+        pa.lerp(*pb, t as f32)
+    };
+
+    match (inside.len(), outside.len()) {
+        (1, 3) => {
+            let a = inside[0];
+            out.push([
+                crossing(a, outside[0]),
+                crossing(a, outside[1]),
+                crossing(a, outside[2]),
+            ]);
+        }
+        (3, 1) => {
+            let a = outside[0];
+            out.push([
+                crossing(a, inside[0]),
+                crossing(a, inside[1]),
+                crossing(a, inside[2]),
+            ]);
+        }
+        (2, 2) => {
+            let (a, b) = (inside[0], inside[1]);
+            let (c, d) = (outside[0], outside[1]);
+            let quad = [
+                crossing(a, c),
+                crossing(a, d),
+                crossing(b, d),
+                crossing(b, c),
+            ];
+            out.push([quad[0], quad[1], quad[2]]);
+            out.push([quad[0], quad[2], quad[3]]);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: usize, f: impl Fn(Vec3) -> f64) -> Vec<f64> {
+        let mut values = vec![];
+        for k in 0..width {
+            for j in 0..width {
+                for i in 0..width {
+                    values.push(f(Vec3::new(i as f32, j as f32, k as f32)));
+                }
+            }
+        }
+        values
+    }
+
+    fn area(triangles: &[[Vec3; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| (*b - *a).cross(*c - *a).length() / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn tetrahedra_fill_the_cube() {
+        let corner = |c: usize| Vec3::new((c & 1) as f32, ((c >> 1) & 1) as f32, (c >> 2) as f32);
+        let volume: f32 = CUBE_TETRAHEDRA
+            .iter()
+            .map(|tet| {
+                let [a, b, c, d] = tet.map(corner);
+                (b - a).dot((c - a).cross(d - a)).abs() / 6.0
+            })
+            .sum();
+        assert!((volume - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_surface_when_level_is_not_crossed() {
+        let values = grid(4, |_| 1.0);
+        assert!(marching_tetrahedra(&values, 4, 2.0).is_empty());
+        assert!(marching_tetrahedra(&values, 4, 0.5).is_empty());
+        assert!(marching_tetrahedra(&[1.0], 1, 0.5).is_empty());
+    }
+
+    #[test]
+    fn plane_is_flat_and_spans_the_grid() {
+        let width = 4;
+        let values = grid(width, |p| p.x as f64);
+        let triangles = marching_tetrahedra(&values, width, 1.25);
+
+        assert!(
+            triangles
+                .iter()
+                .flatten()
+                .all(|v| (v.x - 1.25).abs() < 1e-5)
+        );
+        let side = (width - 1) as f32;
+        assert!((area(&triangles) - side * side).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sphere_vertices_lie_near_the_radius() {
+        let (width, radius) = (12, 4.0);
+        let center = Vec3::splat(5.5);
+        let values = grid(width, |p| p.distance(center) as f64);
+        let triangles = marching_tetrahedra(&values, width, radius as f64);
+
+        assert!(!triangles.is_empty());
+        for v in triangles.iter().flatten() {
+            assert!((v.distance(center) - radius).abs() < 0.2, "{v:?}");
+        }
+        let sphere = 4.0 * std::f32::consts::PI * radius * radius;
+        assert!((area(&triangles) / sphere - 1.0).abs() < 0.1);
+    }
+}
@@ -2,11 +2,29 @@
 
 mod app;
 pub use app::FdtdApp;
+#[cfg(not(target_arch = "wasm32"))]
+pub use app::run_headless;
+pub mod checkpoint;
 mod circuit_editor;
+pub mod circuit_history;
+pub mod colormap;
 pub mod common;
+pub mod diagnostics;
 mod fdtd_editor;
+pub mod field_quantity;
 pub mod field_vis;
+pub mod generators;
+pub mod history;
+pub mod isosurface;
+pub mod nets;
 pub mod node_map;
+pub mod npy;
+pub mod particles;
+pub mod picking;
+pub mod plot;
+pub mod port_diagnostics;
 pub mod sim;
+pub mod slice_vis;
 pub mod streamers;
+pub mod vtk;
 pub mod wire_editor_3d;
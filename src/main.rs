@@ -3,6 +3,16 @@
 fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        if let Err(e) = headless(&args) {
+            eprintln!("{e}");
+            eprintln!("{HEADLESS_USAGE}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -21,6 +31,37 @@ fn main() -> eframe::Result {
     )
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+const HEADLESS_USAGE: &str =
+    "Usage: fdtd --headless <file.emf> --steps <N> --out <dir> [--every <steps>]";
+
+/// Runs a document without a window and writes its fields as a VTK time series
+#[cfg(not(target_arch = "wasm32"))]
+fn headless(args: &[String]) -> Result<(), String> {
+    let mut emf = None;
+    let mut steps = None;
+    let mut out = None;
+    let mut every = 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        let number = |v: &String| v.parse::<usize>().map_err(|e| format!("{arg}: {e}"));
+        match arg.as_str() {
+            "--headless" => emf = Some(value()?),
+            "--steps" => steps = Some(number(value()?)?),
+            "--out" => out = Some(value()?),
+            "--every" => every = number(value()?)?,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    let emf = emf.ok_or("Missing --headless <file.emf>")?;
+    let steps = steps.ok_or("Missing --steps <N>")?;
+    let out = out.ok_or("Missing --out <dir>")?;
+    fdtd::run_headless(emf.as_ref(), steps, out.as_ref(), every)
+}
+
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
 fn main() {
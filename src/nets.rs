@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use egui::{Color32, ecolor::Hsva};
+
+use crate::{
+    common::IntPos3,
+    wire_editor_3d::{WireId, Wiring3D},
+};
+
+/// Groups of grid points which are electrically connected through wires,
+/// or through ports of the same name (which `NodeMap` shorts together).
+/// Lumped components separate nets.
+#[derive(Default)]
+pub struct Nets {
+    /// Net index of every grid point touched by a wire, component or port
+    pub net_of: HashMap<IntPos3, usize>,
+    /// Net names; nets touching ports are named after them, the rest are numbered
+    pub names: Vec<String>,
+    /// Port names touching each net, sorted
+    pub ports: Vec<Vec<String>>,
+}
+
+impl Nets {
+    pub fn new(wiring: &Wiring3D) -> Self {
+        let mut points: Vec<IntPos3> = wiring
+            .unit_edges()
+            .into_iter()
+            .flat_map(|((a, b), _)| [a, b])
+            .chain(wiring.components.keys().flat_map(|&(a, b)| [a, b]))
+            .chain(wiring.ports.keys().copied())
+            .collect();
+        points.sort();
+        points.dedup();
+
+        let index: HashMap<IntPos3, usize> =
+            points.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let mut sets = DisjointSet::new(points.len());
+
+        for ((a, b), _) in wiring.unit_edges() {
+            sets.union(index[&a], index[&b]);
+        }
+
+        let mut first_with_name: HashMap<&str, usize> = HashMap::new();
+        for (pos, port) in &wiring.ports {
+            let idx = index[pos];
+            let first = *first_with_name.entry(&port.0).or_insert(idx);
+            sets.union(first, idx);
+        }
+
+        // Number nets in order of their least point, so names are stable
+        let mut root_to_net = HashMap::new();
+        let mut net_of = HashMap::new();
+        for (i, pos) in points.iter().enumerate() {
+            let next = root_to_net.len();
+            let net = *root_to_net.entry(sets.find(i)).or_insert(next);
+            net_of.insert(*pos, net);
+        }
+
+        let mut ports = vec![vec![]; root_to_net.len()];
+        for (pos, port) in &wiring.ports {
+            ports[net_of[pos]].push(port.0.clone());
+        }
+        for names in &mut ports {
+            names.sort();
+            names.dedup();
+        }
+
+        let names = ports
+            .iter()
+            .enumerate()
+            .map(|(i, port_names)| {
+                if port_names.is_empty() {
+                    format!("N{i}")
+                } else {
+                    port_names.join("/")
+                }
+            })
+            .collect();
+
+        Self {
+            net_of,
+            names,
+            ports,
+        }
+    }
+
+    pub fn net_of_wire(&self, (a, _): WireId) -> Option<usize> {
+        self.net_of.get(&a).copied()
+    }
+
+    /// A distinct colour per net, spread around the hue circle by the golden ratio
+    pub fn color(&self, net: usize) -> Color32 {
+        let hue = (net as f32 * 0.618_034).fract();
+        Hsva::new(hue, 0.6, 0.9, 1.0).into()
+    }
+}
+
+struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nets(ron: &str) -> Nets {
+        Nets::new(&ron::from_str(ron).unwrap())
+    }
+
+    #[test]
+    fn wires_sharing_a_point_form_one_net() {
+        let nets = nets(
+            "(wires: {
+                ((0, 0, 0), (0, 2, 0)): (resistance: 1.0),
+                ((0, 2, 0), (2, 2, 1)): (resistance: 1.0),
+                ((3, 3, 3), (3, 3, 4)): (resistance: 1.0),
+            }, ports: {})",
+        );
+        assert_eq!(nets.names, ["N0", "N1"]);
+        assert_eq!(nets.net_of[&(0, 0, 0)], 0);
+        assert_eq!(nets.net_of[&(2, 2, 1)], 0);
+        assert_eq!(nets.net_of_wire(((3, 3, 4), (3, 3, 3))), Some(1));
+        assert_eq!(nets.net_of_wire(((5, 5, 5), (5, 5, 6))), None);
+    }
+
+    #[test]
+    fn lumped_components_separate_nets() {
+        let nets = nets(
+            "(wires: {
+                ((0, 0, 0), (0, 0, 2)): (resistance: 1.0),
+                ((0, 0, 3), (0, 0, 5)): (resistance: 1.0),
+            }, ports: {}, components: {
+                ((0, 0, 2), (0, 0, 3)): (component: Resistor(50.0)),
+            })",
+        );
+        assert_eq!(nets.names.len(), 2);
+        assert_ne!(nets.net_of[&(0, 0, 2)], nets.net_of[&(0, 0, 3)]);
+    }
+
+    #[test]
+    fn ports_of_the_same_name_join_and_name_nets() {
+        let nets = nets(
+            r#"(wires: {
+                ((0, 0, 0), (0, 0, 1)): (resistance: 1.0),
+                ((4, 0, 0), (4, 0, 1)): (resistance: 1.0),
+                ((8, 0, 0), (8, 0, 1)): (resistance: 1.0),
+            }, ports: {
+                (0, 0, 0): ("in"),
+                (4, 0, 1): ("in"),
+                (8, 0, 0): ("out"),
+                (8, 0, 1): ("gnd"),
+            })"#,
+        );
+        assert_eq!(nets.names, ["in", "gnd/out"]);
+        assert_eq!(nets.ports[1], ["gnd", "out"]);
+        assert_eq!(nets.net_of[&(4, 0, 0)], nets.net_of[&(0, 0, 1)]);
+    }
+}
@@ -12,7 +12,19 @@ use crate::{
 
 pub struct NodeMap {
     pub pos_map: HashMap<IntPos3, usize>,
+    /// Maps each unit edge to its two-terminal component index
     pub component_idx_map: HashMap<WireId, usize>,
+    /// Maps each unit edge to the wire segment it was decomposed from
+    pub edge_segment_map: HashMap<WireId, WireId>,
+    /// Maps each wire segment to its least unit edge; all of them carry the same current
+    pub segment_edge_map: HashMap<WireId, WireId>,
+    /// Maps each lumped component's edge to its two-terminal component index,
+    /// and whether it is reversed with respect to the edge
+    pub lumped_idx_map: HashMap<WireId, (usize, bool)>,
+    /// Number of schematic nodes carrying each port name, before the 3D ports were added
+    pub schematic_ports: HashMap<String, usize>,
+    /// Sorted node indices carrying each port name, from the schematic and the 3D wiring
+    pub port_nodes: HashMap<String, Vec<usize>>,
 }
 
 impl NodeMap {
@@ -31,21 +43,54 @@ impl NodeMap {
             })
         }
 
-        // Insert resistors for the wires
+        // Insert resistors for each unit edge of the wires
         let mut pos_map = HashMap::new();
         let mut component_idx_map = HashMap::new();
-        for (wire_id @ (a, b), wire) in &wiring.wires {
-            let a_idx = nodemap_insert(&mut pos_map, *a, &mut rich.primitive);
-            let b_idx = nodemap_insert(&mut pos_map, *b, &mut rich.primitive);
+        let mut edge_segment_map = HashMap::new();
+        let mut segment_edge_map = HashMap::<WireId, WireId>::new();
+        for (edge @ (a, b), segment) in wiring.unit_edges() {
+            let wire = &wiring.wires[&segment];
+            let a_idx = nodemap_insert(&mut pos_map, a, &mut rich.primitive);
+            let b_idx = nodemap_insert(&mut pos_map, b, &mut rich.primitive);
             let component = cirmcut::cirmcut_sim::TwoTerminalComponent::Resistor(wire.resistance);
             let component_idx = rich.primitive.two_terminal.len();
             rich.primitive
                 .two_terminal
                 .push(([a_idx, b_idx], component));
-            component_idx_map.insert(*wire_id, component_idx);
+            component_idx_map.insert(edge, component_idx);
+            edge_segment_map.insert(edge, segment);
+            segment_edge_map
+                .entry(segment)
+                .and_modify(|first| *first = (*first).min(edge))
+                .or_insert(edge);
+        }
+
+        // Lumped components, in a deterministic order
+        let mut lumped_idx_map = HashMap::new();
+        let mut lumped: Vec<_> = wiring.components.iter().collect();
+        lumped.sort_by_key(|(edge, _)| **edge);
+        for (edge @ (a, b), component) in lumped {
+            let a_idx = nodemap_insert(&mut pos_map, *a, &mut rich.primitive);
+            let b_idx = nodemap_insert(&mut pos_map, *b, &mut rich.primitive);
+            let terminals = if component.reversed {
+                [b_idx, a_idx]
+            } else {
+                [a_idx, b_idx]
+            };
+            let component_idx = rich.primitive.two_terminal.len();
+            rich.primitive
+                .two_terminal
+                .push((terminals, component.component));
+            lumped_idx_map.insert(*edge, (component_idx, component.reversed));
         }
 
         // Ports
+        let schematic_ports = rich
+            .ports
+            .iter()
+            .map(|(name, indices)| (name.clone(), indices.len()))
+            .collect();
+
         for (pos, port) in &wiring.ports {
             if let Some(node_idx) = pos_map.get(&pos) {
                 rich.ports
@@ -55,6 +100,17 @@ impl NodeMap {
             }
         }
 
+        let port_nodes = rich
+            .ports
+            .iter()
+            .map(|(name, indices)| {
+                let mut indices = indices.clone();
+                indices.sort();
+                indices.dedup();
+                (name.clone(), indices)
+            })
+            .collect();
+
         for (_name, port_indices) in &rich.ports {
             for i in 0..port_indices.len() {
                 for j in i + 1..port_indices.len() {
@@ -68,6 +124,22 @@ impl NodeMap {
         Self {
             pos_map,
             component_idx_map,
+            edge_segment_map,
+            segment_edge_map,
+            lumped_idx_map,
+            schematic_ports,
+            port_nodes,
         }
     }
+
+    /// Name of the net a node belongs to: the first port name carried by it, else its index
+    pub fn net_name(&self, node: usize) -> String {
+        self.port_nodes
+            .iter()
+            .filter(|(_, nodes)| nodes.binary_search(&node).is_ok())
+            .map(|(name, _)| name)
+            .min()
+            .cloned()
+            .unwrap_or_else(|| format!("n{node}"))
+    }
 }
@@ -0,0 +1,377 @@
+//! Writers for NumPy `.npy` arrays and `.npz` archives, readable with `numpy.load`.
+//!
+//! Arrays are written as little-endian `f64` in C order, so an `Array4` field keeps its
+//! `(x, y, z, component)` shape. `.npz` archives are uncompressed zip files; axis names
+//! and other metadata are stored alongside the data as small arrays of their own.
+
+use std::io::{self, Write};
+
+use ndarray::{ArrayBase, Data, Dimension};
+
+use crate::sim::{FdtdSim, FdtdSimConfig};
+
+/// Axis names of the fields, stored next to them in archives
+pub const FIELD_AXES: [&str; 4] = ["x", "y", "z", "component"];
+
+/// Writes an array as a `.npy` file
+pub fn write_array<S, D>(w: &mut impl Write, array: &ArrayBase<S, D>) -> io::Result<()>
+where
+    S: Data<Elem = f64>,
+    D: Dimension,
+{
+    write_header(w, "<f8", array.shape())?;
+    // Iteration is in logical (C) order regardless of the memory layout
+    for v in array.iter() {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a one-dimensional array, such as a time series, as a `.npy` file
+pub fn write_slice(w: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    write_header(w, "<f8", &[values.len()])?;
+    for v in values {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a one-dimensional array of strings as a `.npy` file of fixed width unicode
+pub fn write_strings(w: &mut impl Write, values: &[&str]) -> io::Result<()> {
+    let width = values
+        .iter()
+        .map(|s| s.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    write_header(w, &format!("<U{width}"), &[values.len()])?;
+    for s in values {
+        let mut n = 0;
+        for c in s.chars() {
+            w.write_all(&(c as u32).to_le_bytes())?;
+            n += 1;
+        }
+        for _ in n..width {
+            w.write_all(&0_u32.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Format version 1.0 header, padded so the data starts on a 64 byte boundary
+fn write_header(w: &mut impl Write, descr: &str, shape: &[usize]) -> io::Result<()> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => {
+            let dims: Vec<String> = shape.iter().map(|n| n.to_string()).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+
+    // Magic (6), version (2), header length (2), header and its terminating newline
+    let unpadded = 6 + 2 + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    let len = u16::try_from(header.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "npy header too long"))?;
+
+    w.write_all(b"\x93NUMPY")?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(header.as_bytes())
+}
+
+/// Builds a `.npz` archive. Each entry is loaded by `numpy.load` under its name.
+pub struct NpzWriter<W: Write> {
+    w: W,
+    /// Name, CRC-32, size and offset of each entry written so far
+    entries: Vec<(String, u32, u32, u32)>,
+    offset: u32,
+}
+
+impl<W: Write> NpzWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            w,
+            entries: vec![],
+            offset: 0,
+        }
+    }
+
+    pub fn add_array<S, D>(&mut self, name: &str, array: &ArrayBase<S, D>) -> io::Result<()>
+    where
+        S: Data<Elem = f64>,
+        D: Dimension,
+    {
+        let mut data = vec![];
+        write_array(&mut data, array)?;
+        self.add_npy(name, &data)
+    }
+
+    pub fn add_slice(&mut self, name: &str, values: &[f64]) -> io::Result<()> {
+        let mut data = vec![];
+        write_slice(&mut data, values)?;
+        self.add_npy(name, &data)
+    }
+
+    pub fn add_strings(&mut self, name: &str, values: &[&str]) -> io::Result<()> {
+        let mut data = vec![];
+        write_strings(&mut data, values)?;
+        self.add_npy(name, &data)
+    }
+
+    /// Stores an already encoded `.npy` file
+    pub fn add_npy(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "npz archive over 4 GiB");
+
+        let file_name = format!("{name}.npy");
+        let crc = crc32(data);
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+
+        let mut header = vec![];
+        header.extend(0x04034b50_u32.to_le_bytes());
+        header.extend(local_fields(crc, size, &file_name));
+        header.extend(file_name.as_bytes());
+
+        self.w.write_all(&header)?;
+        self.w.write_all(data)?;
+
+        self.entries.push((file_name, crc, size, self.offset));
+        self.offset = u32::try_from(header.len() + data.len())
+            .ok()
+            .and_then(|len| self.offset.checked_add(len))
+            .ok_or_else(too_large)?;
+        Ok(())
+    }
+
+    /// Writes the central directory, returning the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut directory = vec![];
+        for (file_name, crc, size, offset) in &self.entries {
+            directory.extend(0x02014b50_u32.to_le_bytes());
+            // Version made by
+            directory.extend(20_u16.to_le_bytes());
+            directory.extend(local_fields(*crc, *size, file_name));
+            // Comment length, disk number, internal and external attributes
+            directory.extend([0; 2 + 2 + 2 + 4]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(file_name.as_bytes());
+        }
+
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many npz entries"))?;
+
+        let mut end = vec![];
+        end.extend(0x06054b50_u32.to_le_bytes());
+        // Disk numbers
+        end.extend([0; 4]);
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend((directory.len() as u32).to_le_bytes());
+        end.extend(self.offset.to_le_bytes());
+        // Comment length
+        end.extend([0; 2]);
+
+        self.w.write_all(&directory)?;
+        self.w.write_all(&end)?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// The fields shared by local file headers and central directory entries,
+/// for an uncompressed file without extra fields
+fn local_fields(crc: u32, size: u32, file_name: &str) -> Vec<u8> {
+    let mut out = vec![];
+    // Version needed, flags, method (stored)
+    out.extend(20_u16.to_le_bytes());
+    out.extend(0_u16.to_le_bytes());
+    out.extend(0_u16.to_le_bytes());
+    // Modification time and date, 1980-01-01 00:00
+    out.extend(0_u16.to_le_bytes());
+    out.extend(0x21_u16.to_le_bytes());
+    out.extend(crc.to_le_bytes());
+    // Compressed and uncompressed sizes
+    out.extend(size.to_le_bytes());
+    out.extend(size.to_le_bytes());
+    out.extend((file_name.len() as u16).to_le_bytes());
+    // Extra field length
+    out.extend(0_u16.to_le_bytes());
+    out
+}
+
+/// CRC-32 (IEEE), as used by zip
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Writes E and H (converted out of their scaled units) with their axis names
+/// and the grid spacing, time step and step count of the simulation.
+pub fn write_fields(w: impl Write, sim: &FdtdSim, cfg: &FdtdSimConfig) -> io::Result<()> {
+    let mut npz = NpzWriter::new(w);
+    npz.add_array("E", sim.e_field())?;
+    npz.add_array("H", &(sim.h_field() * (cfg.eps / cfg.mu).sqrt()))?;
+    npz.add_strings("axes", &FIELD_AXES)?;
+    npz.add_slice("dx", &[cfg.dx])?;
+    npz.add_slice("dt", &[cfg.dt])?;
+    npz.add_slice("step", &[sim.steps() as f64])?;
+    npz.finish()?;
+    Ok(())
+}
+
+/// Writes time series sharing a time axis, one entry per trace plus `time`
+/// and `names`, listing the traces in order.
+pub fn write_traces(w: impl Write, time: &[f64], traces: &[(&str, Vec<f64>)]) -> io::Result<()> {
+    let mut npz = NpzWriter::new(w);
+    npz.add_slice("time", time)?;
+    for (name, values) in traces {
+        npz.add_slice(name, values)?;
+    }
+    let names: Vec<&str> = traces.iter().map(|(name, _)| *name).collect();
+    npz.add_strings("names", &names)?;
+    npz.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+
+    fn u16_at(b: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([b[at], b[at + 1]]) as usize
+    }
+
+    fn u32_at(b: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+    }
+
+    /// Header dict and data of a `.npy` file
+    fn parse_npy(b: &[u8]) -> (&str, &[u8]) {
+        assert_eq!(&b[..8], b"\x93NUMPY\x01\x00");
+        let len = u16_at(b, 8);
+        assert_eq!((10 + len) % 64, 0, "data is not aligned");
+        let header = std::str::from_utf8(&b[10..10 + len]).unwrap();
+        assert!(header.ends_with('\n'));
+        (header, &b[10 + len..])
+    }
+
+    /// Name and contents of each entry of a stored zip, checked against the central directory
+    fn parse_zip(b: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = b.len() - 22;
+        assert_eq!(u32_at(b, end), 0x06054b50);
+        let count = u16_at(b, end + 10);
+        let directory = u32_at(b, end + 16) as usize;
+
+        let mut entries = vec![];
+        let mut at = directory;
+        for _ in 0..count {
+            assert_eq!(u32_at(b, at), 0x02014b50);
+            let crc = u32_at(b, at + 16);
+            let size = u32_at(b, at + 20) as usize;
+            let name_len = u16_at(b, at + 28);
+            let offset = u32_at(b, at + 42) as usize;
+            let name = String::from_utf8(b[at + 46..at + 46 + name_len].to_vec()).unwrap();
+
+            assert_eq!(u32_at(b, offset), 0x04034b50);
+            let data_start = offset + 30 + u16_at(b, offset + 26) + u16_at(b, offset + 28);
+            let data = b[data_start..data_start + size].to_vec();
+            assert_eq!(crc32(&data), crc, "{name}");
+
+            entries.push((name, data));
+            at += 46 + name_len;
+        }
+        assert_eq!(at, end);
+        entries
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn array_is_written_in_c_order() {
+        let array = Array2::from_shape_fn((2, 3), |(i, j)| (3 * i + j) as f64);
+        let mut bytes = vec![];
+        write_array(&mut bytes, &array.t()).unwrap();
+
+        let (header, data) = parse_npy(&bytes);
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (3, 2), }"));
+        let values: Vec<f64> = data
+            .chunks(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn one_dimensional_shape_has_a_trailing_comma() {
+        let mut bytes = vec![];
+        write_slice(&mut bytes, &[1.0, 2.0]).unwrap();
+        let (header, data) = parse_npy(&bytes);
+        assert!(header.contains("'shape': (2,)"));
+        assert_eq!(data.len(), 16);
+    }
+
+    #[test]
+    fn strings_are_padded_to_the_longest() {
+        let mut bytes = vec![];
+        write_strings(&mut bytes, &["ab", "µ", "xyz"]).unwrap();
+        let (header, data) = parse_npy(&bytes);
+        assert!(header.contains("'descr': '<U3'"));
+        assert_eq!(data.len(), 3 * 3 * 4);
+        assert_eq!(u32_at(data, 12), 'µ' as u32);
+        assert_eq!(u32_at(data, 16), 0);
+    }
+
+    #[test]
+    fn npz_entries_round_trip() {
+        let mut bytes = vec![];
+        write_traces(
+            &mut bytes,
+            &[0.0, 0.5],
+            &[("a", vec![1.0, 2.0]), ("b", vec![3.0, 4.0])],
+        )
+        .unwrap();
+
+        let entries = parse_zip(&bytes);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["time.npy", "a.npy", "b.npy", "names.npy"]);
+
+        let (_, b) = &entries[2];
+        let (_, data) = parse_npy(b);
+        assert_eq!(f64::from_le_bytes(data[8..16].try_into().unwrap()), 4.0);
+    }
+
+    #[test]
+    fn fields_archive_holds_e_h_and_metadata() {
+        let sim = FdtdSim::new(2);
+        let mut bytes = vec![];
+        write_fields(&mut bytes, &sim, &FdtdSimConfig::default()).unwrap();
+
+        let entries = parse_zip(&bytes);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["E.npy", "H.npy", "axes.npy", "dx.npy", "dt.npy", "step.npy"]
+        );
+        let (header, _) = parse_npy(&entries[0].1);
+        assert!(header.contains("'shape': (2, 2, 2, 3)"));
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::VecDeque;
+
+use egui::{Color32, DragValue, Stroke, Ui};
+use ndarray::Array4;
+use rand::Rng;
+use threegui::{Painter3D, Vec3};
+
+use crate::{
+    common::{espace, interp},
+    sim::{FdtdSim, FdtdSimConfig},
+};
+
+/// A point charge moving through the grid
+#[derive(Clone)]
+pub struct Particle {
+    /// Normalized, -1 for an electron
+    pub charge: f32,
+    /// Normalized, 1 for an electron
+    pub mass: f32,
+    /// Grid coordinates
+    pub pos: Vec3,
+    /// Meters per second
+    pub vel: Vec3,
+    /// Past positions, most recent last
+    trail: VecDeque<Vec3>,
+}
+
+/// Charged particles pushed by the Lorentz force of the FDTD fields
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(from = "Vec<ParticleRecord>", into = "Vec<ParticleRecord>")]
+pub struct Particles {
+    pub particles: Vec<Particle>,
+}
+
+/// Serialized form of a `Particle`
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ParticleRecord {
+    charge: f32,
+    mass: f32,
+    pos: [f32; 3],
+    vel: [f32; 3],
+    trail: Vec<[f32; 3]>,
+}
+
+impl From<Vec<ParticleRecord>> for Particles {
+    fn from(records: Vec<ParticleRecord>) -> Self {
+        let particles = records
+            .into_iter()
+            .map(|r| Particle {
+                charge: r.charge,
+                mass: r.mass,
+                pos: Vec3::from(r.pos),
+                vel: Vec3::from(r.vel),
+                trail: r.trail.into_iter().map(Vec3::from).collect(),
+            })
+            .collect();
+        Self { particles }
+    }
+}
+
+impl From<Particles> for Vec<ParticleRecord> {
+    fn from(particles: Particles) -> Self {
+        particles
+            .particles
+            .into_iter()
+            .map(|p| ParticleRecord {
+                charge: p.charge,
+                mass: p.mass,
+                pos: p.pos.to_array(),
+                vel: p.vel.to_array(),
+                trail: p.trail.iter().map(|t| t.to_array()).collect(),
+            })
+            .collect()
+    }
+}
+
+/// Settings for injecting particles and how they are simulated
+pub struct ParticleSettings {
+    pub charge: f32,
+    pub mass: f32,
+    /// Grid coordinates
+    pub position: Vec3,
+    /// Meters per second
+    pub velocity: Vec3,
+    pub count: usize,
+    /// Particles are placed randomly within this many cells of `position`
+    pub spread: f32,
+    /// Past positions kept per particle
+    pub trail_len: usize,
+    /// Add the particles' current to the FDTD source term each step
+    pub deposit_current: bool,
+}
+
+/// Normalized charge and mass presets. Physical values barely deflect at typical grid settings.
+const PRESETS: [(&str, f32, f32); 3] = [
+    ("Electron", -1.0, 1.0),
+    ("Positron", 1.0, 1.0),
+    ("Ion", 1.0, 1836.0),
+];
+
+impl ParticleSettings {
+    pub fn new(width: usize) -> Self {
+        Self {
+            charge: -1.0,
+            mass: 1.0,
+            position: Vec3::new(1.0, width as f32 / 2.0, width as f32 / 2.0),
+            velocity: Vec3::new(1.0, 0.0, 0.0),
+            count: 10,
+            spread: 0.5,
+            trail_len: 200,
+            deposit_current: false,
+        }
+    }
+
+    /// Returns true if particles should be injected
+    pub fn show_ui(&mut self, ui: &mut Ui, width: usize) -> bool {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Preset: ");
+            for (name, charge, mass) in PRESETS {
+                if ui.button(name).clicked() {
+                    self.charge = charge;
+                    self.mass = mass;
+                }
+            }
+        });
+        let units = "Normalized units, in which an electron has charge -1 and mass 1";
+        ui.add(
+            DragValue::new(&mut self.charge)
+                .prefix("Charge: ")
+                .speed(1e-2),
+        )
+        .on_hover_text(units);
+        ui.add(
+            DragValue::new(&mut self.mass)
+                .range(1e-30..=f32::MAX)
+                .prefix("Mass: ")
+                .speed(1e-2),
+        )
+        .on_hover_text(units);
+        ui.weak("Charge and mass are normalized (electron: -1, 1)");
+
+        let max = width.saturating_sub(1) as f32;
+        ui.horizontal(|ui| {
+            ui.label("Position: ");
+            for (c, name) in ["x: ", "y: ", "z: "].into_iter().enumerate() {
+                ui.add(
+                    DragValue::new(&mut self.position[c])
+                        .range(0.0..=max)
+                        .prefix(name)
+                        .speed(0.1),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Velocity: ");
+            for (c, name) in ["x: ", "y: ", "z: "].into_iter().enumerate() {
+                ui.add(
+                    DragValue::new(&mut self.velocity[c])
+                        .prefix(name)
+                        .speed(1e-2),
+                );
+            }
+            ui.label("m/s");
+        });
+        ui.add(
+            DragValue::new(&mut self.count)
+                .range(1..=10_000)
+                .prefix("Count: "),
+        );
+        ui.add(
+            DragValue::new(&mut self.spread)
+                .range(0.0..=max)
+                .prefix("Spread: ")
+                .suffix(" cells")
+                .speed(0.05),
+        );
+
+        let inject = ui.button("Inject").clicked();
+
+        ui.separator();
+        ui.add(
+            DragValue::new(&mut self.trail_len)
+                .range(0..=100_000)
+                .prefix("Trail length: "),
+        );
+        ui.checkbox(&mut self.deposit_current, "Deposit current into the grid");
+
+        inject
+    }
+}
+
+impl Particles {
+    pub fn inject(&mut self, settings: &ParticleSettings) {
+        let mut rng = rand::thread_rng();
+        let s = settings.spread;
+        for _ in 0..settings.count {
+            let jitter = if s > 0.0 {
+                Vec3::new(
+                    rng.gen_range(-s..=s),
+                    rng.gen_range(-s..=s),
+                    rng.gen_range(-s..=s),
+                )
+            } else {
+                Vec3::ZERO
+            };
+            self.particles.push(Particle {
+                charge: settings.charge,
+                mass: settings.mass,
+                pos: settings.position + jitter,
+                vel: settings.velocity,
+                trail: VecDeque::new(),
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Advances every particle by one FDTD time step with the Boris pusher,
+    /// and removes those which leave the grid.
+    ///
+    /// The update is symmetric in E and H, meaning H is stored scaled by sqrt(mu / eps),
+    /// so the magnetic flux density is B = sqrt(mu * eps) * H.
+    pub fn step(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, trail_len: usize) {
+        let dt = cfg.dt as f32;
+        let dx = cfg.dx as f32;
+        let b_scale = (cfg.mu * cfg.eps).sqrt() as f32;
+        let max = sim.width() as f32 - 1.0;
+
+        for particle in &mut self.particles {
+            let e = interp(sim.e_field(), particle.pos);
+            let b = interp(sim.h_field(), particle.pos) * b_scale;
+
+            let q_m = particle.charge / particle.mass;
+
+            // Half electric kick, magnetic rotation, then the other half kick
+            let v_minus = particle.vel + e * (q_m * dt / 2.0);
+            let t = b * (q_m * dt / 2.0);
+            let s = 2.0 * t / (1.0 + t.length_squared());
+            let v_prime = v_minus + v_minus.cross(t);
+            let v_plus = v_minus + v_prime.cross(s);
+            particle.vel = v_plus + e * (q_m * dt / 2.0);
+
+            particle.trail.push_back(particle.pos);
+            while particle.trail.len() > trail_len {
+                particle.trail.pop_front();
+            }
+
+            particle.pos += particle.vel * (dt / dx);
+        }
+
+        self.particles.retain(|p| on_grid(p.pos, max));
+    }
+
+    /// Adds the particles' current to a source array like the one built from the wires,
+    /// holding amps along each axis at each grid point. Each particle's q * v / dx is
+    /// shared between its eight surrounding grid points by trilinear weights.
+    /// Particles off the grid deposit nothing.
+    pub fn deposit_current(&self, cfg: &FdtdSimConfig, current: &mut Array4<f64>) {
+        let (width, _, _, _) = current.dim();
+        let max = width as f32 - 1.0;
+        for particle in &self.particles {
+            // Off-grid coordinates would saturate to the boundary when cast
+            if !on_grid(particle.pos, max) {
+                continue;
+            }
+
+            let base = particle.pos.floor();
+            let frac = particle.pos - base;
+            let element = particle.vel * (particle.charge / cfg.dx as f32);
+
+            for corner in 0..8 {
+                let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+                let mut weight = 1.0;
+                let mut idx = [0; 3];
+                for c in 0..3 {
+                    weight *= if offset[c] == 1 {
+                        frac[c]
+                    } else {
+                        1.0 - frac[c]
+                    };
+                    idx[c] = base[c] as usize + offset[c];
+                }
+                if idx.iter().any(|&i| i >= width) {
+                    continue;
+                }
+                for axis in 0..3 {
+                    current[(idx[0], idx[1], idx[2], axis)] += (weight * element[axis]) as f64;
+                }
+            }
+        }
+    }
+
+    /// Fading trails, with the particles coloured by the sign of their charge
+    pub fn draw(&self, paint: &Painter3D, width: usize) {
+        for particle in &self.particles {
+            let color = if particle.charge < 0.0 {
+                Color32::from_rgb(80, 200, 255)
+            } else {
+                Color32::from_rgb(255, 160, 60)
+            };
+
+            let n = particle.trail.len();
+            let points = particle.trail.iter().chain(std::iter::once(&particle.pos));
+            for (i, (a, b)) in points.clone().zip(points.skip(1)).enumerate() {
+                let alpha = (i + 1) as f32 / n as f32;
+                paint.line(
+                    espace(width, *a),
+                    espace(width, *b),
+                    Stroke::new(1.0, color.gamma_multiply(alpha)),
+                );
+            }
+            paint.circle_filled(espace(width, particle.pos), 3.0, color);
+        }
+    }
+}
+
+/// Whether each coordinate lies within 0..=max
+fn on_grid(pos: Vec3, max: f32) -> bool {
+    pos.to_array().into_iter().all(|x| (0.0..=max).contains(&x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(pos: Vec3, vel: Vec3) -> Particle {
+        Particle {
+            charge: 1.0,
+            mass: 2.0,
+            pos,
+            vel,
+            trail: VecDeque::new(),
+        }
+    }
+
+    /// A grid with uniform E and B
+    fn uniform_sim(width: usize, cfg: &FdtdSimConfig, e: Vec3, b: Vec3) -> FdtdSim {
+        let mut sim = FdtdSim::new(width);
+        let h = b / (cfg.mu * cfg.eps).sqrt() as f32;
+        for axis in 0..3 {
+            sim.e_field
+                .slice_mut(ndarray::s![.., .., .., axis])
+                .fill(e[axis] as f64);
+            sim.h_field
+                .slice_mut(ndarray::s![.., .., .., axis])
+                .fill(h[axis] as f64);
+        }
+        sim
+    }
+
+    #[test]
+    fn electric_field_accelerates_along_it() {
+        let cfg = FdtdSimConfig::default();
+        let sim = uniform_sim(8, &cfg, Vec3::new(0.0, 3.0, 0.0), Vec3::ZERO);
+        let mut particles = Particles {
+            particles: vec![particle(Vec3::splat(4.0), Vec3::ZERO)],
+        };
+
+        for _ in 0..10 {
+            particles.step(&sim, &cfg, 0);
+        }
+
+        // q / m * E * t
+        let expected = 0.5 * 3.0 * 10.0 * cfg.dt as f32;
+        let vel = particles.particles[0].vel;
+        assert!((vel.y - expected).abs() < 1e-5 && vel.x == 0.0 && vel.z == 0.0);
+    }
+
+    #[test]
+    fn magnetic_field_rotates_without_changing_speed() {
+        let cfg = FdtdSimConfig::default();
+        let b = 20.0;
+        let sim = uniform_sim(16, &cfg, Vec3::ZERO, Vec3::new(0.0, 0.0, b));
+        let mut particles = Particles {
+            particles: vec![particle(Vec3::splat(8.0), Vec3::new(1.0, 0.0, 0.0))],
+        };
+
+        let steps = 50;
+        for _ in 0..steps {
+            particles.step(&sim, &cfg, 0);
+        }
+
+        let vel = particles.particles[0].vel;
+        assert!((vel.length() - 1.0).abs() < 1e-4);
+        assert_eq!(vel.z, 0.0);
+
+        // The Boris rotation turns by 2 atan(ω dt / 2) per step, clockwise for positive charge
+        let omega_dt = 0.5 * b * cfg.dt as f32;
+        let angle = steps as f32 * 2.0 * (omega_dt / 2.0).atan();
+        assert!((vel.y.atan2(vel.x) + angle).abs() < 1e-3);
+    }
+
+    #[test]
+    fn particles_leaving_the_grid_are_removed() {
+        let cfg = FdtdSimConfig::default();
+        let sim = FdtdSim::new(4);
+        let mut particles = Particles {
+            particles: vec![
+                particle(Vec3::new(2.999, 1.0, 1.0), Vec3::new(10.0, 0.0, 0.0)),
+                particle(Vec3::splat(1.5), Vec3::ZERO),
+            ],
+        };
+
+        particles.step(&sim, &cfg, 0);
+        assert_eq!(particles.particles.len(), 1);
+        assert_eq!(particles.particles[0].pos, Vec3::splat(1.5));
+    }
+
+    #[test]
+    fn deposited_current_sums_to_q_v_over_dx() {
+        let cfg = FdtdSimConfig {
+            dx: 0.5,
+            ..Default::default()
+        };
+        let particles = Particles {
+            particles: vec![particle(
+                Vec3::new(1.25, 2.5, 1.75),
+                Vec3::new(1.0, -2.0, 0.5),
+            )],
+        };
+        let mut current = Array4::zeros((4, 4, 4, 3));
+        particles.deposit_current(&cfg, &mut current);
+
+        for (axis, v) in [1.0, -2.0, 0.5].into_iter().enumerate() {
+            let total: f64 = current.slice(ndarray::s![.., .., .., axis]).sum();
+            assert!((total - v / cfg.dx).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn particles_off_the_grid_deposit_nothing() {
+        let cfg = FdtdSimConfig::default();
+        let particles = Particles {
+            particles: vec![
+                particle(Vec3::new(-0.5, 1.0, 1.0), Vec3::X),
+                particle(Vec3::new(1.0, 3.5, 1.0), Vec3::Y),
+                particle(Vec3::new(1.0, 1.0, f32::NAN), Vec3::Z),
+            ],
+        };
+        let mut current = Array4::zeros((4, 4, 4, 3));
+        particles.deposit_current(&cfg, &mut current);
+        assert!(current.iter().all(|&c| c == 0.0));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut p = particle(Vec3::new(1.0, 2.0, 3.0), Vec3::new(-1.0, 0.5, 0.0));
+        p.trail.extend([Vec3::ONE, Vec3::splat(2.0)]);
+        let particles = Particles { particles: vec![p] };
+
+        let text = ron::to_string(&particles).unwrap();
+        let back: Particles = ron::from_str(&text).unwrap();
+        let (a, b) = (&particles.particles[0], &back.particles[0]);
+        assert_eq!(
+            (a.charge, a.mass, a.pos, a.vel),
+            (b.charge, b.mass, b.pos, b.vel)
+        );
+        assert_eq!(a.trail, b.trail);
+    }
+}
@@ -0,0 +1,373 @@
+//! Cursor picking which avoids projecting the whole grid every frame.
+//!
+//! The camera is only available as a world-to-screen projection, so the cursor ray is
+//! recovered by inverting that projection on two planes of the grid. Each plane maps to
+//! the screen by a homography, which any four projected points on it are enough to invert.
+
+use std::collections::{HashMap, HashSet};
+
+use egui::Pos2;
+use threegui::{Painter3D, Vec3};
+
+use crate::{
+    common::{IntPos3, espace, espacet},
+    wire_editor_3d::{WireId, Wiring3D, WorkingPlane, segment_edges},
+};
+
+/// Side length of the cells the wire index buckets wires into
+const BUCKET_SIZE: usize = 4;
+
+/// Points per side of the lattice searched for reference points on a plane
+const PLANE_SAMPLES: usize = 9;
+
+/// Maps screen positions onto the plane of grid points whose coordinate along `axis` is `index`
+struct PlaneUnprojection {
+    axis: usize,
+    index: f32,
+    /// Screen (x, y, 1) to homogeneous in-plane (u, v, w)
+    h: [[f64; 3]; 3],
+}
+
+impl PlaneUnprojection {
+    /// Fails if no four points of the plane in front of the camera span it, e.g. when
+    /// the plane is behind the camera or seen edge-on
+    fn new(width: usize, paint: &Painter3D, axis: usize, index: f32) -> Option<Self> {
+        let last = width.checked_sub(1)? as f32;
+        let step = last / (PLANE_SAMPLES - 1) as f32;
+
+        // The corners if they are visible, otherwise the outermost visible lattice points,
+        // as happens when the camera is inside the grid
+        let visible: Vec<((f32, f32), Pos2)> = (0..PLANE_SAMPLES * PLANE_SAMPLES)
+            .filter_map(|i| {
+                let u = (i % PLANE_SAMPLES) as f32 * step;
+                let v = (i / PLANE_SAMPLES) as f32 * step;
+                let screen = paint.transform(espace(width, plane_point(axis, index, u, v)))?;
+                Some(((u, v), screen))
+            })
+            .collect();
+        let extreme = |key: fn(f32, f32) -> f32| {
+            visible
+                .iter()
+                .copied()
+                .max_by(|((a, b), _), ((c, d), _)| key(*a, *b).total_cmp(&key(*c, *d)))
+        };
+        let references = [
+            extreme(|u, v| -u - v)?,
+            extreme(|u, v| u - v)?,
+            extreme(|u, v| u + v)?,
+            extreme(|u, v| v - u)?,
+        ];
+
+        // Solve for the eight unknowns of the screen-to-plane homography
+        let mut a = [[0.0; 9]; 8];
+        for (i, &((u, v), screen)) in references.iter().enumerate() {
+            let (x, y, u, v) = (screen.x as f64, screen.y as f64, u as f64, v as f64);
+            a[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u, u];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v, v];
+        }
+        let s = solve8(a)?;
+
+        Some(Self {
+            axis,
+            index,
+            h: [[s[0], s[1], s[2]], [s[3], s[4], s[5]], [s[6], s[7], 1.0]],
+        })
+    }
+
+    /// Grid-space point on the plane under the given screen position
+    fn unproject(&self, screen: Pos2) -> Option<Vec3> {
+        let p = [screen.x as f64, screen.y as f64, 1.0];
+        let [u, v, w] = self
+            .h
+            .map(|row| row[0] * p[0] + row[1] * p[1] + row[2] * p[2]);
+        if w.abs() < 1e-12 {
+            return None;
+        }
+        Some(plane_point(
+            self.axis,
+            self.index,
+            (u / w) as f32,
+            (v / w) as f32,
+        ))
+    }
+}
+
+/// Grid-space point at in-plane coordinates (u, v), using the same axis order as `WorkingPlane`
+fn plane_point(axis: usize, index: f32, u: f32, v: f32) -> Vec3 {
+    let mut p = Vec3::ZERO;
+    p[axis] = index;
+    p[(axis + 1) % 3] = u;
+    p[(axis + 2) % 3] = v;
+    p
+}
+
+/// Gaussian elimination with partial pivoting on an augmented 8x9 system
+fn solve8(mut a: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        let pivot_row = a[col];
+        for (i, row) in a.iter_mut().enumerate() {
+            if i != col {
+                let f = row[col] / pivot_row[col];
+                for (x, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *x -= f * p;
+                }
+            }
+        }
+    }
+    Some(std::array::from_fn(|i| a[i][8] / a[i][i]))
+}
+
+/// Area of the grid's face at index 0 along `axis`, on screen
+fn projected_face_area(width: usize, paint: &Painter3D, axis: usize) -> f32 {
+    let last = width.saturating_sub(1) as f32;
+    let corners = [(0.0, 0.0), (last, 0.0), (last, last), (0.0, last)];
+    let Some(pts) = corners
+        .iter()
+        .map(|&(u, v)| paint.transform(espace(width, plane_point(axis, 0.0, u, v))))
+        .collect::<Option<Vec<Pos2>>>()
+    else {
+        return 0.0;
+    };
+
+    // Shoelace formula
+    let mut area = 0.0;
+    for i in 0..4 {
+        let (a, b) = (pts[i], pts[(i + 1) % 4]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area.abs() / 2.0
+}
+
+/// Grid points near the ray under the cursor. The ray is walked one slice at a time
+/// along the axis it advances fastest in, so it crosses at most about one cell per
+/// slice along the other two axes.
+/// Returns `None` if the ray could not be recovered, e.g. when the grid is out of view.
+fn ray_candidates(width: usize, paint: &Painter3D, cursor: Pos2) -> Option<Vec<IntPos3>> {
+    if width < 2 {
+        return None;
+    }
+
+    // Faces seen most directly give the best conditioned homographies
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|&a, &b| {
+        projected_face_area(width, paint, b).total_cmp(&projected_face_area(width, paint, a))
+    });
+
+    let last = (width - 1) as f32;
+    let (near, far) = axes.iter().find_map(|&axis| {
+        let near = PlaneUnprojection::new(width, paint, axis, 0.0)?.unproject(cursor)?;
+        let far = PlaneUnprojection::new(width, paint, axis, last)?.unproject(cursor)?;
+        Some((near, far))
+    })?;
+    let dir = far - near;
+    let walk = (0..3).max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))?;
+    let (u_axis, v_axis) = ((walk + 1) % 3, (walk + 2) % 3);
+
+    let mut candidates = vec![];
+    for slice in 0..width {
+        let p = near + dir * ((slice as f32 - near[walk]) / dir[walk]);
+        let (u, v) = (p[u_axis].round() as isize, p[v_axis].round() as isize);
+        let plane = WorkingPlane {
+            axis: walk,
+            index: slice,
+        };
+        candidates.extend(plane_neighbourhood(width, plane, u, v));
+    }
+
+    Some(candidates)
+}
+
+/// Points of the plane within one cell of in-plane coordinates (u, v)
+fn plane_neighbourhood(width: usize, plane: WorkingPlane, u: isize, v: isize) -> Vec<IntPos3> {
+    let mut points = vec![];
+    for du in -1..=1 {
+        for dv in -1..=1 {
+            let (u, v) = (u + du, v + dv);
+            if u < 0 || v < 0 || u as usize >= width || v as usize >= width {
+                continue;
+            }
+            points.push(plane.point(u as usize, v as usize));
+        }
+    }
+    points
+}
+
+/// Points of the working plane within one cell of the given point on it
+pub fn plane_candidates_around(width: usize, plane: WorkingPlane, pos: IntPos3) -> Vec<IntPos3> {
+    let pos = [pos.0, pos.1, pos.2];
+    let (u, v) = (pos[(plane.axis + 1) % 3], pos[(plane.axis + 2) % 3]);
+    plane_neighbourhood(width, plane, u as isize, v as isize)
+}
+
+/// Candidate points in the working plane around the cursor
+fn plane_candidates(
+    width: usize,
+    paint: &Painter3D,
+    cursor: Pos2,
+    plane: WorkingPlane,
+) -> Option<Vec<IntPos3>> {
+    let p =
+        PlaneUnprojection::new(width, paint, plane.axis, plane.index as f32)?.unproject(cursor)?;
+    let (u_axis, v_axis) = ((plane.axis + 1) % 3, (plane.axis + 2) % 3);
+    let clamp = |c: f32| c.round().clamp(0.0, width.saturating_sub(1) as f32) as isize;
+    let (u, v) = (clamp(p[u_axis]), clamp(p[v_axis]));
+    Some(plane_neighbourhood(width, plane, u, v))
+}
+
+/// Grid points worth testing against the cursor.
+/// `None` means the cursor ray, or the working plane under it, could not be recovered.
+pub fn pick_candidates(
+    width: usize,
+    paint: &Painter3D,
+    cursor: Pos2,
+    plane: Option<WorkingPlane>,
+) -> Option<Vec<IntPos3>> {
+    match plane {
+        Some(plane) => plane_candidates(width, paint, cursor, plane),
+        None => ray_candidates(width, paint, cursor),
+    }
+}
+
+/// Closest of the candidates to the cursor, on screen
+pub fn closest_candidate(
+    width: usize,
+    paint: &Painter3D,
+    cursor: Pos2,
+    candidates: &[IntPos3],
+) -> Option<(IntPos3, f32)> {
+    candidates
+        .iter()
+        .filter_map(|&pos| {
+            let screen = paint.transform(espacet(width, pos))?;
+            Some((pos, screen.distance(cursor)))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Wires and lumped components bucketed by the grid cells their unit edges touch
+#[derive(Default)]
+pub struct WireIndex {
+    buckets: HashMap<IntPos3, Vec<WireId>>,
+}
+
+impl WireIndex {
+    pub fn new(wiring: &Wiring3D) -> Self {
+        let mut buckets: HashMap<IntPos3, Vec<WireId>> = HashMap::new();
+        for &wire_id in wiring.wires.keys().chain(wiring.components.keys()) {
+            let keys: HashSet<IntPos3> = segment_edges(wire_id)
+                .into_iter()
+                .flat_map(|(a, b)| [bucket(a), bucket(b)])
+                .collect();
+            for key in keys {
+                buckets.entry(key).or_default().push(wire_id);
+            }
+        }
+        Self { buckets }
+    }
+
+    /// Wires sharing a bucket with any of the given points
+    pub fn near(&self, points: &[IntPos3]) -> HashSet<WireId> {
+        let keys: HashSet<IntPos3> = points.iter().map(|&p| bucket(p)).collect();
+        keys.iter()
+            .filter_map(|key| self.buckets.get(key))
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+fn bucket((x, y, z): IntPos3) -> IntPos3 {
+    (x / BUCKET_SIZE, y / BUCKET_SIZE, z / BUCKET_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire_editor_3d::Wire;
+
+    /// The system `PlaneUnprojection::new` solves, for screen corners mapped to plane corners
+    fn homography_system(pairs: [((f64, f64), (f64, f64)); 4]) -> [[f64; 9]; 8] {
+        let mut a = [[0.0; 9]; 8];
+        for (i, ((x, y), (u, v))) in pairs.into_iter().enumerate() {
+            a[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u, u];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v, v];
+        }
+        a
+    }
+
+    fn apply(s: &[f64; 8], (x, y): (f64, f64)) -> (f64, f64) {
+        let w = s[6] * x + s[7] * y + 1.0;
+        (
+            (s[0] * x + s[1] * y + s[2]) / w,
+            (s[3] * x + s[4] * y + s[5]) / w,
+        )
+    }
+
+    #[test]
+    fn solve8_recovers_an_affine_map() {
+        // u = 2x + 1, v = 3y - 2
+        let screen = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let pairs = screen.map(|(x, y)| ((x, y), (2.0 * x + 1.0, 3.0 * y - 2.0)));
+        let s = solve8(homography_system(pairs)).unwrap();
+
+        let expected = [2.0, 0.0, 1.0, 0.0, 3.0, -2.0, 0.0, 0.0];
+        for (got, want) in s.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-9, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn solve8_maps_perspective_corners_exactly() {
+        // A trapezoid on screen, as a receding plane projects to
+        let pairs = [
+            ((100.0, 400.0), (0.0, 0.0)),
+            ((500.0, 400.0), (9.0, 0.0)),
+            ((400.0, 100.0), (9.0, 9.0)),
+            ((200.0, 100.0), (0.0, 9.0)),
+        ];
+        let s = solve8(homography_system(pairs)).unwrap();
+        for (screen, (u, v)) in pairs {
+            let (pu, pv) = apply(&s, screen);
+            assert!((pu - u).abs() < 1e-6 && (pv - v).abs() < 1e-6);
+        }
+
+        // The near half of the plane covers more of the screen
+        let (_, v) = apply(&s, (300.0, 250.0));
+        assert!(v < 4.5);
+    }
+
+    #[test]
+    fn solve8_rejects_degenerate_corners() {
+        // All four corners on one line, as for a plane seen edge-on
+        let pairs = [
+            ((0.0, 0.0), (0.0, 0.0)),
+            ((1.0, 0.0), (1.0, 0.0)),
+            ((2.0, 0.0), (1.0, 1.0)),
+            ((3.0, 0.0), (0.0, 1.0)),
+        ];
+        assert!(solve8(homography_system(pairs)).is_none());
+    }
+
+    #[test]
+    fn wire_index_finds_wires_in_nearby_buckets() {
+        let wire = Wire {
+            resistance: 1.0,
+            radius: None,
+        };
+        let mut wiring = Wiring3D::default();
+        let short = ((1, 1, 1), (2, 1, 1));
+        let long = ((0, 10, 0), (9, 10, 0));
+        wiring.insert(short, wire);
+        wiring.insert(long, wire);
+        let index = WireIndex::new(&wiring);
+
+        assert_eq!(index.near(&[(3, 3, 3)]), HashSet::from([short]));
+        assert_eq!(index.near(&[(6, 9, 1)]), HashSet::from([long]));
+        assert!(index.near(&[(20, 20, 20)]).is_empty());
+    }
+}
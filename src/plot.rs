@@ -0,0 +1,103 @@
+use egui::{Color32, Pos2, Sense, Shape, Stroke, Ui, Vec2};
+
+/// A named line on a plot, sampled at evenly spaced x
+pub struct Series<'a> {
+    pub name: &'a str,
+    pub color: Color32,
+    pub values: Vec<f64>,
+}
+
+/// Line plot of several series sharing an automatically fitted y axis,
+/// with a legend, the y range labelled and the values under the cursor shown.
+/// `x_label` maps a sample index to the text shown for it when hovered.
+pub fn line_plot(
+    ui: &mut Ui,
+    height: f32,
+    series: &[Series<'_>],
+    x_label: impl Fn(usize) -> String,
+) {
+    ui.horizontal_wrapped(|ui| {
+        for s in series {
+            ui.colored_label(s.color, format!("━ {}", s.name));
+        }
+    });
+
+    let (rect, resp) =
+        ui.allocate_exact_size(Vec2::new(ui.available_width(), height), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    let n = series.iter().map(|s| s.values.len()).max().unwrap_or(0);
+    let finite = series
+        .iter()
+        .flat_map(|s| s.values.iter().copied())
+        .filter(|v| v.is_finite());
+    let (mut lo, mut hi) = finite.fold((0.0_f64, 0.0_f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if hi - lo < 1e-300 {
+        lo -= 1.0;
+        hi += 1.0;
+    }
+    if n < 2 {
+        return;
+    }
+
+    let to_screen = |i: usize, v: f64| {
+        let x = rect.left() + rect.width() * i as f32 / (n - 1) as f32;
+        let y = rect.bottom() - rect.height() * ((v - lo) / (hi - lo)) as f32;
+        Pos2::new(x, y)
+    };
+
+    // Zero line
+    let zero = to_screen(0, 0.0).y;
+    painter.hline(
+        rect.x_range(),
+        zero,
+        Stroke::new(1.0, Color32::from_gray(60)),
+    );
+
+    for s in series {
+        let points: Vec<Pos2> = s
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_finite())
+            .map(|(i, &v)| to_screen(i, v))
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, s.color)));
+    }
+
+    let font = egui::FontId::monospace(10.0);
+    let text_color = Color32::LIGHT_GRAY;
+    painter.text(
+        rect.left_top() + Vec2::new(2.0, 2.0),
+        egui::Align2::LEFT_TOP,
+        format!("{hi:.3e}"),
+        font.clone(),
+        text_color,
+    );
+    painter.text(
+        rect.left_bottom() + Vec2::new(2.0, -2.0),
+        egui::Align2::LEFT_BOTTOM,
+        format!("{lo:.3e}"),
+        font,
+        text_color,
+    );
+
+    if let Some(pos) = resp.hover_pos() {
+        let i = (((pos.x - rect.left()) / rect.width()) * (n - 1) as f32).round() as usize;
+        let i = i.min(n - 1);
+        painter.vline(
+            to_screen(i, 0.0).x,
+            rect.y_range(),
+            Stroke::new(1.0, Color32::from_gray(100)),
+        );
+
+        let mut text = x_label(i);
+        for s in series {
+            if let Some(v) = s.values.get(i) {
+                text += &format!("\n{}: {v:.4e}", s.name);
+            }
+        }
+        resp.on_hover_text_at_pointer(text);
+    }
+}
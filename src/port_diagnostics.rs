@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+
+use crate::{common::IntPos3, node_map::NodeMap, wire_editor_3d::Wiring3D};
+
+/// Everything known about one port name, across the schematic and the 3D wiring.
+pub struct PortDiagnostic {
+    pub name: String,
+    /// Schematic nodes carrying this port name
+    pub schematic_nodes: usize,
+    /// 3D grid points carrying this port name, in sorted order
+    pub fdtd_positions: Vec<IntPos3>,
+    /// How many of `fdtd_positions` lie on a wire and are therefore connected
+    pub fdtd_connected: usize,
+    pub warnings: Vec<String>,
+}
+
+impl PortDiagnostic {
+    /// Total number of circuit nodes shorted together by this port
+    pub fn connected_nodes(&self) -> usize {
+        self.schematic_nodes + self.fdtd_connected
+    }
+
+    /// Collects diagnostics for every port name on either side, sorted by name.
+    pub fn collect(nodemap: &NodeMap, wiring: &Wiring3D) -> Vec<Self> {
+        let schematic_names: BTreeSet<&str> =
+            nodemap.schematic_ports.keys().map(|s| s.as_str()).collect();
+        let fdtd_names: BTreeSet<&str> = wiring.ports.values().map(|p| p.0.as_str()).collect();
+
+        schematic_names
+            .union(&fdtd_names)
+            .map(|&name| {
+                let schematic_nodes = nodemap.schematic_ports.get(name).copied().unwrap_or(0);
+
+                let mut fdtd_positions: Vec<IntPos3> = wiring
+                    .ports
+                    .iter()
+                    .filter(|(_, port)| port.0 == name)
+                    .map(|(pos, _)| *pos)
+                    .collect();
+                fdtd_positions.sort();
+
+                let fdtd_connected = fdtd_positions
+                    .iter()
+                    .filter(|pos| nodemap.pos_map.contains_key(pos))
+                    .count();
+
+                let mut warnings = vec![];
+
+                if name.trim() != name {
+                    warnings.push("Name has leading or trailing whitespace".to_string());
+                }
+
+                for pos in &fdtd_positions {
+                    if !nodemap.pos_map.contains_key(pos) {
+                        warnings.push(format!(
+                            "3D port at {pos:?} is not on a wire and is ignored"
+                        ));
+                    }
+                }
+
+                let other_side = match (schematic_nodes > 0, !fdtd_positions.is_empty()) {
+                    (true, false) => {
+                        warnings.push("Only present in the schematic".to_string());
+                        Some(&fdtd_names)
+                    }
+                    (false, true) => {
+                        warnings.push("Only present in the 3D wiring".to_string());
+                        Some(&schematic_names)
+                    }
+                    _ => None,
+                };
+
+                if let Some(other_side) = other_side {
+                    for candidate in other_side {
+                        if is_probable_typo(name, candidate) {
+                            warnings.push(format!("Did you mean \"{candidate}\"?"));
+                        }
+                    }
+                }
+
+                if schematic_nodes + fdtd_connected < 2 {
+                    warnings.push("Connects fewer than two nodes".to_string());
+                }
+
+                Self {
+                    name: name.to_string(),
+                    schematic_nodes,
+                    fdtd_positions,
+                    fdtd_connected,
+                    warnings,
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_probable_typo(a: &str, b: &str) -> bool {
+    if a == b {
+        return false;
+    }
+
+    let (a_norm, b_norm) = (a.trim().to_lowercase(), b.trim().to_lowercase());
+    a_norm == b_norm || levenshtein(&a_norm, &b_norm) <= 2.min(a_norm.len().max(b_norm.len()) / 2)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = diag + usize::from(ca != *cb);
+            diag = row[j + 1];
+            row[j + 1] = subst.min(row[j] + 1).min(diag + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_distances() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("flaw", "lawn"), 2);
+        assert_eq!(levenshtein("port", "port"), 0);
+        assert_eq!(levenshtein("Vcc", "Vcc1"), 1);
+    }
+
+    #[test]
+    fn levenshtein_is_symmetric() {
+        for (a, b) in [("gnd", "ground"), ("in_a", "ina"), ("µ", "u")] {
+            assert_eq!(levenshtein(a, b), levenshtein(b, a));
+        }
+    }
+
+    #[test]
+    fn typos_are_near_but_not_equal() {
+        assert!(is_probable_typo("GND", "gnd"));
+        assert!(is_probable_typo("out1", "out2"));
+        assert!(!is_probable_typo("out", "out"));
+        assert!(!is_probable_typo("a", "b"));
+        assert!(!is_probable_typo("input", "output"));
+    }
+}
@@ -1,19 +1,104 @@
+use std::collections::HashMap;
+
 use ndarray::Array4;
 
+use crate::common::IntPos3;
+
+/// Equivalent radius of an uncorrected one-cell wire, as a fraction of dx.
+pub const THIN_WIRE_EQUIVALENT_RADIUS: f64 = 0.135;
+
+/// A unit edge carrying a thin wire: (lesser node, axis, wire radius in meters)
+pub type ThinWireEdge = (IntPos3, usize, f64);
+
 pub struct FdtdSim {
     pub e_field: Array4<f64>,
     pub h_field: Array4<f64>,
+    /// Per-component multiplier on the curl term of the E update (1/relative permittivity)
+    e_coeff: Array4<f64>,
+    /// Per-component multiplier on the curl term of the H update (1/relative permeability)
+    h_coeff: Array4<f64>,
     width: usize,
+    /// Number of steps taken since creation
+    steps: usize,
 }
 
 impl FdtdSim {
     pub fn new(width: usize) -> Self {
         let e_field = Array4::zeros((width, width, width, 3));
         let h_field = Array4::zeros((width, width, width, 3));
+        let e_coeff = Array4::ones((width, width, width, 3));
+        let h_coeff = Array4::ones((width, width, width, 3));
         Self {
             e_field,
             h_field,
+            e_coeff,
+            h_coeff,
             width,
+            steps: 0,
+        }
+    }
+
+    /// Applies a Holland-style thin-wire correction around unit edges. In the ring of cells
+    /// around both ends of each edge, the circulating H components see their permeability
+    /// scaled up and the radial E components their permittivity scaled down by
+    /// `ln(dx / radius) / ln(dx / r_eq)`,
+    /// so the in-cell inductance matches that of a wire with the given radius.
+    /// Where rings overlap, the thinnest wire wins, independent of the order of the edges.
+    pub fn set_thin_wires(
+        &mut self,
+        cfg: &FdtdSimConfig,
+        edges: impl IntoIterator<Item = ThinWireEdge>,
+    ) {
+        self.e_coeff.fill(1.0);
+        self.h_coeff.fill(1.0);
+
+        let width = self.width as isize;
+        let r_eq = THIN_WIRE_EQUIVALENT_RADIUS * cfg.dx;
+
+        // Thinnest radius seen by each (cell, component) of the E and H rings
+        type Radii = HashMap<(usize, usize, usize, usize), f64>;
+        fn thinnest(map: &mut Radii, key: (usize, usize, usize, usize), radius: f64) {
+            map.entry(key)
+                .and_modify(|r| *r = r.min(radius))
+                .or_insert(radius);
+        }
+        let mut e_radius = Radii::new();
+        let mut h_radius = Radii::new();
+
+        for ((x, y, z), axis, radius) in edges {
+            let radius = radius.clamp(1e-6 * cfg.dx, 0.5 * cfg.dx);
+
+            for end in [0, 1] {
+                let mut node = [x as isize, y as isize, z as isize];
+                node[axis] += end;
+
+                for radial in [X, Y, Z] {
+                    if radial == axis {
+                        continue;
+                    }
+                    let circulating = 3 - axis - radial;
+
+                    for sign in [-1, 1] {
+                        let mut q = node;
+                        q[radial] += sign;
+                        if q.iter().any(|&c| c < 0 || c >= width) {
+                            continue;
+                        }
+
+                        let [qx, qy, qz] = q.map(|c| c as usize);
+                        thinnest(&mut h_radius, (qx, qy, qz, circulating), radius);
+                        thinnest(&mut e_radius, (qx, qy, qz, radial), radius);
+                    }
+                }
+            }
+        }
+
+        let correction = |radius: f64| (cfg.dx / radius).ln() / (cfg.dx / r_eq).ln();
+        for (key, radius) in h_radius {
+            self.h_coeff[key] = 1.0 / correction(radius);
+        }
+        for (key, radius) in e_radius {
+            self.e_coeff[key] = correction(radius);
         }
     }
 
@@ -29,6 +114,10 @@ impl FdtdSim {
         self.width
     }
 
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
     pub fn step(
         &mut self,
         cfg: &FdtdSimConfig,
@@ -36,6 +125,7 @@ impl FdtdSim {
         current: &Array4<f64>,
     ) -> Array4<f64> {
         let prev_e_field = self.e_field.clone();
+        self.steps += 1;
 
         self.e_field -= &(cfg.dt * cfg.mu * current);
 
@@ -43,6 +133,7 @@ impl FdtdSim {
             &mut self.e_field,
             &(&self.h_field + magnetization),
             cfg.scaling(),
+            &self.e_coeff,
         );
 
         let width = self.width();
@@ -61,6 +152,7 @@ impl FdtdSim {
             &mut self.h_field,
             &(&self.e_field + current),
             -cfg.scaling(),
+            &self.h_coeff,
         );
 
         let width = self.width();
@@ -75,15 +167,16 @@ impl FdtdSim {
             }
         }
 
-        let induced_current = &self.e_field - &(prev_e_field - &(cfg.dt * curl(&self.h_field)));
+        // Weighted like the update, so the thin-wire coefficients don't read as induced current
+        let curl_h = curl(&self.h_field) * &self.e_coeff;
+        let induced_current = &self.e_field - &(prev_e_field - &(cfg.dt * curl_h));
         let induced_current = induced_current / (cfg.dt * cfg.mu);
 
         induced_current
     }
 }
 
-#[derive(Clone)]
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct FdtdSimConfig {
     /// Spacial step (meters)
     pub dx: f64,
@@ -108,8 +201,8 @@ const Z: usize = 2;
 /// Some Numerical Techniques for Maxwell's
 /// Equations in Different Types of Geometries
 /// (Bengt Fornberg)
-fn half_step(a: &mut Array4<f64>, b: &Array4<f64>, scale: f64) {
-    *a += &(curl(b) * scale);
+fn half_step(a: &mut Array4<f64>, b: &Array4<f64>, scale: f64, coeff: &Array4<f64>) {
+    *a += &(curl(b) * coeff * scale);
 }
 
 fn curl(b: &Array4<f64>) -> Array4<f64> {
@@ -153,3 +246,78 @@ impl Default for FdtdSimConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire_editor_3d::Wiring3D;
+
+    fn coefficients(sim: &FdtdSim) -> (Vec<u64>, Vec<u64>) {
+        let bits = |a: &Array4<f64>| a.iter().map(|v| v.to_bits()).collect();
+        (bits(&sim.e_coeff), bits(&sim.h_coeff))
+    }
+
+    #[test]
+    fn thin_wires_are_independent_of_edge_order() {
+        let cfg = FdtdSimConfig::default();
+        // Overlapping rings of a thick and a thin wire
+        let edges = [
+            ((3, 3, 3), Z, 0.05),
+            ((3, 3, 4), Z, 0.3),
+            ((3, 4, 4), X, 0.01),
+        ];
+
+        let mut forward = FdtdSim::new(8);
+        forward.set_thin_wires(&cfg, edges);
+        let mut backward = FdtdSim::new(8);
+        backward.set_thin_wires(&cfg, edges.into_iter().rev());
+
+        assert_eq!(coefficients(&forward), coefficients(&backward));
+    }
+
+    #[test]
+    fn thinnest_wire_wins_where_rings_overlap() {
+        let cfg = FdtdSimConfig::default();
+        let mut thin = FdtdSim::new(8);
+        thin.set_thin_wires(&cfg, [((3, 3, 3), Z, 0.01)]);
+        let mut both = FdtdSim::new(8);
+        both.set_thin_wires(&cfg, [((3, 3, 3), Z, 0.01), ((3, 3, 3), Z, 0.3)]);
+
+        // Radial E next to the wire
+        assert_eq!(thin.e_coeff[(4, 3, 3, X)], both.e_coeff[(4, 3, 3, X)]);
+        assert!(thin.e_coeff[(4, 3, 3, X)] > 1.0);
+        assert!(thin.h_coeff[(4, 3, 3, Y)] < 1.0);
+    }
+
+    #[test]
+    fn equivalent_radius_needs_no_correction() {
+        let cfg = FdtdSimConfig::default();
+        let mut sim = FdtdSim::new(6);
+        let radius = THIN_WIRE_EQUIVALENT_RADIUS * cfg.dx;
+        sim.set_thin_wires(&cfg, [((2, 2, 2), Y, radius)]);
+        assert!(sim.e_coeff.iter().all(|&c| (c - 1.0).abs() < 1e-12));
+        assert!(sim.h_coeff.iter().all(|&c| (c - 1.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn removing_wires_resets_the_coefficients() {
+        let cfg = FdtdSimConfig::default();
+        let mut sim = FdtdSim::new(6);
+        sim.set_thin_wires(&cfg, [((2, 2, 2), Y, 0.01)]);
+        sim.set_thin_wires(&cfg, []);
+        assert!(sim.e_coeff.iter().all(|&c| c == 1.0));
+        assert!(sim.h_coeff.iter().all(|&c| c == 1.0));
+    }
+
+    #[test]
+    fn documents_without_wire_radii_need_no_correction() {
+        let cfg = FdtdSimConfig::default();
+        let wiring: Wiring3D =
+            ron::from_str("(wires: {((2, 2, 2), (2, 4, 3)): (resistance: 1.0)}, ports: {})")
+                .unwrap();
+        let mut sim = FdtdSim::new(6);
+        sim.set_thin_wires(&cfg, wiring.thin_wire_edges(cfg.dx));
+        assert!(sim.e_coeff.iter().all(|&c| c == 1.0));
+        assert!(sim.h_coeff.iter().all(|&c| c == 1.0));
+    }
+}
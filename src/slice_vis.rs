@@ -0,0 +1,283 @@
+use egui::{
+    Color32, ColorImage, DragValue, Mesh, Pos2, Rect, Sense, Shape, TextureHandle, TextureOptions,
+    Ui, Vec2, epaint::Vertex,
+};
+use threegui::{Painter3D, Vec3};
+
+use crate::{
+    colormap::{ColorScale, Colormap, ScaleRange, data_range, draw_legend},
+    common::espace,
+    field_quantity::FieldQuantity,
+    sim::{FdtdSim, FdtdSimConfig},
+};
+
+/// A plane of grid points coloured by a field quantity, drawn in the 3D view and in its own pane
+pub struct SliceVisualization {
+    /// Draw the slice in the 3D view
+    pub show_3d: bool,
+    /// Axis normal to the plane
+    pub axis: usize,
+    pub index: usize,
+    pub quantity: FieldQuantity,
+    pub scale: ColorScale,
+    pub opacity: f32,
+    texture: Option<TextureHandle>,
+    /// The slice last uploaded to `texture`, and the pass it was sampled in
+    cache: Option<(u64, Slice)>,
+}
+
+/// Quads per side of the mesh the slice is drawn on in the 3D view, so that
+/// the texture follows the perspective projection closely
+const SUBDIVISIONS: usize = 16;
+
+/// Values of a quantity on the plane, indexed by `u + v * width` where (u, v) are
+/// the coordinates along the two axes following `axis`, as for the working plane.
+pub struct Slice {
+    pub width: usize,
+    pub values: Vec<f64>,
+    pub range: ScaleRange,
+}
+
+impl SliceVisualization {
+    pub fn new(width: usize) -> Self {
+        Self {
+            show_3d: false,
+            axis: 2,
+            index: width / 2,
+            quantity: FieldQuantity::EMagnitude,
+            scale: ColorScale::new(Colormap::Viridis),
+            opacity: 0.8,
+            texture: None,
+            cache: None,
+        }
+    }
+
+    pub fn show_ui(&mut self, ui: &mut Ui, width: usize) {
+        ui.horizontal_wrapped(|ui| {
+            let prev = self.quantity;
+            egui::ComboBox::from_id_salt("slice_quantity")
+                .selected_text(self.quantity.name())
+                .show_ui(ui, |ui| {
+                    for quantity in FieldQuantity::ALL {
+                        ui.selectable_value(&mut self.quantity, quantity, quantity.name());
+                    }
+                });
+            // Switch to a fitting colormap when the quantity changes sign convention
+            if prev.is_signed() != self.quantity.is_signed() {
+                self.scale.colormap = if self.quantity.is_signed() {
+                    Colormap::Diverging
+                } else {
+                    Colormap::Viridis
+                };
+            }
+
+            for (axis, name) in ["YZ", "XZ", "XY"].into_iter().enumerate() {
+                ui.selectable_value(&mut self.axis, axis, name);
+            }
+            ui.add(
+                DragValue::new(&mut self.index)
+                    .range(0..=width.saturating_sub(1))
+                    .prefix(["x", "y", "z"][self.axis].to_string() + " = "),
+            );
+        });
+
+        self.scale.show_ui(ui, "slice");
+
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(&mut self.show_3d, "Show in 3D view");
+            ui.add(
+                DragValue::new(&mut self.opacity)
+                    .range(0.0..=1.0)
+                    .prefix("Opacity: ")
+                    .speed(1e-2),
+            );
+        });
+    }
+
+    pub fn sample(&self, sim: &FdtdSim, cfg: &FdtdSimConfig) -> Slice {
+        let width = sim.width();
+        let index = self.index.min(width.saturating_sub(1));
+
+        let mut values = Vec::with_capacity(width * width);
+        for v in 0..width {
+            for u in 0..width {
+                let mut p = [0; 3];
+                p[self.axis] = index;
+                p[(self.axis + 1) % 3] = u;
+                p[(self.axis + 2) % 3] = v;
+                values.push(self.quantity.sample(sim, cfg, (p[0], p[1], p[2])));
+            }
+        }
+
+        let (lo, hi) = data_range(values.iter().copied());
+        let range = self.scale.range(lo, hi);
+
+        Slice {
+            width,
+            values,
+            range,
+        }
+    }
+
+    /// Samples the slice and uploads it as a texture, at most once per frame,
+    /// so the 3D view and the pane share both
+    fn update(&mut self, ctx: &egui::Context, sim: &FdtdSim, cfg: &FdtdSimConfig) {
+        let pass = ctx.cumulative_pass_nr();
+        if self
+            .cache
+            .as_ref()
+            .is_some_and(|(sampled, _)| *sampled == pass)
+        {
+            return;
+        }
+
+        let slice = self.sample(sim, cfg);
+        let width = slice.width;
+
+        // Flip vertically so v increases upwards
+        let pixels = (0..width)
+            .rev()
+            .flat_map(|v| (0..width).map(move |u| (u, v)))
+            .map(|(u, v)| slice.color(&self.scale, u, v))
+            .collect();
+        let image = ColorImage::new([width, width], pixels);
+
+        match &mut self.texture {
+            Some(texture) => texture.set(image, TextureOptions::NEAREST),
+            None => {
+                self.texture = Some(ctx.load_texture("field_slice", image, TextureOptions::NEAREST))
+            }
+        }
+        self.cache = Some((pass, slice));
+    }
+
+    /// Draws the slice as a single textured quad, plus a colour bar in the corner of the view
+    pub fn draw(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, paint: &Painter3D) {
+        if !self.show_3d {
+            return;
+        }
+
+        self.update(paint.egui().ctx(), sim, cfg);
+        let (Some((_, slice)), Some(texture)) = (&self.cache, &self.texture) else {
+            return;
+        };
+        let width = slice.width;
+        let last = width.saturating_sub(1) as f32;
+        let index = self.index.min(width.saturating_sub(1)) as f32;
+
+        let point = |u: f32, v: f32| {
+            let mut p = Vec3::ZERO;
+            p[self.axis] = index;
+            p[(self.axis + 1) % 3] = u;
+            p[(self.axis + 2) % 3] = v;
+            paint.transform(espace(width, p))
+        };
+
+        // The quad spans the grid points; texels are centered on them
+        let n = SUBDIVISIONS;
+        let color = Color32::WHITE.gamma_multiply(self.opacity);
+        let mut mesh = Mesh::with_texture(texture.id());
+        let mut vertex_idx = vec![None; (n + 1) * (n + 1)];
+        for j in 0..=n {
+            for i in 0..=n {
+                let u = last * i as f32 / n as f32;
+                let v = last * j as f32 / n as f32;
+                let Some(pos) = point(u, v) else {
+                    continue;
+                };
+                let uv = Pos2::new((u + 0.5) / width as f32, 1.0 - (v + 0.5) / width as f32);
+                vertex_idx[i + j * (n + 1)] = Some(mesh.vertices.len() as u32);
+                mesh.vertices.push(Vertex { pos, uv, color });
+            }
+        }
+        for j in 0..n {
+            for i in 0..n {
+                let corner = |di: usize, dj: usize| vertex_idx[i + di + (j + dj) * (n + 1)];
+                if let (Some(a), Some(b), Some(c), Some(d)) =
+                    (corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1))
+                {
+                    mesh.add_triangle(a, b, c);
+                    mesh.add_triangle(a, c, d);
+                }
+            }
+        }
+        paint.egui().add(Shape::mesh(mesh));
+
+        let clip = paint.egui().clip_rect();
+        let bar = Rect::from_min_size(
+            clip.right_top() + Vec2::new(-80.0, 24.0),
+            Vec2::new(14.0, 150.0),
+        );
+        draw_legend(
+            paint.egui(),
+            bar,
+            &self.scale,
+            &slice.range,
+            self.quantity.name(),
+        );
+    }
+
+    /// The slice as an image, with a colour bar and the value under the cursor
+    pub fn show_pane(&mut self, ui: &mut Ui, sim: &FdtdSim, cfg: &FdtdSimConfig) {
+        self.show_ui(ui, sim.width());
+        ui.separator();
+
+        self.update(ui.ctx(), sim, cfg);
+        let (Some((_, slice)), Some(texture)) = (&self.cache, &self.texture) else {
+            return;
+        };
+        let width = slice.width;
+
+        let bar_width = 80.0;
+        let avail = ui.available_size() - Vec2::new(0.0, ui.spacing().interact_size.y);
+        let side = (avail.x - bar_width).min(avail.y).max(16.0);
+
+        let mut hovered = None;
+        ui.horizontal(|ui| {
+            let (rect, resp) = ui.allocate_exact_size(Vec2::splat(side), Sense::hover());
+            ui.painter().image(
+                texture.id(),
+                rect,
+                Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                Color32::WHITE,
+            );
+
+            if let Some(pos) = resp.hover_pos() {
+                let rel = (pos - rect.min) / rect.size();
+                let u = ((rel.x * width as f32) as usize).min(width - 1);
+                let v = width - 1 - ((rel.y * width as f32) as usize).min(width - 1);
+                hovered = Some((u, v));
+            }
+
+            let (bar_rect, _) = ui.allocate_exact_size(Vec2::new(bar_width, side), Sense::hover());
+            let bar = Rect::from_min_size(
+                bar_rect.min + Vec2::new(4.0, 8.0),
+                Vec2::new(14.0, side - 16.0),
+            );
+            draw_legend(
+                ui.painter(),
+                bar,
+                &self.scale,
+                &slice.range,
+                self.quantity.name(),
+            );
+        });
+
+        let names = ["x", "y", "z"];
+        let (u_name, v_name) = (names[(self.axis + 1) % 3], names[(self.axis + 2) % 3]);
+        match hovered {
+            Some((u, v)) => ui.label(format!(
+                "{u_name} = {u}, {v_name} = {v}: {} = {:.3e}",
+                self.quantity.name(),
+                slice.values[u + v * width]
+            )),
+            None => ui.label(format!("Horizontal: {u_name}, vertical: {v_name}")),
+        };
+    }
+}
+
+impl Slice {
+    pub fn color(&self, scale: &ColorScale, u: usize, v: usize) -> Color32 {
+        scale.color(&self.range, self.values[u + v * self.width])
+    }
+}
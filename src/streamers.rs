@@ -1,10 +1,12 @@
-use egui::{Color32, Stroke};
-use rand::Rng;
+use egui::{Color32, DragValue, Stroke, Ui};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
 use threegui::{Painter3D, Vec3};
 
 use crate::{
-    common::{espace, interp, screenspace_arrow},
-    sim::FdtdSim,
+    common::{IntPos3, espace, espacet, interp},
+    field_quantity::poynting_field,
+    sim::{FdtdSim, FdtdSimConfig},
+    wire_editor_3d::WireId,
 };
 
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
@@ -13,88 +15,343 @@ pub enum StreamersMode {
     Off,
     HField,
     EField,
+    /// Power flow, S = E × H
+    Poynting,
 }
 
+/// Where streamlines start, in grid coordinates
+#[derive(Clone, PartialEq)]
+pub enum Seeding {
+    /// Uniformly throughout the grid
+    Random,
+    /// Evenly spaced along a line
+    Line { start: Vec3, end: Vec3 },
+    /// Spread over a disc normal to one axis
+    Disc {
+        center: Vec3,
+        axis: usize,
+        radius: f32,
+    },
+    /// Spiralling around the wire selected in the 3D editor
+    AroundWire { radius: f32 },
+}
+
+impl Seeding {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Random => "Random",
+            Self::Line { .. } => "Line",
+            Self::Disc { .. } => "Disc",
+            Self::AroundWire { .. } => "Around selected wire",
+        }
+    }
+}
+
+/// Settings the current seeds were generated for
+#[derive(PartialEq)]
+struct SeedKey {
+    seeding: Seeding,
+    count: usize,
+    deterministic: bool,
+    seed: u64,
+    width: usize,
+    wire: Option<WireId>,
+}
+
+/// Field lines traced from a set of seed points with RK4, redrawn every frame
 pub struct Streamers {
-    pub points: Vec<Vec3>,
+    pub mode: StreamersMode,
+    pub seeding: Seeding,
+    pub count: usize,
+    /// Integration steps per streamline
+    pub length: usize,
+    /// Integration step, in cells
+    pub step: f32,
+    /// Opacity lost over the length of each line, from 0 (none) to 1 (transparent at the end)
+    pub fade: f32,
+    /// Seed the random number generator with `seed`, so seeds are the same between runs
+    pub deterministic: bool,
+    pub seed: u64,
+    seeds: Vec<Vec3>,
+    seeded_for: Option<SeedKey>,
 }
 
-impl Streamers {
-    pub fn new(width: usize, n: usize) -> Self {
-        let mut rng = rand::thread_rng();
+/// Angle between successive seeds of a sunflower spiral, which spreads them evenly
+const GOLDEN_ANGLE: f32 = 2.399_963;
 
+impl Default for Streamers {
+    fn default() -> Self {
         Self {
-            points: (0..n).map(|_| Self::random_pos(width, &mut rng)).collect(),
+            mode: StreamersMode::HField,
+            seeding: Seeding::Random,
+            count: 500,
+            length: 40,
+            step: 0.25,
+            fade: 0.8,
+            deterministic: false,
+            seed: 0,
+            seeds: vec![],
+            seeded_for: None,
+        }
+    }
+}
+
+impl Streamers {
+    pub fn show_ui(&mut self, ui: &mut Ui, width: usize) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, StreamersMode::Off, "Off");
+            ui.selectable_value(&mut self.mode, StreamersMode::HField, "H field");
+            ui.selectable_value(&mut self.mode, StreamersMode::EField, "E field");
+            ui.selectable_value(&mut self.mode, StreamersMode::Poynting, "Poynting");
+        });
+
+        let mid = (width as f32 - 1.0) / 2.0;
+        let quarter = width as f32 / 4.0;
+        let options = [
+            Seeding::Random,
+            Seeding::Line {
+                start: Vec3::new(mid, mid, 0.0),
+                end: Vec3::new(mid, mid, width as f32 - 1.0),
+            },
+            Seeding::Disc {
+                center: Vec3::splat(mid),
+                axis: 2,
+                radius: quarter,
+            },
+            Seeding::AroundWire { radius: 1.0 },
+        ];
+        egui::ComboBox::from_label("Seeding")
+            .selected_text(self.seeding.name())
+            .show_ui(ui, |ui| {
+                for option in options {
+                    let selected = option.name() == self.seeding.name();
+                    let name = option.name();
+                    if ui.selectable_label(selected, name).clicked() && !selected {
+                        self.seeding = option;
+                    }
+                }
+            });
+
+        let max = width.saturating_sub(1) as f32;
+        let point_ui = |ui: &mut Ui, label: &str, p: &mut Vec3| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                for (c, name) in ["x: ", "y: ", "z: "].into_iter().enumerate() {
+                    ui.add(
+                        DragValue::new(&mut p[c])
+                            .range(0.0..=max)
+                            .prefix(name)
+                            .speed(0.1),
+                    );
+                }
+            });
+        };
+        match &mut self.seeding {
+            Seeding::Random => (),
+            Seeding::Line { start, end } => {
+                point_ui(ui, "Start: ", start);
+                point_ui(ui, "End: ", end);
+            }
+            Seeding::Disc {
+                center,
+                axis,
+                radius,
+            } => {
+                point_ui(ui, "Center: ", center);
+                ui.horizontal(|ui| {
+                    ui.label("Normal: ");
+                    for (a, name) in ["X", "Y", "Z"].into_iter().enumerate() {
+                        ui.selectable_value(axis, a, name);
+                    }
+                });
+                ui.add(
+                    DragValue::new(radius)
+                        .range(0.0..=max)
+                        .prefix("Radius: ")
+                        .speed(0.1),
+                );
+            }
+            Seeding::AroundWire { radius } => {
+                ui.add(
+                    DragValue::new(radius)
+                        .range(0.0..=max)
+                        .prefix("Radius: ")
+                        .speed(0.05),
+                );
+            }
         }
+
+        ui.add(
+            DragValue::new(&mut self.count)
+                .range(1..=100_000)
+                .prefix("Lines: "),
+        );
+        ui.add(
+            DragValue::new(&mut self.length)
+                .range(1..=10_000)
+                .prefix("Steps per line: "),
+        );
+        ui.add(
+            DragValue::new(&mut self.step)
+                .range(1e-3..=10.0)
+                .prefix("Step: ")
+                .suffix(" cells")
+                .speed(1e-2),
+        );
+        ui.add(
+            DragValue::new(&mut self.fade)
+                .range(0.0..=1.0)
+                .prefix("Fade: ")
+                .speed(1e-2),
+        );
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.deterministic, "Fixed seed");
+            ui.add_enabled(self.deterministic, DragValue::new(&mut self.seed));
+            if ui.button("Reseed").clicked() {
+                self.seeded_for = None;
+            }
+        });
     }
 
-    fn random_pos(width: usize, rng: &mut impl Rng) -> Vec3 {
-        let width = width as f32 - 1.0;
-        Vec3::new(
-            rng.gen_range(0.0..=width),
-            rng.gen_range(0.0..=width),
-            rng.gen_range(0.0..=width),
-        )
+    fn reseed(&mut self, width: usize, wire: Option<WireId>) {
+        let mut rng = if self.deterministic {
+            SmallRng::seed_from_u64(self.seed)
+        } else {
+            SmallRng::from_entropy()
+        };
+        let n = self.count;
+        let max = width as f32 - 1.0;
+
+        // Sunflower spiral of n points on the unit disc
+        let spiral = |i: usize| {
+            let r = ((i as f32 + 0.5) / n as f32).sqrt();
+            let theta = i as f32 * GOLDEN_ANGLE;
+            (r * theta.cos(), r * theta.sin())
+        };
+
+        self.seeds = match &self.seeding {
+            Seeding::Random => (0..n)
+                .map(|_| {
+                    Vec3::new(
+                        rng.gen_range(0.0..=max),
+                        rng.gen_range(0.0..=max),
+                        rng.gen_range(0.0..=max),
+                    )
+                })
+                .collect(),
+            Seeding::Line { start, end } => (0..n)
+                .map(|i| start.lerp(*end, (i as f32 + 0.5) / n as f32))
+                .collect(),
+            Seeding::Disc {
+                center,
+                axis,
+                radius,
+            } => (0..n)
+                .map(|i| {
+                    let (u, v) = spiral(i);
+                    let mut offset = Vec3::ZERO;
+                    offset[(axis + 1) % 3] = u * radius;
+                    offset[(axis + 2) % 3] = v * radius;
+                    *center + offset
+                })
+                .collect(),
+            Seeding::AroundWire { radius } => match wire {
+                Some((a, b)) => {
+                    let (a, b) = (to_vec3(a), to_vec3(b));
+                    let dir = (b - a).normalize_or_zero();
+                    let u = dir.any_orthonormal_vector();
+                    let v = dir.cross(u);
+                    (0..n)
+                        .map(|i| {
+                            let t = (i as f32 + 0.5) / n as f32;
+                            let theta = i as f32 * GOLDEN_ANGLE;
+                            a.lerp(b, t) + (u * theta.cos() + v * theta.sin()) * *radius
+                        })
+                        .collect()
+                }
+                None => vec![],
+            },
+        };
     }
 
-    pub fn step(
+    /// Traces and draws the streamlines. `selected_wire` is used for seeding around a wire.
+    pub fn draw(
         &mut self,
         sim: &FdtdSim,
+        cfg: &FdtdSimConfig,
         paint: &Painter3D,
-        dt: f32,
-        shimmer: f64,
-        scale: f32,
-        mode: StreamersMode,
+        selected_wire: Option<WireId>,
     ) {
-        let is_efield = match mode {
+        let poynting;
+        let (field, color) = match self.mode {
             StreamersMode::Off => return,
-            StreamersMode::HField => false,
-            StreamersMode::EField => true,
+            StreamersMode::EField => (sim.e_field(), Color32::YELLOW),
+            StreamersMode::HField => (sim.h_field(), Color32::RED),
+            StreamersMode::Poynting => {
+                poynting = poynting_field(sim, cfg);
+                (&poynting, Color32::from_rgb(120, 255, 120))
+            }
         };
 
-        let color = if is_efield {
-            Color32::YELLOW
-        } else {
-            Color32::RED
+        let width = sim.width();
+        let seed_wire =
+            selected_wire.filter(|_| matches!(self.seeding, Seeding::AroundWire { .. }));
+        let key = SeedKey {
+            seeding: self.seeding.clone(),
+            count: self.count,
+            deterministic: self.deterministic,
+            seed: self.seed,
+            width,
+            wire: seed_wire,
         };
+        if self.seeded_for.as_ref() != Some(&key) {
+            self.reseed(width, key.wire);
+            self.seeded_for = Some(key);
+        }
 
-        let mut rng = rand::thread_rng();
-        for point in &mut self.points {
-            let width = sim.width();
-            let w = width as f32;
-            let out_of_bounds = point.to_array().into_iter().any(|x| x < 0.0 || x > w - 1.0);
+        let max = width as f32 - 1.0;
+        let in_bounds = |p: Vec3| p.to_array().into_iter().all(|x| (0.0..=max).contains(&x));
+        // Streamlines follow the field direction only, so steps are a fixed length
+        let direction = |p: Vec3| interp(field, p).normalize_or_zero();
 
-            if out_of_bounds || rng.gen_bool(shimmer) {
-                *point = Self::random_pos(width, &mut rng);
-                continue;
-            }
+        if let Some((a, b)) = seed_wire {
+            let stroke = Stroke::new(3.0, color.gamma_multiply(0.3));
+            paint.line(espacet(width, a), espacet(width, b), stroke);
+        }
+
+        for &seed in &self.seeds {
+            let mut p = seed;
+            for n in 0..self.length {
+                if !in_bounds(p) {
+                    break;
+                }
+                let next = rk4_step(&direction, p, self.step);
+                if next == p {
+                    break;
+                }
 
-            let field = if is_efield {
-                sim.e_field()
-            } else {
-                sim.h_field()
-            };
-            let field = interp(field, *point);
-
-            let before = *point;
-            let after = *point + field * scale;
-
-            *point += field * dt;
-
-            let stroke = Stroke::new(1., color);
-            screenspace_arrow(
-                paint,
-                espace(sim.width(), before),
-                espace(sim.width(), after),
-                stroke,
-            );
-            /*
-            paint.line(
-                espace(sim.width(), before),
-                espace(sim.width(), after),
-                Stroke::new(1., Color32::WHITE),
-            );
-            */
+                let alpha = 1.0 - self.fade * n as f32 / self.length as f32;
+                paint.line(
+                    espace(width, p),
+                    espace(width, next),
+                    Stroke::new(1., color.gamma_multiply(alpha)),
+                );
+                p = next;
+            }
         }
     }
 }
+
+fn to_vec3((x, y, z): IntPos3) -> Vec3 {
+    Vec3::new(x as f32, y as f32, z as f32)
+}
+
+/// One classic fourth-order Runge-Kutta step along the vector field `f`
+fn rk4_step(f: &impl Fn(Vec3) -> Vec3, p: Vec3, h: f32) -> Vec3 {
+    let k1 = f(p);
+    let k2 = f(p + k1 * (h / 2.0));
+    let k3 = f(p + k2 * (h / 2.0));
+    let k4 = f(p + k3 * h);
+    p + (k1 + 2.0 * k2 + 2.0 * k3 + k4) * (h / 6.0)
+}
@@ -0,0 +1,448 @@
+//! Export of fields and wiring to VTK XML files for ParaView.
+//!
+//! Fields are written as ImageData (`.vti`) on the FDTD grid with spacing `dx`,
+//! the wiring as PolyData (`.vtp`), and time series are indexed by a `.pvd` collection.
+//! All data is written as ASCII, which ParaView reads without any extra libraries.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use ndarray::{Array3, Array4};
+
+use crate::{
+    field_quantity::{divergence_fields, energy_density_field, poynting_field},
+    sim::{FdtdSim, FdtdSimConfig},
+    wire_editor_3d::Wiring3D,
+};
+
+/// A named array of point data on the grid
+pub enum PointData<'a> {
+    /// Indexed (x, y, z), like the fields
+    Scalar(&'a str, &'a Array3<f64>),
+    /// Indexed (x, y, z, component), like the fields
+    Vector(&'a str, &'a Array4<f64>),
+}
+
+/// Writes point data on a cubic grid of the given spacing as VTK ImageData
+pub fn write_image_data(w: &mut impl Write, dx: f64, data: &[PointData<'_>]) -> io::Result<()> {
+    let width = data
+        .iter()
+        .map(|d| match d {
+            PointData::Scalar(_, a) => a.dim().0,
+            PointData::Vector(_, a) => a.dim().0,
+        })
+        .next()
+        .unwrap_or(0);
+    let extent = width.saturating_sub(1);
+
+    writeln!(w, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        w,
+        r#"<VTKFile type="ImageData" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(
+        w,
+        r#"<ImageData WholeExtent="0 {extent} 0 {extent} 0 {extent}" Origin="0 0 0" Spacing="{dx} {dx} {dx}">"#
+    )?;
+    writeln!(w, r#"<Piece Extent="0 {extent} 0 {extent} 0 {extent}">"#)?;
+    writeln!(w, "<PointData>")?;
+
+    for d in data {
+        let (name, components) = match d {
+            PointData::Scalar(name, _) => (name, 1),
+            PointData::Vector(name, _) => (name, 3),
+        };
+        writeln!(
+            w,
+            r#"<DataArray type="Float64" Name="{}" NumberOfComponents="{components}" format="ascii">"#,
+            escape(name)
+        )?;
+
+        // VTK orders points with x varying fastest
+        let mut line = String::new();
+        for k in 0..width {
+            for j in 0..width {
+                line.clear();
+                for i in 0..width {
+                    match d {
+                        PointData::Scalar(_, a) => write!(line, "{} ", a[(i, j, k)]),
+                        PointData::Vector(_, a) => write!(
+                            line,
+                            "{} {} {} ",
+                            a[(i, j, k, 0)],
+                            a[(i, j, k, 1)],
+                            a[(i, j, k, 2)]
+                        ),
+                    }
+                    .unwrap();
+                }
+                writeln!(w, "{}", line.trim_end())?;
+            }
+        }
+
+        writeln!(w, "</DataArray>")?;
+    }
+
+    writeln!(w, "</PointData>")?;
+    writeln!(w, "</Piece>")?;
+    writeln!(w, "</ImageData>")?;
+    writeln!(w, "</VTKFile>")?;
+    Ok(())
+}
+
+/// Writes E, H, B, the Poynting vector, energy density and the divergences of the simulation.
+/// H is converted out of the scaled units it is stored in.
+pub fn write_fields(w: &mut impl Write, sim: &FdtdSim, cfg: &FdtdSimConfig) -> io::Result<()> {
+    let h_field = sim.h_field() * (cfg.eps / cfg.mu).sqrt();
+    let b_field = sim.h_field() * (cfg.mu * cfg.eps).sqrt();
+    let poynting = poynting_field(sim, cfg);
+    let energy = energy_density_field(sim, cfg);
+    let (div_e, div_b) = divergence_fields(sim, cfg);
+
+    write_image_data(
+        w,
+        cfg.dx,
+        &[
+            PointData::Vector("E", sim.e_field()),
+            PointData::Vector("H", &h_field),
+            PointData::Vector("B", &b_field),
+            PointData::Vector("S", &poynting),
+            PointData::Scalar("Energy density", &energy),
+            PointData::Scalar("div E", &div_e),
+            PointData::Scalar("div B", &div_b),
+        ],
+    )
+}
+
+/// Writes the wires as line cells carrying their resistance and radius,
+/// and the ports and lumped components as vertices and lines of their own.
+pub fn write_wiring(w: &mut impl Write, wiring: &Wiring3D, dx: f64) -> io::Result<()> {
+    let to_point = |(x, y, z): (usize, usize, usize)| {
+        format!("{} {} {}", x as f64 * dx, y as f64 * dx, z as f64 * dx)
+    };
+
+    let wires = wiring.ordered_wire_ids();
+    let mut ports: Vec<_> = wiring.ports.iter().collect();
+    ports.sort_by_key(|(pos, _)| **pos);
+    let mut components: Vec<_> = wiring.components.keys().copied().collect();
+    components.sort();
+
+    // Points: two per wire, one per port, two per component
+    let mut points = vec![];
+    for (a, b) in wires.iter().chain(&components) {
+        points.push(to_point(*a));
+        points.push(to_point(*b));
+    }
+    let first_port = points.len();
+    for (pos, _) in &ports {
+        points.push(to_point(**pos));
+    }
+
+    let n_lines = wires.len() + components.len();
+
+    writeln!(w, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        w,
+        r#"<VTKFile type="PolyData" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(w, "<PolyData>")?;
+    writeln!(
+        w,
+        r#"<Piece NumberOfPoints="{}" NumberOfVerts="{}" NumberOfLines="{n_lines}" NumberOfStrips="0" NumberOfPolys="0">"#,
+        points.len(),
+        ports.len()
+    )?;
+
+    writeln!(w, "<Points>")?;
+    writeln!(
+        w,
+        r#"<DataArray type="Float64" NumberOfComponents="3" format="ascii">"#
+    )?;
+    for p in &points {
+        writeln!(w, "{p}")?;
+    }
+    writeln!(w, "</DataArray>")?;
+    writeln!(w, "</Points>")?;
+
+    writeln!(w, "<Verts>")?;
+    write_cells(w, (0..ports.len()).map(|i| vec![first_port + i]))?;
+    writeln!(w, "</Verts>")?;
+
+    writeln!(w, "<Lines>")?;
+    write_cells(w, (0..n_lines).map(|i| vec![2 * i, 2 * i + 1]))?;
+    writeln!(w, "</Lines>")?;
+
+    // Cell data is ordered verts, then lines. -1 where not applicable.
+    let cell_array = |w: &mut dyn Write, name: &str, values: &mut dyn Iterator<Item = f64>| {
+        writeln!(
+            w,
+            r#"<DataArray type="Float64" Name="{name}" format="ascii">"#
+        )?;
+        for v in values {
+            writeln!(w, "{v}")?;
+        }
+        writeln!(w, "</DataArray>")
+    };
+
+    writeln!(w, "<CellData>")?;
+    cell_array(
+        w,
+        "Resistance",
+        &mut (0..ports.len())
+            .map(|_| -1.0)
+            .chain(wires.iter().map(|id| wiring.wires[id].resistance))
+            .chain(components.iter().map(|_| -1.0)),
+    )?;
+    cell_array(
+        w,
+        "Radius",
+        &mut (0..ports.len())
+            .map(|_| -1.0)
+            .chain(
+                wires
+                    .iter()
+                    .map(|id| wiring.wires[id].radius_or_equivalent(dx)),
+            )
+            .chain(components.iter().map(|_| -1.0)),
+    )?;
+    // 0 = port, 1 = wire, 2 = lumped component
+    cell_array(
+        w,
+        "Kind",
+        &mut (0..ports.len())
+            .map(|_| 0.0)
+            .chain(wires.iter().map(|_| 1.0))
+            .chain(components.iter().map(|_| 2.0)),
+    )?;
+    writeln!(w, "</CellData>")?;
+
+    writeln!(w, "</Piece>")?;
+    writeln!(w, "</PolyData>")?;
+    writeln!(w, "</VTKFile>")?;
+    Ok(())
+}
+
+/// Writes the connectivity and offsets arrays of a cell block
+fn write_cells(w: &mut impl Write, cells: impl Iterator<Item = Vec<usize>>) -> io::Result<()> {
+    let mut connectivity = String::new();
+    let mut offsets = String::new();
+    let mut offset = 0;
+    for cell in cells {
+        for idx in &cell {
+            write!(connectivity, "{idx} ").unwrap();
+        }
+        offset += cell.len();
+        write!(offsets, "{offset} ").unwrap();
+    }
+
+    writeln!(
+        w,
+        r#"<DataArray type="Int64" Name="connectivity" format="ascii">"#
+    )?;
+    writeln!(w, "{}", connectivity.trim_end())?;
+    writeln!(w, "</DataArray>")?;
+    writeln!(
+        w,
+        r#"<DataArray type="Int64" Name="offsets" format="ascii">"#
+    )?;
+    writeln!(w, "{}", offsets.trim_end())?;
+    writeln!(w, "</DataArray>")?;
+    Ok(())
+}
+
+/// Index of a time series of files, written as a `.pvd` collection
+#[derive(Default, Clone)]
+pub struct Collection {
+    /// Time and file name, relative to the `.pvd` file
+    pub entries: Vec<(f64, String)>,
+}
+
+impl Collection {
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, r#"<?xml version="1.0"?>"#)?;
+        writeln!(w, r#"<VTKFile type="Collection" version="1.0">"#)?;
+        writeln!(w, "<Collection>")?;
+        for (time, file) in &self.entries {
+            writeln!(
+                w,
+                r#"<DataSet timestep="{time}" part="0" file="{}"/>"#,
+                escape(file)
+            )?;
+        }
+        writeln!(w, "</Collection>")?;
+        writeln!(w, "</VTKFile>")?;
+        Ok(())
+    }
+}
+
+/// Writes a field snapshot into a directory every few steps, keeping a `.pvd` index up to date
+pub struct TimeSeries {
+    dir: PathBuf,
+    prefix: String,
+    pub every_n_steps: usize,
+    collection: Collection,
+}
+
+impl TimeSeries {
+    pub fn new(dir: impl Into<PathBuf>, prefix: impl Into<String>, every_n_steps: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: prefix.into(),
+            every_n_steps,
+            collection: Collection::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn len(&self) -> usize {
+        self.collection.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.entries.is_empty()
+    }
+
+    /// Writes a snapshot if the simulation is on a recorded step,
+    /// indexed by the elapsed simulated `time`
+    pub fn record(&mut self, sim: &FdtdSim, cfg: &FdtdSimConfig, time: f64) -> io::Result<()> {
+        let step = sim.steps();
+        if !step.is_multiple_of(self.every_n_steps.max(1)) {
+            return Ok(());
+        }
+
+        let name = format!("{}_{step:06}.vti", self.prefix);
+        let mut file = io::BufWriter::new(std::fs::File::create(self.dir.join(&name))?);
+        write_fields(&mut file, sim, cfg)?;
+        file.flush()?;
+
+        self.collection.entries.push((time, name));
+
+        let mut index = io::BufWriter::new(std::fs::File::create(
+            self.dir.join(format!("{}.pvd", self.prefix)),
+        )?);
+        self.collection.write(&mut index)?;
+        index.flush()
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use cirmcut::cirmcut_sim::TwoTerminalComponent;
+
+    use super::*;
+    use crate::wire_editor_3d::{LumpedComponent, Port, Wire};
+
+    fn to_string(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut bytes = vec![];
+        write(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Whitespace-separated contents of the data array called `name`
+    fn data_array<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+        let start = xml.find(&format!(r#"Name="{name}""#)).unwrap();
+        let body = &xml[start..];
+        let body = &body[body.find('>').unwrap() + 1..body.find("</DataArray>").unwrap()];
+        body.split_whitespace().collect()
+    }
+
+    #[test]
+    fn image_data_is_x_fastest() {
+        let scalar = Array3::from_shape_fn((2, 2, 2), |(i, j, k)| (i + 10 * j + 100 * k) as f64);
+        let vector = Array4::from_shape_fn((2, 2, 2, 3), |(i, _, _, c)| (i * 3 + c) as f64);
+        let xml = to_string(|w| {
+            write_image_data(
+                w,
+                0.5,
+                &[
+                    PointData::Scalar("s", &scalar),
+                    PointData::Vector("v<1>", &vector),
+                ],
+            )
+        });
+
+        assert!(xml.contains(r#"WholeExtent="0 1 0 1 0 1""#));
+        assert!(xml.contains(r#"Spacing="0.5 0.5 0.5""#));
+        assert_eq!(
+            data_array(&xml, "s"),
+            ["0", "1", "10", "11", "100", "101", "110", "111"]
+        );
+        let v = data_array(&xml, "v&lt;1&gt;");
+        assert_eq!(v.len(), 24);
+        assert_eq!(v[..6], ["0", "1", "2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn fields_hold_every_array() {
+        let sim = FdtdSim::new(3);
+        let xml = to_string(|w| write_fields(w, &sim, &FdtdSimConfig::default()));
+        for name in ["E", "H", "B", "S", "Energy density", "div E", "div B"] {
+            let expected = if name.len() == 1 { 3 * 27 } else { 27 };
+            assert_eq!(data_array(&xml, name).len(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn wiring_cells_are_ports_then_wires_then_components() {
+        let mut wiring = Wiring3D::default();
+        wiring.insert(
+            ((0, 0, 0), (2, 0, 0)),
+            Wire {
+                resistance: 3.0,
+                radius: Some(0.25),
+            },
+        );
+        wiring.ports.insert((0, 0, 0), Port("in".into()));
+        wiring.components.insert(
+            ((2, 0, 0), (2, 1, 0)),
+            LumpedComponent {
+                component: TwoTerminalComponent::Resistor(50.0),
+                reversed: false,
+            },
+        );
+        let xml = to_string(|w| write_wiring(w, &wiring, 2.0));
+
+        assert!(xml.contains(r#"NumberOfPoints="5" NumberOfVerts="1" NumberOfLines="2""#));
+        assert_eq!(data_array(&xml, "Kind"), ["0", "1", "2"]);
+        assert_eq!(data_array(&xml, "Resistance"), ["-1", "3", "-1"]);
+        assert_eq!(data_array(&xml, "Radius"), ["-1", "0.25", "-1"]);
+        // The wire's end is scaled by dx
+        assert!(xml.contains("\n4 0 0\n"));
+    }
+
+    #[test]
+    fn time_series_writes_every_n_steps() {
+        let dir = std::env::temp_dir().join(format!("fdtd_vtk_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let cfg = FdtdSimConfig::default();
+        let mut sim = FdtdSim::new(3);
+        let current = Array4::zeros((3, 3, 3, 3));
+        let mut series = TimeSeries::new(&dir, "run", 2);
+        for step in 0..5 {
+            series.record(&sim, &cfg, step as f64 * 0.25).unwrap();
+            sim.step(&cfg, &current, &current);
+        }
+
+        assert_eq!(series.len(), 3);
+        let pvd = std::fs::read_to_string(dir.join("run.pvd")).unwrap();
+        assert!(pvd.contains(r#"timestep="0.5" part="0" file="run_000002.vti""#));
+        assert!(dir.join("run_000004.vti").exists());
+        assert!(!dir.join("run_000001.vti").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
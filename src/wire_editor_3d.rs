@@ -1,88 +1,233 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use cirmcut::{circuit_widget::VisualizationOptions, cirmcut_sim::SimOutputs};
-use egui::{Color32, DragValue, Pos2, Stroke, Ui, Vec2};
+use cirmcut::{
+    circuit_widget::VisualizationOptions,
+    cirmcut_sim::{SimOutputs, TwoTerminalComponent},
+};
+use egui::{Color32, DragValue, Pos2, RichText, Stroke, Ui, Vec2};
 use threegui::{Painter3D, ThreeUi};
 
 use crate::{
     common::{IntPos3, espacet},
+    generators::StructureGenerator,
+    nets::Nets,
     node_map::NodeMap,
+    picking::{WireIndex, closest_candidate, pick_candidates, plane_candidates_around},
+    sim::{THIN_WIRE_EQUIVALENT_RADIUS, ThinWireEdge},
 };
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
 pub struct Wire {
-    /// Ohms
+    /// Ohms, per unit edge of the segment
     pub resistance: f64,
+    /// Meters. Drives the thin-wire correction in the FDTD grid. `None` is the equivalent
+    /// radius of an uncorrected one-cell wire, so documents saved before wires had a radius
+    /// simulate as they always did.
+    #[serde(default)]
+    pub radius: Option<f64>,
 }
 
-const DEFAULT_WIRE: Wire = Wire { resistance: 1.0 };
+const DEFAULT_WIRE: Wire = Wire {
+    resistance: 1.0,
+    radius: None,
+};
+
+/// Radius offered when the thin-wire correction is first enabled on a wire
+const DEFAULT_WIRE_RADIUS: f64 = 0.1;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Port(pub String);
 
+/// A circuit component placed directly on a unit edge of the grid.
+/// Its current drives the FDTD grid like a wire's, but the field along
+/// the edge is not fed back into the component.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct LumpedComponent {
+    pub component: TwoTerminalComponent,
+    /// If true, the component's first terminal is the greater endpoint of the edge
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+/// Components which may be placed on a grid edge, with their default values
+const LUMPED_COMPONENTS: [(&str, TwoTerminalComponent); 7] = [
+    ("Resistor", TwoTerminalComponent::Resistor(1000.0)),
+    ("Inductor", TwoTerminalComponent::Inductor(1.0, None)),
+    ("Capacitor", TwoTerminalComponent::Capacitor(10e-6)),
+    ("Diode", TwoTerminalComponent::Diode),
+    ("Battery", TwoTerminalComponent::Battery(5.0)),
+    ("Switch", TwoTerminalComponent::Switch(true)),
+    ("Current source", TwoTerminalComponent::CurrentSource(0.1)),
+];
+
+/// A straight segment between two grid points. The segment need not be
+/// axis-aligned or one cell long; see [`segment_edges`].
 pub type WireId = (IntPos3, IntPos3);
 
 #[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
 pub struct Wiring3D {
     pub wires: HashMap<WireId, Wire>,
     pub ports: HashMap<IntPos3, Port>,
+    /// Keyed by normalized unit edge
+    #[serde(default)]
+    pub components: HashMap<WireId, LumpedComponent>,
 }
 
 pub struct WireEditor3D {
     sel_pos: Option<Selection>,
-    undo: Option<Wiring3D>,
+    /// Screen position where a box selection drag started
+    box_start: Option<Pos2>,
+    /// Wiring copied with Ctrl+C, relative to its minimum corner
+    clipboard: Option<Wiring3D>,
+    /// Point which rotations and mirrors of the selection are about, in half cells,
+    /// so that it can sit midway between grid points
+    pivot: [isize; 3],
+    /// Whether rotations and mirrors add a transformed copy instead of moving the selection
+    transform_copy: bool,
+    /// Why the last paste, move, rotation or mirror could not be applied
+    edit_error: Option<String>,
+    /// Description of the last edit, for the document history
+    last_action: Option<String>,
+    /// Bumped on every edit of the wiring, by this editor or through `wiring_changed`
+    wiring_revision: u64,
+    /// Revision of the wiring `nets` and `wire_index` were built from
+    cached_revision: Option<u64>,
+    /// Nets of the wiring, rebuilt when the view is drawn after the wiring changed
+    nets: Nets,
+    /// Spatial index for picking wires, rebuilt alongside `nets`
+    wire_index: WireIndex,
+    /// Net under the cursor, as of the last frame
+    hovered_net: Option<usize>,
+    /// Created when the generator UI is first opened
+    generator: Option<StructureGenerator>,
+    /// Structure shown in the view while the generator UI is open
+    preview: Option<Wiring3D>,
+    /// Picking and drawing are restricted to this plane, if set
+    plane: Option<WorkingPlane>,
 }
 
-#[derive(Clone, Copy)]
+/// The grid points whose coordinate along `axis` equals `index`
+#[derive(Clone, Copy, PartialEq)]
+pub struct WorkingPlane {
+    pub axis: usize,
+    pub index: usize,
+}
+
+impl WorkingPlane {
+    const NAMES: [&str; 3] = ["YZ", "XZ", "XY"];
+
+    pub fn contains(&self, pos: IntPos3) -> bool {
+        [pos.0, pos.1, pos.2][self.axis] == self.index
+    }
+
+    pub fn contains_wire(&self, (a, b): WireId) -> bool {
+        self.contains(a) && self.contains(b)
+    }
+
+    /// Maps in-plane coordinates (u, v) to a grid point
+    pub fn point(&self, u: usize, v: usize) -> IntPos3 {
+        let mut p = [0; 3];
+        p[self.axis] = self.index;
+        p[(self.axis + 1) % 3] = u;
+        p[(self.axis + 2) % 3] = v;
+        (p[0], p[1], p[2])
+    }
+
+    fn draw_grid(&self, width: usize, paint: &Painter3D) {
+        let stroke = Stroke::new(1.0, Color32::from_gray(60));
+        let last = width.saturating_sub(1);
+        for i in 0..width {
+            paint.line(
+                espacet(width, self.point(i, 0)),
+                espacet(width, self.point(i, last)),
+                stroke,
+            );
+            paint.line(
+                espacet(width, self.point(0, i)),
+                espacet(width, self.point(last, i)),
+                stroke,
+            );
+        }
+    }
+}
+
+#[derive(Clone)]
 enum Selection {
     Position(IntPos3),
     WireId((IntPos3, IntPos3)),
+    Group(Group),
+}
+
+/// Wires, lumped components and ports selected together
+#[derive(Clone, Default)]
+struct Group {
+    /// Keys into either `Wiring3D::wires` or `Wiring3D::components`
+    wires: HashSet<WireId>,
+    ports: HashSet<IntPos3>,
 }
 
 impl Default for WireEditor3D {
     fn default() -> Self {
         Self {
             sel_pos: None,
-            undo: None,
+            box_start: None,
+            clipboard: None,
+            pivot: [0; 3],
+            transform_copy: false,
+            edit_error: None,
+            last_action: None,
+            wiring_revision: 0,
+            cached_revision: None,
+            nets: Nets::default(),
+            wire_index: WireIndex::default(),
+            hovered_net: None,
+            generator: None,
+            preview: None,
+            plane: None,
         }
     }
 }
 
-fn find_closest_grid_point_screenspace(
+/// Scans every point of the working plane, for when the plane can't be unprojected
+fn find_closest_plane_point_screenspace(
     width: usize,
     paint: &Painter3D,
     screen_pos: Pos2,
-) -> Option<(IntPos3, f32)> {
+    plane: WorkingPlane,
+) -> Option<IntPos3> {
     let mut closest = None;
     let mut closest_dist = 99e9;
 
-    for i in 0..width {
-        for j in 0..width {
-            for k in 0..width {
-                if let Some(pt_pos) = paint.transform(espacet(width, (i, j, k))) {
-                    let dist = pt_pos.distance(screen_pos);
-                    if dist < closest_dist {
-                        closest_dist = dist;
-                        closest = Some((i, j, k));
-                    }
+    for u in 0..width {
+        for v in 0..width {
+            let pos = plane.point(u, v);
+            if let Some(pt_pos) = paint.transform(espacet(width, pos)) {
+                let dist = pt_pos.distance(screen_pos);
+                if dist < closest_dist {
+                    closest_dist = dist;
+                    closest = Some(pos);
                 }
             }
         }
     }
 
-    closest.map(|c| (c, closest_dist))
+    closest
 }
 
 fn find_closest_wire_screenspace(
     width: usize,
-    wiring: &Wiring3D,
+    wire_ids: impl IntoIterator<Item = WireId>,
     paint: &Painter3D,
     screen_pos: Pos2,
+    plane: Option<WorkingPlane>,
 ) -> Option<(WireId, f32)> {
     let mut closest = None;
     let mut closest_dist = 99e9;
 
-    for &wire_id in wiring.wires.keys() {
+    for wire_id in wire_ids {
+        if plane.is_some_and(|plane| !plane.contains_wire(wire_id)) {
+            continue;
+        }
         if let Some(dist) = screenspace_wire_dist(wire_id, paint, width, screen_pos) {
             if dist < closest_dist {
                 closest_dist = dist;
@@ -95,31 +240,135 @@ fn find_closest_wire_screenspace(
 }
 
 impl WireEditor3D {
+    /// Selects a grid point, e.g. from the port overview
+    pub fn select_position(&mut self, pos: IntPos3) {
+        self.sel_pos = Some(Selection::Position(pos));
+    }
+
+    /// The selected wire or lumped component, if exactly one is selected
+    pub fn selected_wire(&self) -> Option<WireId> {
+        match &self.sel_pos {
+            Some(Selection::WireId(wire_id)) => Some(*wire_id),
+            _ => None,
+        }
+    }
+
+    /// Port names on the net under the cursor and the net's colour, if any
+    pub fn hovered_ports(&self) -> Option<(&[String], Color32)> {
+        let net = self.hovered_net?;
+        Some((&self.nets.ports[net], self.nets.color(net)))
+    }
+
+    /// Describes the edit about to be made, for the document history
+    fn record(&mut self, action: impl Into<String>) {
+        self.last_action = Some(action.into());
+        self.wiring_changed();
+    }
+
+    /// Must be called when the wiring is replaced or edited outside this editor (e.g. undo),
+    /// so the nets and picking index are rebuilt
+    pub fn wiring_changed(&mut self) {
+        self.wiring_revision += 1;
+    }
+
+    pub fn wiring_revision(&self) -> u64 {
+        self.wiring_revision
+    }
+
+    fn update_caches(&mut self, wiring: &Wiring3D) {
+        if self.cached_revision != Some(self.wiring_revision) {
+            self.nets = Nets::new(wiring);
+            self.wire_index = WireIndex::new(wiring);
+            self.cached_revision = Some(self.wiring_revision);
+        }
+    }
+
+    /// Description of the last edit since this was last called
+    pub fn take_last_action(&mut self) -> Option<String> {
+        self.last_action.take()
+    }
+
     /// Returns true if the edit was destructive
     pub fn edit(&mut self, width: usize, thr: &ThreeUi, wiring: &mut Wiring3D) -> bool {
         let paint = thr.painter();
 
         // Draw wiring
-        wiring.draw(width, paint);
+        self.update_caches(wiring);
+        if let Some(plane) = self.plane {
+            plane.draw_grid(width, paint);
+        }
+        wiring.draw(width, paint, &self.nets, self.hovered_net, self.plane);
+        self.hovered_net = None;
+
+        if let Some(preview) = &self.preview {
+            preview.draw_preview(width, paint);
+        }
 
         // Projecting the cursor
         let Some(cursor_pos) = paint.egui().ctx().input(|r| r.pointer.latest_pos()) else {
             return false;
         };
 
+        // Only points and wires near the cursor ray are tested
+        let candidates = match (
+            pick_candidates(width, paint, cursor_pos, self.plane),
+            self.plane,
+        ) {
+            (Some(candidates), _) => candidates,
+            // A working plane seen edge-on is small enough to scan
+            (None, Some(plane)) => {
+                let Some(pos) =
+                    find_closest_plane_point_screenspace(width, paint, cursor_pos, plane)
+                else {
+                    return false;
+                };
+                plane_candidates_around(width, plane, pos)
+            }
+            // The grid isn't in view under the cursor
+            (None, None) => return false,
+        };
+
         let Some((cursor_pos_3d, cursor_grid_dist)) =
-            find_closest_grid_point_screenspace(width, paint, cursor_pos)
+            closest_candidate(width, paint, cursor_pos, &candidates)
         else {
             return false;
         };
 
         // Finding the nearest wire
-        let closest_wire = find_closest_wire_screenspace(width, wiring, paint, cursor_pos);
+        let near = self.wire_index.near(&candidates);
+        let closest_wire =
+            find_closest_wire_screenspace(width, near, paint, cursor_pos, self.plane);
 
         let cursor_circle_size = 10.0;
 
         let cursor_color = Color32::GREEN;
 
+        let ctrl = thr.resp.ctx.input(|r| r.modifiers.ctrl);
+
+        // Box selection (ctrl + drag)
+        if thr.resp.drag_started() && ctrl {
+            self.box_start = thr.resp.interact_pointer_pos();
+        }
+        if let Some(start) = self.box_start {
+            let rect = egui::Rect::from_two_pos(start, cursor_pos);
+            paint.egui().rect_stroke(
+                rect,
+                0.0,
+                Stroke::new(1.0, Color32::YELLOW),
+                egui::StrokeKind::Inside,
+            );
+
+            if !thr.resp.dragged() {
+                self.box_start = None;
+                let group = wiring.select_in_rect(width, paint, rect);
+                if let Some(center) = wiring.extract(&group).half_cell_center() {
+                    self.pivot = center;
+                }
+                self.sel_pos = Some(Selection::Group(group));
+            }
+            return false;
+        }
+
         // If the wire is closer...
         if let Some((wire_id, wire_dist)) = closest_wire
             && wire_dist < cursor_grid_dist
@@ -127,9 +376,14 @@ impl WireEditor3D {
             let stroke = Stroke::new(1.0, cursor_color);
             let (a, b) = wire_id;
             paint.line(espacet(width, a), espacet(width, b), stroke);
+            self.hovered_net = self.nets.net_of_wire(wire_id);
 
             if thr.resp.clicked() {
-                self.sel_pos = Some(Selection::WireId(wire_id));
+                if ctrl {
+                    self.toggle_in_group(wire_id);
+                } else {
+                    self.sel_pos = Some(Selection::WireId(wire_id));
+                }
             }
         } else {
             // If the grid is closer...
@@ -138,13 +392,13 @@ impl WireEditor3D {
                 cursor_circle_size,
                 (1.0, cursor_color),
             );
+            self.hovered_net = self.nets.net_of.get(&cursor_pos_3d).copied();
 
             if thr.resp.clicked() {
                 if thr.resp.ctx.input(|r| r.modifiers.shift) {
-                    self.undo = Some(wiring.clone());
-                    self.line_to_selection(cursor_pos_3d, wiring, DEFAULT_WIRE);
+                    let added = self.line_to_selection(cursor_pos_3d, wiring, DEFAULT_WIRE);
                     self.sel_pos = Some(Selection::Position(cursor_pos_3d));
-                    return true;
+                    return added;
                 } else {
                     self.sel_pos = Some(Selection::Position(cursor_pos_3d));
                     return false;
@@ -152,24 +406,42 @@ impl WireEditor3D {
             }
         }
 
-        // Undo
-        if thr
-            .resp
-            .ctx
-            .input(|r| r.modifiers.ctrl && r.key_released(egui::Key::Z))
-        {
-            if let Some(state) = self.undo.take() {
-                *wiring = state;
-                return true;
-            }
+        // Label the hovered net
+        if let Some(net) = self.hovered_net {
+            paint.egui().text(
+                cursor_pos + Vec2::new(12.0, -12.0),
+                egui::Align2::LEFT_BOTTOM,
+                &self.nets.names[net],
+                Default::default(),
+                self.nets.color(net),
+            );
         }
 
         // Delete
         if thr.resp.ctx.input(|r| r.key_released(egui::Key::Delete)) {
-            if let Some(Selection::WireId(wire_id)) = self.sel_pos {
-                self.undo = Some(wiring.clone());
-                wiring.wires.remove(&wire_id);
-                self.sel_pos = None;
+            match self.sel_pos.take() {
+                Some(Selection::WireId(wire_id)) => {
+                    self.record("Delete wire");
+                    wiring.wires.remove(&wire_id);
+                    wiring.components.remove(&wire_id);
+                    return true;
+                }
+                Some(Selection::Group(group)) => {
+                    self.record("Delete selection");
+                    wiring.remove_group(&group);
+                    return true;
+                }
+                other => self.sel_pos = other,
+            }
+        }
+
+        // Clipboard and moving the selection, only while the view is hovered
+        if thr.resp.contains_pointer() {
+            self.handle_plane_keys(thr, width);
+            if self.handle_clipboard(thr, wiring, width, cursor_pos_3d) {
+                return true;
+            }
+            if self.handle_move_keys(thr, wiring, width) {
                 return true;
             }
         }
@@ -177,14 +449,21 @@ impl WireEditor3D {
         // Draw selection
         let selection_stroke = Stroke::new(1.0, Color32::YELLOW);
 
-        if let Some(selection) = self.sel_pos {
+        if let Some(selection) = &self.sel_pos {
             match selection {
                 Selection::Position(pos) => {
-                    paint.circle(espacet(width, pos), cursor_circle_size, selection_stroke);
+                    paint.circle(espacet(width, *pos), cursor_circle_size, selection_stroke);
                 }
-                Selection::WireId(wire_id) => {
-                    let (a, b) = wire_id;
-                    paint.line(espacet(width, a), espacet(width, b), selection_stroke);
+                Selection::WireId((a, b)) => {
+                    paint.line(espacet(width, *a), espacet(width, *b), selection_stroke);
+                }
+                Selection::Group(group) => {
+                    for (a, b) in &group.wires {
+                        paint.line(espacet(width, *a), espacet(width, *b), selection_stroke);
+                    }
+                    for pos in &group.ports {
+                        paint.circle(espacet(width, *pos), cursor_circle_size, selection_stroke);
+                    }
                 }
             }
         }
@@ -192,19 +471,309 @@ impl WireEditor3D {
         return false;
     }
 
-    pub fn draw_current(
+    /// Adds or removes a wire from the group selection, starting one if needed
+    fn toggle_in_group(&mut self, wire_id: WireId) {
+        let mut group = match self.sel_pos.take() {
+            Some(Selection::Group(group)) => group,
+            Some(Selection::WireId(other)) => Group {
+                wires: [other].into(),
+                ports: HashSet::new(),
+            },
+            _ => Group::default(),
+        };
+
+        if !group.wires.remove(&wire_id) {
+            group.wires.insert(wire_id);
+        }
+        self.sel_pos = Some(Selection::Group(group));
+    }
+
+    /// Ctrl+C copies the selection, Ctrl+V pastes at the cursor.
+    /// Returns true if the wiring changed.
+    fn handle_clipboard(
         &mut self,
         thr: &ThreeUi,
         wiring: &mut Wiring3D,
+        width: usize,
+        cursor: IntPos3,
+    ) -> bool {
+        let (copy, paste) = thr.resp.ctx.input(|r| {
+            let copy = r.events.iter().any(|e| matches!(e, egui::Event::Copy));
+            let paste = r.events.iter().find_map(|e| match e {
+                egui::Event::Paste(text) => Some(Some(text.clone())),
+                egui::Event::Key {
+                    key: egui::Key::V,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } if modifiers.command => Some(None),
+                _ => None,
+            });
+            (copy, paste)
+        });
+
+        if copy {
+            let fragment = match &self.sel_pos {
+                Some(Selection::Group(group)) => Some(wiring.extract(group)),
+                Some(Selection::WireId(wire_id)) => Some(wiring.extract(&Group {
+                    wires: [*wire_id].into(),
+                    ports: HashSet::new(),
+                })),
+                _ => None,
+            };
+
+            if let Some(fragment) = fragment
+                && let Some((min, _)) = fragment.bounds()
+            {
+                let origin = [min.0, min.1, min.2].map(|x| -(x as isize));
+                let fragment = fragment.translated_unbounded(origin);
+                if let Ok(text) = ron::to_string(&fragment) {
+                    thr.resp.ctx.copy_text(text);
+                }
+                self.clipboard = fragment;
+            }
+        }
+
+        if let Some(text) = paste {
+            // Prefer the system clipboard, so wiring can be pasted between windows
+            let fragment = text
+                .and_then(|text| ron::from_str::<Wiring3D>(&text).ok())
+                .or_else(|| self.clipboard.clone());
+
+            let offset = [cursor.0, cursor.1, cursor.2].map(|x| x as isize);
+            if let Some(pasted) = fragment.and_then(|f| f.translated(width, offset)) {
+                if wiring.overlaps(&pasted) {
+                    self.edit_error = Some("Paste: would overlap existing wiring".into());
+                    return false;
+                }
+                self.edit_error = None;
+                self.record("Paste");
+                self.sel_pos = Some(Selection::Group(pasted.group()));
+                wiring.merge(pasted);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// PageUp/PageDown move the working plane along its axis
+    fn handle_plane_keys(&mut self, thr: &ThreeUi, width: usize) {
+        let Some(plane) = &mut self.plane else {
+            return;
+        };
+        thr.resp.ctx.input(|r| {
+            if r.key_pressed(egui::Key::PageUp) && plane.index + 1 < width {
+                plane.index += 1;
+            }
+            if r.key_pressed(egui::Key::PageDown) {
+                plane.index = plane.index.saturating_sub(1);
+            }
+        });
+    }
+
+    /// Working plane toggle, orientation and position
+    fn show_plane_ui(&mut self, ui: &mut Ui, width: usize) {
+        let mut enabled = self.plane.is_some();
+        ui.checkbox(&mut enabled, "Working plane");
+        if !enabled {
+            self.plane = None;
+            return;
+        }
+
+        let plane = self.plane.get_or_insert(WorkingPlane {
+            axis: 2,
+            index: width / 2,
+        });
+        ui.horizontal(|ui| {
+            for (axis, name) in WorkingPlane::NAMES.into_iter().enumerate() {
+                ui.selectable_value(&mut plane.axis, axis, name);
+            }
+            ui.add(
+                DragValue::new(&mut plane.index)
+                    .range(0..=width.saturating_sub(1))
+                    .prefix(["x", "y", "z"][plane.axis].to_string() + " = "),
+            );
+        });
+        ui.label(
+            "PageUp/PageDown move the plane. Only points and wires in the plane can be picked.",
+        );
+    }
+
+    /// Arrow keys move the selection along x and y; with shift, up/down move along z.
+    /// Returns true if the wiring changed.
+    fn handle_move_keys(&mut self, thr: &ThreeUi, wiring: &mut Wiring3D, width: usize) -> bool {
+        let offset = thr.resp.ctx.input(|r| {
+            let mut offset = [0_isize; 3];
+            let up_down_axis = if r.modifiers.shift { 2 } else { 1 };
+            if r.key_pressed(egui::Key::ArrowLeft) {
+                offset[0] -= 1;
+            }
+            if r.key_pressed(egui::Key::ArrowRight) {
+                offset[0] += 1;
+            }
+            if r.key_pressed(egui::Key::ArrowUp) {
+                offset[up_down_axis] += 1;
+            }
+            if r.key_pressed(egui::Key::ArrowDown) {
+                offset[up_down_axis] -= 1;
+            }
+            offset
+        });
+
+        if offset == [0; 3] {
+            return false;
+        }
+
+        self.transform_selection(wiring, "Move selection", false, |w| {
+            w.translated(width, offset)
+        })
+    }
+
+    /// The selection as a group, if it contains any wires or ports
+    fn selected_group(&self) -> Option<Group> {
+        match &self.sel_pos {
+            Some(Selection::Group(group)) => Some(group.clone()),
+            Some(Selection::WireId(wire_id)) => Some(Group {
+                wires: [*wire_id].into(),
+                ports: HashSet::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Replaces the selection with its image under `transform`, which returns
+    /// `None` if the result would leave the grid, or adds the image next to it if
+    /// `keep_original` is set. Refuses if the result would overlap other wiring.
+    /// The image becomes the selection. Returns true if the wiring changed.
+    fn transform_selection(
+        &mut self,
+        wiring: &mut Wiring3D,
+        action: &str,
+        keep_original: bool,
+        transform: impl FnOnce(&Wiring3D) -> Option<Wiring3D>,
+    ) -> bool {
+        let Some(group) = self.selected_group() else {
+            return false;
+        };
+
+        let Some(transformed) = transform(&wiring.extract(&group)) else {
+            self.edit_error = Some(format!("{action}: result would leave the grid"));
+            return false;
+        };
+
+        let mut rest = wiring.clone();
+        if !keep_original {
+            rest.remove_group(&group);
+        }
+        if rest.overlaps(&transformed) {
+            self.edit_error = Some(format!("{action}: result would overlap existing wiring"));
+            return false;
+        }
+
+        self.edit_error = None;
+        self.record(action);
+        *wiring = rest;
+        self.sel_pos = Some(Selection::Group(transformed.group()));
+        wiring.merge(transformed);
+        true
+    }
+
+    fn show_transform_ui(&mut self, ui: &mut Ui, width: usize, wiring: &mut Wiring3D) -> bool {
+        let mut changed = false;
+        let max = width.saturating_sub(1);
+
+        // The pivot is edited in cells and snapped to the nearest half cell
+        ui.horizontal(|ui| {
+            ui.label("Pivot: ");
+            for (c, name) in self.pivot.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                let mut cells = *c as f64 / 2.0;
+                let resp = ui.add(
+                    DragValue::new(&mut cells)
+                        .range(0.0..=max as f64)
+                        .speed(0.5)
+                        .fixed_decimals(1)
+                        .prefix(name),
+                );
+                if resp.changed() {
+                    *c = (cells * 2.0).round() as isize;
+                }
+            }
+        });
+        if ui.button("Pivot at center of selection").clicked()
+            && let Some(center) = self
+                .selected_group()
+                .and_then(|group| wiring.extract(&group).half_cell_center())
+        {
+            self.pivot = center;
+        }
+
+        ui.checkbox(&mut self.transform_copy, "Transform a copy")
+            .on_hover_text("Otherwise the selection is replaced in place by its transformed image");
+
+        let pivot = self.pivot;
+        let copy = self.transform_copy;
+        let axes = ["X", "Y", "Z"];
+
+        ui.horizontal(|ui| {
+            ui.label("Mirror across: ");
+            for (axis, name) in axes.iter().enumerate() {
+                if ui.button(*name).clicked() {
+                    changed |= self.transform_selection(wiring, "Mirror selection", copy, |w| {
+                        w.map_positions(width, |p| mirror(p, pivot, axis))
+                    });
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Rotate 90° about: ");
+            for (axis, name) in axes.iter().enumerate() {
+                for (quarter_turns, sign) in [(1, "+"), (3, "-")] {
+                    if !ui.button(format!("{sign}{name}")).clicked() {
+                        continue;
+                    }
+                    if !rotation_stays_on_grid(pivot, axis) {
+                        self.edit_error = Some(
+                            "Rotate selection: the pivot must be on a grid point or a cell \
+                             center in the plane of rotation"
+                                .into(),
+                        );
+                        continue;
+                    }
+                    changed |= self.transform_selection(wiring, "Rotate selection", copy, |w| {
+                        w.map_positions(width, |p| rotate90(p, pivot, axis, quarter_turns))
+                    });
+                }
+            }
+        });
+
+        changed
+    }
+
+    pub fn draw_current(
+        &mut self,
+        thr: &ThreeUi,
         nodemap: &NodeMap,
         soln: &SimOutputs,
         width: usize,
         vis: &VisualizationOptions,
     ) {
         let time = thr.painter().egui().ctx().input(|r| r.time);
-        for wire_id in wiring.wires.keys() {
-            let component_idx = nodemap.component_idx_map[wire_id];
-            let current = soln.two_terminal_current[component_idx];
+        let lumped = nodemap
+            .lumped_idx_map
+            .iter()
+            .map(|(wire_id, &(idx, reversed))| (wire_id, idx, reversed));
+        let wires = nodemap
+            .component_idx_map
+            .iter()
+            .map(|(wire_id, &idx)| (wire_id, idx, false));
+
+        for (wire_id, component_idx, reversed) in wires.chain(lumped) {
+            let mut current = soln.two_terminal_current[component_idx];
+            if reversed {
+                current = -current;
+            }
 
             if current == 0.0 {
                 continue;
@@ -235,9 +804,45 @@ impl WireEditor3D {
         }
     }
 
-    pub fn show_ui(&mut self, ui: &mut Ui, width: usize, wiring: &mut Wiring3D) -> bool {
+    pub fn show_ui(
+        &mut self,
+        ui: &mut Ui,
+        width: usize,
+        wiring: &mut Wiring3D,
+        nodemap: &NodeMap,
+    ) -> bool {
         let mut rebuild_sim = false;
 
+        // Taken for the duration, as the edits below borrow self mutably
+        let nets = std::mem::take(&mut self.nets);
+        let net_label = |ui: &mut Ui, net: Option<usize>| {
+            let Some(net) = net else {
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                ui.label("Net: ");
+                ui.label(RichText::new(&nets.names[net]).color(nets.color(net)));
+            });
+
+            let schematic: Vec<String> = nets.ports[net]
+                .iter()
+                .filter_map(|name| {
+                    let count = nodemap.schematic_ports.get(name)?;
+                    Some(format!("{name} ({count} nodes)"))
+                })
+                .collect();
+            if !schematic.is_empty() {
+                ui.label(format!("Schematic ports: {}", schematic.join(", ")));
+            }
+        };
+
+        if let Some(net) = self.hovered_net {
+            ui.strong("Hovered");
+            net_label(ui, Some(net));
+            ui.separator();
+        }
+
         ui.strong("Wires");
 
         /*
@@ -256,35 +861,133 @@ impl WireEditor3D {
             }
         }
         */
-        ui.label("To add a wire: select a point, then hold shift and select another point.");
+        ui.label("To add a wire: select a point, then hold shift and select another point. Diagonal wires are routed as a staircase along the grid.");
+        ui.label("Ctrl+drag to box select, ctrl+click to add or remove a wire from the selection.");
+        self.show_plane_ui(ui, width);
         ui.separator();
 
         if let Some(Selection::WireId(wire_id)) = self.sel_pos {
             ui.strong("Editing wire");
-            if let Some(wire) = wiring.wires.get_mut(&wire_id) {
-                wire.show_ui(ui);
+            net_label(ui, nets.net_of_wire(wire_id));
+            if let Some(&wire) = wiring.wires.get(&wire_id) {
+                ui.label(format!("Length: {} cells", segment_edges(wire_id).len()));
+                let mut edited = wire;
+                rebuild_sim |= edited.show_ui(ui);
+                if edited != wire {
+                    self.record("Edit wire");
+                    wiring.wires.insert(wire_id, edited);
+                }
+
                 if ui.button("Delete").clicked() {
+                    self.record("Delete wire");
                     wiring.wires.remove(&wire_id);
                     self.sel_pos = None;
                     rebuild_sim = true;
                 }
+
+                if segment_edges(wire_id).len() == 1 {
+                    ui.label("Replace with lumped component:");
+                    ui.horizontal_wrapped(|ui| {
+                        for (name, component) in LUMPED_COMPONENTS {
+                            if ui.button(name).clicked() {
+                                if wiring.edge_shared(wire_id) {
+                                    self.edit_error = Some(format!(
+                                        "Place {name}: another wire covers this edge"
+                                    ));
+                                    continue;
+                                }
+                                self.edit_error = None;
+                                self.record(format!("Place {name}"));
+                                wiring.wires.remove(&wire_id);
+                                wiring.components.insert(
+                                    wire_id,
+                                    LumpedComponent {
+                                        component,
+                                        reversed: false,
+                                    },
+                                );
+                                rebuild_sim = true;
+                            }
+                        }
+                    });
+                }
+            } else if let Some(lumped) = wiring.components.get(&wire_id) {
+                ui.strong("Editing lumped component");
+                let mut edited = lumped.clone();
+                if edited.show_ui(ui) {
+                    rebuild_sim |= edited.reversed != lumped.reversed;
+                    self.record("Edit component");
+                    wiring.components.insert(wire_id, edited);
+                }
+                if ui.button("Replace with wire").clicked() {
+                    self.record("Replace component with wire");
+                    wiring.components.remove(&wire_id);
+                    wiring.insert(wire_id, DEFAULT_WIRE);
+                    rebuild_sim = true;
+                }
+                if ui.button("Delete").clicked() {
+                    self.record("Delete component");
+                    wiring.components.remove(&wire_id);
+                    self.sel_pos = None;
+                    rebuild_sim = true;
+                }
             }
         }
 
+        if let Some(Selection::Group(group)) = self.sel_pos.clone() {
+            ui.strong("Selection");
+            ui.label(format!(
+                "{} wires/components, {} ports",
+                group.wires.len(),
+                group.ports.len()
+            ));
+            ui.label("Ctrl+C/Ctrl+V to copy and paste at the cursor. Arrow keys move along x/y, shift+up/down along z.");
+            ui.label("Pasting or moving onto existing wiring is refused.");
+            if ui.button("Delete").clicked() {
+                self.record("Delete selection");
+                wiring.remove_group(&group);
+                self.sel_pos = None;
+                rebuild_sim = true;
+            }
+        }
+
+        if self.selected_group().is_some() {
+            ui.collapsing("Rotate / mirror", |ui| {
+                rebuild_sim |= self.show_transform_ui(ui, width, wiring);
+            });
+        }
+
+        if let Some(error) = &self.edit_error {
+            ui.label(RichText::new(error).color(Color32::RED));
+        }
+
+        self.preview = None;
+        ui.collapsing("Generate structure", |ui| {
+            rebuild_sim |= self.show_generator_ui(ui, width, wiring);
+        });
+
         if let Some(Selection::Position(pos)) = self.sel_pos {
             ui.strong("Editing node");
-            if let Some(port) = wiring.ports.get_mut(&pos) {
+            net_label(ui, nets.net_of.get(&pos).copied());
+            if let Some(port) = wiring.ports.get(&pos) {
+                let mut name = port.0.clone();
                 ui.horizontal(|ui| {
                     ui.label("Port: ");
-                    ui.text_edit_singleline(&mut port.0);
-                    rebuild_sim = true;
+                    ui.text_edit_singleline(&mut name);
                 });
+                if name != port.0 {
+                    self.record("Rename port");
+                    wiring.ports.insert(pos, Port(name));
+                    rebuild_sim = true;
+                }
                 if ui.button("Delete").clicked() {
+                    self.record("Delete port");
                     wiring.ports.remove(&pos);
                     rebuild_sim = true;
                 }
             } else {
                 if ui.button("Add port").clicked() {
+                    self.record("Add port");
                     wiring.ports.insert(pos, Port("New port".into()));
                     rebuild_sim = true;
                 }
@@ -293,34 +996,73 @@ impl WireEditor3D {
 
         ui.separator();
 
+        self.nets = nets;
         rebuild_sim
     }
 
-    fn line_to_selection(&mut self, start: IntPos3, wiring: &mut Wiring3D, wire: Wire) {
-        let Some(Selection::Position(end)) = self.sel_pos else {
-            return;
-        };
-
-        let (sx, sy, sz) = start;
-        let (ex, ey, ez) = end;
+    /// Parameters for a generated structure, previewed until it is inserted
+    fn show_generator_ui(&mut self, ui: &mut Ui, width: usize, wiring: &mut Wiring3D) -> bool {
+        let sel_pos = self.sel_pos.clone();
+        let generator = self
+            .generator
+            .get_or_insert_with(|| StructureGenerator::new(width, DEFAULT_WIRE));
 
-        for x in sx.min(ex)..sx.max(ex) {
-            wiring.insert(((x, sy, sz), (x + 1, sy, sz)), wire);
+        if let Some(Selection::Position(pos)) = sel_pos
+            && ui.button("Center on selected node").clicked()
+        {
+            generator.center = pos;
+        }
+        generator.show_ui(ui, width);
+
+        let kind = generator.kind.name();
+        match generator.generate(width) {
+            Ok(structure) => {
+                if ui.button("Insert").clicked() {
+                    let action = format!("Generate {}", kind.to_lowercase());
+                    if wiring.overlaps(&structure) {
+                        self.edit_error = Some(format!("{action}: would overlap existing wiring"));
+                        return false;
+                    }
+                    self.edit_error = None;
+                    self.record(action);
+                    self.sel_pos = Some(Selection::Group(structure.group()));
+                    wiring.merge(structure);
+                    return true;
+                }
+                self.preview = Some(structure);
+            }
+            Err(e) => {
+                ui.colored_label(Color32::RED, e);
+            }
         }
 
-        for y in sy.min(ey)..sy.max(ey) {
-            wiring.insert(((ex, y, sz), (ex, y + 1, sz)), wire);
+        false
+    }
+
+    /// Adds a wire from the selected point, unless it would run over a lumped component.
+    /// Returns true if the wiring changed.
+    fn line_to_selection(&mut self, start: IntPos3, wiring: &mut Wiring3D, wire: Wire) -> bool {
+        let Some(Selection::Position(end)) = self.sel_pos else {
+            return false;
+        };
+        if start == end {
+            return false;
         }
 
-        for z in sz.min(ez)..sz.max(ez) {
-            wiring.insert(((ex, ey, z), (ex, ey, z + 1)), wire);
+        if wiring.covers_component((start, end)) {
+            self.edit_error = Some("Add wire: would overlap a lumped component".into());
+            return false;
         }
+        self.edit_error = None;
+        self.record("Add wire");
+        wiring.insert((start, end), wire);
+        true
     }
 }
 
 impl Wiring3D {
     pub fn insert(&mut self, pos: WireId, wire: Wire) -> Option<Wire> {
-        self.wires.insert(pos, wire)
+        self.wires.insert(normalize_wire_id(pos), wire)
     }
 
     pub fn get(&self, wire_id @ (a, b): WireId) -> Option<&Wire> {
@@ -340,16 +1082,79 @@ impl Wiring3D {
         self.wires.remove(&(b, a));
     }
 
-    pub fn draw(&self, width: usize, paint: &Painter3D) {
+    /// Draws the wiring, coloured by net. If a net is highlighted, the others are dimmed,
+    /// as is everything outside the working plane.
+    pub fn draw(
+        &self,
+        width: usize,
+        paint: &Painter3D,
+        nets: &Nets,
+        highlight: Option<usize>,
+        plane: Option<WorkingPlane>,
+    ) {
+        let off_plane = |wire_id: WireId| plane.is_some_and(|plane| !plane.contains_wire(wire_id));
+
         // Draw lines
-        let stroke = Stroke::new(1.0, Color32::GRAY);
-        for (a, b) in self.wires.keys() {
-            paint.line(espacet(width, *a), espacet(width, *b), stroke);
+        for &wire_id @ (a, b) in self.wires.keys() {
+            let net = nets.net_of_wire(wire_id);
+            let mut color = net.map(|net| nets.color(net)).unwrap_or(Color32::GRAY);
+            if off_plane(wire_id) {
+                color = color.gamma_multiply(0.3);
+            }
+            let stroke = match highlight {
+                None => Stroke::new(1.0, color),
+                Some(h) if Some(h) == net => Stroke::new(3.0, color),
+                Some(_) => Stroke::new(1.0, color.gamma_multiply(0.3)),
+            };
+            paint.line(espacet(width, a), espacet(width, b), stroke);
+        }
+
+        // Draw lumped components
+        for (&wire_id @ (a, b), lumped) in &self.components {
+            let mut color = Color32::LIGHT_BLUE;
+            if off_plane(wire_id) {
+                color = color.gamma_multiply(0.3);
+            }
+            let (a, b) = (espacet(width, a), espacet(width, b));
+            paint.line(a, b, Stroke::new(3.0, color));
+            paint.text(
+                a.lerp(b, 0.5),
+                egui::Align2::LEFT_BOTTOM,
+                lumped.label(),
+                Default::default(),
+                color,
+            );
         }
 
         // Draw ports
+        for (&pos, port) in &self.ports {
+            let mut color = Color32::ORANGE;
+            if plane.is_some_and(|plane| !plane.contains(pos)) {
+                color = color.gamma_multiply(0.3);
+            }
+            let pos = espacet(width, pos);
+            paint.circle(pos, 7.0, Stroke::new(1.0, color));
+            paint.text(
+                pos,
+                egui::Align2::RIGHT_TOP,
+                &port.0,
+                Default::default(),
+                color,
+            );
+        }
+    }
+
+    /// Draws the wiring in a single translucent colour, for structures not yet inserted
+    fn draw_preview(&self, width: usize, paint: &Painter3D) {
+        let color = Color32::from_rgb(120, 200, 255).gamma_multiply(0.6);
+        for &(a, b) in self.wires.keys().chain(self.components.keys()) {
+            paint.line(
+                espacet(width, a),
+                espacet(width, b),
+                Stroke::new(2.0, color),
+            );
+        }
         for (pos, port) in &self.ports {
-            let color = Color32::ORANGE;
             let pos = espacet(width, *pos);
             paint.circle(pos, 7.0, Stroke::new(1.0, color));
             paint.text(
@@ -379,17 +1184,468 @@ impl Wiring3D {
 
         ordered_keys
     }
+
+    /// Wires, components and ports whose points all project inside a screen rectangle
+    fn select_in_rect(&self, width: usize, paint: &Painter3D, rect: egui::Rect) -> Group {
+        let inside = |pos: IntPos3| {
+            paint
+                .transform(espacet(width, pos))
+                .is_some_and(|p| rect.contains(p))
+        };
+
+        Group {
+            wires: self
+                .wires
+                .keys()
+                .chain(self.components.keys())
+                .filter(|(a, b)| inside(*a) && inside(*b))
+                .copied()
+                .collect(),
+            ports: self.ports.keys().filter(|p| inside(**p)).copied().collect(),
+        }
+    }
+
+    /// Everything in this wiring, as a group selection
+    fn group(&self) -> Group {
+        Group {
+            wires: self
+                .wires
+                .keys()
+                .chain(self.components.keys())
+                .copied()
+                .collect(),
+            ports: self.ports.keys().copied().collect(),
+        }
+    }
+
+    /// Copies the grouped wires, components and ports into a new wiring
+    fn extract(&self, group: &Group) -> Wiring3D {
+        let mut out = Wiring3D::default();
+        for wire_id in &group.wires {
+            if let Some(wire) = self.wires.get(wire_id) {
+                out.wires.insert(*wire_id, *wire);
+            }
+            if let Some(component) = self.components.get(wire_id) {
+                out.components.insert(*wire_id, component.clone());
+            }
+        }
+        for pos in &group.ports {
+            if let Some(port) = self.ports.get(pos) {
+                out.ports.insert(*pos, port.clone());
+            }
+        }
+        out
+    }
+
+    fn remove_group(&mut self, group: &Group) {
+        for wire_id in &group.wires {
+            self.wires.remove(wire_id);
+            self.components.remove(wire_id);
+        }
+        for pos in &group.ports {
+            self.ports.remove(pos);
+        }
+    }
+
+    /// Whether a wire along `segment` would cover an edge holding a lumped component
+    pub fn covers_component(&self, segment: WireId) -> bool {
+        segment_edges(segment)
+            .into_iter()
+            .any(|edge| self.components.contains_key(&edge))
+    }
+
+    /// Whether a wire other than the unit-edge wire `edge` also covers it
+    pub fn edge_shared(&self, edge: WireId) -> bool {
+        self.wires
+            .keys()
+            .any(|&other| other != edge && segment_edges(other).contains(&edge))
+    }
+
+    /// Whether `other` would cover a unit edge or port already used here
+    pub fn overlaps(&self, other: &Wiring3D) -> bool {
+        let edges = |wiring: &Wiring3D| -> HashSet<WireId> {
+            wiring
+                .unit_edges()
+                .into_iter()
+                .map(|(edge, _)| edge)
+                .chain(wiring.components.keys().copied())
+                .collect()
+        };
+        let ours = edges(self);
+        edges(other).iter().any(|edge| ours.contains(edge))
+            || other.ports.keys().any(|pos| self.ports.contains_key(pos))
+    }
+
+    /// Adds everything from `other`, replacing anything already at the same place
+    pub fn merge(&mut self, other: Wiring3D) {
+        self.wires.extend(other.wires);
+        self.components.extend(other.components);
+        self.ports.extend(other.ports);
+    }
+
+    /// Least and greatest corner of the box containing every point, if any
+    pub fn bounds(&self) -> Option<(IntPos3, IntPos3)> {
+        let points = self
+            .wires
+            .keys()
+            .chain(self.components.keys())
+            .flat_map(|&(a, b)| [a, b])
+            .chain(self.ports.keys().copied());
+
+        points.fold(None, |acc, (x, y, z)| match acc {
+            None => Some(((x, y, z), (x, y, z))),
+            Some((min, max)) => Some((
+                (min.0.min(x), min.1.min(y), min.2.min(z)),
+                (max.0.max(x), max.1.max(y), max.2.max(z)),
+            )),
+        })
+    }
+
+    /// Moves every point through `f`. Wire endpoints are re-normalized, and lumped
+    /// components whose endpoints swap have their polarity flipped to compensate.
+    /// Returns `None` if any point lands outside a grid of the given width.
+    ///
+    /// A segment whose image would route as a different staircase (which can happen for
+    /// diagonals under rotations, see [`segment_edges`]) is split into its unit edges,
+    /// so the wiring always covers the image of the edges it covered before.
+    pub fn map_positions(
+        &self,
+        width: usize,
+        f: impl Fn([isize; 3]) -> [isize; 3],
+    ) -> Option<Wiring3D> {
+        self.map_positions_within(Some(width), f)
+    }
+
+    /// Like `map_positions`, but with no grid to stay inside when `width` is `None`;
+    /// points then only have to keep non-negative coordinates
+    fn map_positions_within(
+        &self,
+        width: Option<usize>,
+        f: impl Fn([isize; 3]) -> [isize; 3],
+    ) -> Option<Wiring3D> {
+        let map = |(x, y, z): IntPos3| -> Option<IntPos3> {
+            let [x, y, z] = f([x, y, z].map(|c| c as isize));
+            let in_bounds = |c: isize| c >= 0 && width.is_none_or(|width| (c as usize) < width);
+            (in_bounds(x) && in_bounds(y) && in_bounds(z))
+                .then_some((x as usize, y as usize, z as usize))
+        };
+
+        let mut out = Wiring3D::default();
+        for (&(a, b), wire) in &self.wires {
+            let mut edges = vec![];
+            for (p, q) in segment_edges((a, b)) {
+                edges.push(normalize_wire_id((map(p)?, map(q)?)));
+            }
+            let image = (map(a)?, map(b)?);
+
+            let mut routed = segment_edges(image);
+            routed.sort();
+            edges.sort();
+            if routed == edges {
+                out.insert(image, *wire);
+            } else {
+                for edge in edges {
+                    out.insert(edge, *wire);
+                }
+            }
+        }
+        for (&(a, b), component) in &self.components {
+            let (a, b) = (map(a)?, map(b)?);
+            let mut component = component.clone();
+            if a > b {
+                component.reversed = !component.reversed;
+            }
+            out.components.insert(normalize_wire_id((a, b)), component);
+        }
+        for (&pos, port) in &self.ports {
+            out.ports.insert(map(pos)?, port.clone());
+        }
+        Some(out)
+    }
+
+    /// Middle of `bounds()` in half cells, i.e. twice its coordinates, so it is exact
+    pub fn half_cell_center(&self) -> Option<[isize; 3]> {
+        let (min, max) = self.bounds()?;
+        Some([min.0 + max.0, min.1 + max.1, min.2 + max.2].map(|c| c as isize))
+    }
+
+    pub fn translated(&self, width: usize, offset: [isize; 3]) -> Option<Wiring3D> {
+        self.map_positions(width, |p| std::array::from_fn(|i| p[i] + offset[i]))
+    }
+
+    /// Translates without a grid to stay inside, e.g. for the clipboard
+    pub fn translated_unbounded(&self, offset: [isize; 3]) -> Option<Wiring3D> {
+        self.map_positions_within(None, |p| std::array::from_fn(|i| p[i] + offset[i]))
+    }
+
+    /// Every unit edge of a wire, with the radius the thin-wire correction should use
+    pub fn thin_wire_edges(&self, dx: f64) -> Vec<ThinWireEdge> {
+        self.unit_edges()
+            .into_iter()
+            .map(|(edge, segment)| {
+                let radius = self.wires[&segment].radius_or_equivalent(dx);
+                (edge.0, edge_axis(edge), radius)
+            })
+            .collect()
+    }
+
+    /// Every unit edge covered by the wiring, paired with the segment it belongs to.
+    /// Where segments overlap, the edge is attributed to the first segment in
+    /// `ordered_wire_ids()` order.
+    pub fn unit_edges(&self) -> Vec<(WireId, WireId)> {
+        let mut seen = HashSet::new();
+        let mut edges = vec![];
+        for segment in self.ordered_wire_ids() {
+            for edge in segment_edges(segment) {
+                if seen.insert(edge) {
+                    edges.push((edge, segment));
+                }
+            }
+        }
+        edges
+    }
+}
+
+/// Axis (0, 1 or 2) along which a normalized unit edge points.
+pub fn edge_axis((a, b): WireId) -> usize {
+    if b.0 > a.0 {
+        0
+    } else if b.1 > a.1 {
+        1
+    } else {
+        2
+    }
+}
+
+/// Reflects a point across the plane normal to `axis` through `pivot`, given in half cells.
+/// Grid points always map to grid points.
+fn mirror(mut p: [isize; 3], pivot: [isize; 3], axis: usize) -> [isize; 3] {
+    p[axis] = pivot[axis] - p[axis];
+    p
+}
+
+/// Whether rotating about the line through `pivot` (in half cells) parallel to `axis`
+/// maps grid points to grid points: the pivot must be a grid point or a cell center
+/// in the plane of rotation, i.e. both in-plane coordinates whole or both halves.
+fn rotation_stays_on_grid(pivot: [isize; 3], axis: usize) -> bool {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    (pivot[u] - pivot[v]) % 2 == 0
+}
+
+/// Rotates a point by `quarter_turns` × 90° (right-handed) about the line through
+/// `pivot` (in half cells) parallel to `axis`. See [`rotation_stays_on_grid`].
+fn rotate90(p: [isize; 3], pivot: [isize; 3], axis: usize, quarter_turns: usize) -> [isize; 3] {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+    // Offset from the pivot in half cells
+    let mut d: [isize; 3] = std::array::from_fn(|i| 2 * p[i] - pivot[i]);
+    for _ in 0..quarter_turns % 4 {
+        (d[u], d[v]) = (-d[v], d[u]);
+    }
+
+    std::array::from_fn(|i| (pivot[i] + d[i]).div_euclid(2))
+}
+
+/// Orders the endpoints of a wire so that the lesser point comes first.
+pub fn normalize_wire_id((a, b): WireId) -> WireId {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+/// Decomposes a straight segment into a staircase of unit edges along the grid axes,
+/// each normalized with [`normalize_wire_id`], in order from `a` to `b`. At every step
+/// we advance along the axis whose next cell boundary is crossed first by the ideal line.
+///
+/// The staircase is built from both ends toward the middle, so it is symmetric about the
+/// midpoint of the segment and does not depend on which endpoint comes first. Mirrored
+/// segments therefore decompose into mirrored staircases, except where an odd number of
+/// steps along two or more axes meet at the midpoint. There the middle steps are taken
+/// in axis order from the lesser endpoint.
+pub fn segment_edges((a, b): WireId) -> Vec<WireId> {
+    let start = [a.0, a.1, a.2].map(|x| x as isize);
+    let end = [b.0, b.1, b.2].map(|x| x as isize);
+    let delta: [isize; 3] = std::array::from_fn(|i| end[i] - start[i]);
+    let len = delta.map(|d| d.abs());
+
+    // Steps whose boundary crossing lies strictly before the midpoint; the same steps,
+    // reflected, lead back from the far end. The crossing "time" along the line for
+    // axis i is (2 * taken + 1) / (2 * len), compared exactly by cross-multiplying.
+    let half_steps = len.iter().map(|l| l / 2).sum::<isize>();
+    let mut taken = [0_isize; 3];
+    let mut half = Vec::with_capacity(half_steps as usize);
+    for _ in 0..half_steps {
+        let axis = (0..3)
+            .filter(|&i| 2 * taken[i] + 1 < len[i])
+            .min_by(|&i, &j| ((2 * taken[i] + 1) * len[j]).cmp(&((2 * taken[j] + 1) * len[i])))
+            .unwrap();
+        taken[axis] += 1;
+        half.push(axis);
+    }
+
+    // Axes with an odd number of steps cross a boundary exactly at the midpoint. They are
+    // taken in axis order from the lesser endpoint, i.e. in reverse order from the greater.
+    let mut middle: Vec<usize> = (0..3).filter(|&i| len[i] % 2 == 1).collect();
+    if a > b {
+        middle.reverse();
+    }
+
+    // Unit edges along `axes` from `cur`, stepping in direction `dir`
+    fn walk(
+        mut cur: [isize; 3],
+        axes: impl IntoIterator<Item = usize>,
+        dir: [isize; 3],
+    ) -> (Vec<WireId>, [isize; 3]) {
+        let to_pos = |[x, y, z]: [isize; 3]| (x as usize, y as usize, z as usize);
+        let mut edges = vec![];
+        for axis in axes {
+            let mut next = cur;
+            next[axis] += dir[axis];
+            edges.push(normalize_wire_id((to_pos(cur), to_pos(next))));
+            cur = next;
+        }
+        (edges, cur)
+    }
+
+    let dir = delta.map(|d| d.signum());
+    let (mut edges, cur) = walk(start, half.iter().copied(), dir);
+    let (middle_edges, _) = walk(cur, middle, dir);
+    let (far_edges, _) = walk(end, half.iter().copied(), dir.map(|d| -d));
+    edges.extend(middle_edges);
+    edges.extend(far_edges.into_iter().rev());
+
+    edges
 }
 
 impl Wire {
-    pub fn show_ui(&mut self, ui: &mut Ui) {
+    /// The radius in meters, falling back to that of an uncorrected wire in a grid of spacing `dx`
+    pub fn radius_or_equivalent(&self, dx: f64) -> f64 {
+        self.radius.unwrap_or(THIN_WIRE_EQUIVALENT_RADIUS * dx)
+    }
+
+    /// Returns true if the radius changed, which requires a rebuild of the FDTD grid
+    pub fn show_ui(&mut self, ui: &mut Ui) -> bool {
         ui.horizontal(|ui| {
             ui.label("Resistance: ");
-            ui.add(DragValue::new(&mut self.resistance).suffix(" Ohms"));
+            ui.add(DragValue::new(&mut self.resistance).suffix(" Ohms/cell"));
         });
+        ui.horizontal(|ui| {
+            let mut thin = self.radius.is_some();
+            let mut changed = ui
+                .checkbox(&mut thin, "Radius: ")
+                .on_hover_text(
+                    "Without a radius, the wire is as thick as an uncorrected one-cell wire",
+                )
+                .changed();
+            if changed {
+                self.radius = thin.then_some(DEFAULT_WIRE_RADIUS);
+            }
+            if let Some(radius) = &mut self.radius {
+                changed |= ui
+                    .add(
+                        DragValue::new(radius)
+                            .range(0.0..=f64::INFINITY)
+                            .speed(1e-3)
+                            .suffix(" m"),
+                    )
+                    .changed();
+            } else {
+                ui.label("one-cell equivalent");
+            }
+            changed
+        })
+        .inner
     }
 }
 
+impl LumpedComponent {
+    /// Short schematic-style label, e.g. "C 10µ"
+    pub fn label(&self) -> String {
+        let (prefix, value) = match self.component {
+            TwoTerminalComponent::Resistor(r) => ("R", Some(r)),
+            TwoTerminalComponent::Inductor(l, _) => ("L", Some(l)),
+            TwoTerminalComponent::Capacitor(c) => ("C", Some(c)),
+            TwoTerminalComponent::Battery(v) => ("V", Some(v)),
+            TwoTerminalComponent::CurrentSource(i) => ("I", Some(i)),
+            TwoTerminalComponent::Switch(closed) => {
+                (if closed { "S (closed)" } else { "S (open)" }, None)
+            }
+            TwoTerminalComponent::Diode => ("D", None),
+            TwoTerminalComponent::Wire => ("W", None),
+        };
+        let arrow = if self.reversed { "←" } else { "→" };
+
+        match value {
+            Some(value) => format!("{prefix} {} {arrow}", si_prefix(value)),
+            None => format!("{prefix} {arrow}"),
+        }
+    }
+
+    /// Returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut Ui) -> bool {
+        let resp = match &mut self.component {
+            TwoTerminalComponent::Resistor(r) => {
+                Some(ui.add(DragValue::new(r).prefix("Resistance: ").suffix(" Ohms")))
+            }
+            TwoTerminalComponent::Inductor(l, _) => Some(
+                ui.add(
+                    DragValue::new(l)
+                        .prefix("Inductance: ")
+                        .speed(1e-3)
+                        .suffix(" H"),
+                ),
+            ),
+            TwoTerminalComponent::Capacitor(c) => Some(
+                ui.add(
+                    DragValue::new(c)
+                        .prefix("Capacitance: ")
+                        .speed(1e-6)
+                        .suffix(" F"),
+                ),
+            ),
+            TwoTerminalComponent::Battery(v) => Some(
+                ui.add(
+                    DragValue::new(v)
+                        .prefix("Voltage: ")
+                        .speed(1e-2)
+                        .suffix(" V"),
+                ),
+            ),
+            TwoTerminalComponent::CurrentSource(i) => Some(
+                ui.add(
+                    DragValue::new(i)
+                        .prefix("Current: ")
+                        .speed(1e-3)
+                        .suffix(" A"),
+                ),
+            ),
+            TwoTerminalComponent::Switch(closed) => Some(ui.checkbox(closed, "Closed")),
+            TwoTerminalComponent::Diode | TwoTerminalComponent::Wire => None,
+        };
+        let value_changed = resp.is_some_and(|resp| resp.changed());
+
+        ui.checkbox(&mut self.reversed, "Reverse polarity")
+            .changed()
+            || value_changed
+    }
+}
+
+fn si_prefix(value: f64) -> String {
+    let magnitude = value.abs();
+    let (scale, prefix) = [
+        (1e9, "G"),
+        (1e6, "M"),
+        (1e3, "k"),
+        (1.0, ""),
+        (1e-3, "m"),
+        (1e-6, "µ"),
+        (1e-9, "n"),
+    ]
+    .into_iter()
+    .find(|(scale, _)| magnitude >= *scale)
+    .unwrap_or((1e-12, "p"));
+
+    format!("{}{prefix}", value / scale)
+}
+
 fn screenspace_wire_dist(
     wire_id: WireId,
     paint: &Painter3D,
@@ -418,3 +1674,183 @@ fn screenspace_wire_dist(
 fn proj(u: Vec2, v: Vec2) -> f32 {
     return u.dot(v) / v.dot(v);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every edge is a unit step along one axis, and the edges chain from `a` to `b`
+    fn assert_staircase((a, b): WireId, edges: &[WireId]) {
+        let mut cur = a;
+        for &(p, q) in edges {
+            let steps = [(p.0, q.0), (p.1, q.1), (p.2, q.2)]
+                .iter()
+                .map(|&(x, y)| x.abs_diff(y))
+                .sum::<usize>();
+            assert_eq!(steps, 1, "{p:?}-{q:?} is not a unit edge");
+            assert!(p < q, "{p:?}-{q:?} is not normalized");
+            cur = if p == cur { q } else { p };
+        }
+        assert_eq!(cur, b);
+    }
+
+    #[test]
+    fn diagonal_segment_is_a_staircase() {
+        for segment in [
+            ((2, 1, 1), (5, 1, 1)),
+            ((0, 0, 0), (3, 3, 0)),
+            ((0, 0, 0), (4, 2, 1)),
+            ((5, 0, 2), (1, 3, 0)),
+            ((2, 2, 2), (0, 0, 0)),
+        ] {
+            let edges = segment_edges(segment);
+            let (a, b) = segment;
+            assert_eq!(
+                edges.len(),
+                a.0.abs_diff(b.0) + a.1.abs_diff(b.1) + a.2.abs_diff(b.2)
+            );
+            assert_staircase(segment, &edges);
+        }
+    }
+
+    #[test]
+    fn staircase_is_symmetric_about_its_midpoint() {
+        // Alternates between the axes rather than taking all of one first, and comes
+        // out the same from either end
+        for ((a, b), axes) in [
+            (((0, 0, 0), (2, 2, 0)), vec![0, 1, 1, 0]),
+            (((0, 0, 0), (4, 2, 0)), vec![0, 1, 0, 0, 1, 0]),
+        ] {
+            let edges = segment_edges((a, b));
+            let got: Vec<usize> = edges.iter().map(|&edge| edge_axis(edge)).collect();
+            assert_eq!(got, axes);
+            let mut forward = edges;
+            let mut backward = segment_edges((b, a));
+            forward.sort();
+            backward.sort();
+            assert_eq!(forward, backward);
+        }
+    }
+
+    #[test]
+    fn transforms_round_trip() {
+        // Pivots on a grid point and on a cell center (x = 2, y = 1.5 cells)
+        let p = [3, 1, 4];
+        assert_eq!(mirror(p, [4, 0, 0], 0), [1, 1, 4]);
+        assert_eq!(mirror(p, [0, 3, 0], 1), [3, 2, 4]);
+        for pivot in [[4, 4, 0], [0, 3, 3]] {
+            for axis in 0..3 {
+                assert_eq!(mirror(mirror(p, pivot, axis), pivot, axis), p);
+                if rotation_stays_on_grid(pivot, axis) {
+                    let quarter = rotate90(p, pivot, axis, 1);
+                    assert_ne!(quarter, p);
+                    assert_eq!(rotate90(quarter, pivot, axis, 3), p);
+                }
+            }
+        }
+        // About an edge midpoint the rotated corners leave the grid
+        assert!(!rotation_stays_on_grid([1, 2, 0], 2));
+    }
+
+    /// Unit edges of the wiring, sorted
+    fn wiring_edges(wiring: &Wiring3D) -> Vec<WireId> {
+        let mut edges: Vec<WireId> = wiring
+            .unit_edges()
+            .into_iter()
+            .map(|(edge, _)| edge)
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    /// The image of the wiring under `f` covers the images of its unit edges
+    fn assert_edges_follow(wiring: &Wiring3D, f: impl Fn([isize; 3]) -> [isize; 3]) {
+        let map = |(x, y, z): IntPos3| {
+            let [x, y, z] = f([x, y, z].map(|c| c as isize)).map(|c| c as usize);
+            (x, y, z)
+        };
+        let image = wiring.map_positions(4, &f).unwrap();
+        let mut expected: Vec<WireId> = wiring_edges(wiring)
+            .into_iter()
+            .map(|(p, q)| normalize_wire_id((map(p), map(q))))
+            .collect();
+        expected.sort();
+        assert_eq!(wiring_edges(&image), expected);
+    }
+
+    #[test]
+    fn transforms_keep_the_transformed_unit_edges() {
+        let mut wiring = Wiring3D::default();
+        // Ties before the midpoint, and two odd axes meeting at it
+        wiring.insert(((0, 0, 0), (2, 2, 0)), DEFAULT_WIRE);
+        wiring.insert(((0, 0, 2), (1, 3, 3)), DEFAULT_WIRE);
+
+        let pivot = [3, 3, 3];
+        assert_edges_follow(&wiring, |p| mirror(p, pivot, 0));
+        assert_edges_follow(&wiring, |p| mirror(p, pivot, 2));
+        assert_edges_follow(&wiring, |p| rotate90(p, pivot, 2, 1));
+        assert_edges_follow(&wiring, |p| rotate90(p, pivot, 0, 3));
+    }
+
+    /// An L of two wires, a resistor continuing it and a port at its start
+    fn sample_wiring() -> Wiring3D {
+        let mut wiring = Wiring3D::default();
+        wiring.insert(((1, 1, 1), (1, 4, 1)), DEFAULT_WIRE);
+        wiring.insert(((1, 4, 1), (3, 4, 1)), DEFAULT_WIRE);
+        let resistor = LumpedComponent {
+            component: TwoTerminalComponent::Resistor(50.0),
+            reversed: false,
+        };
+        wiring.components.insert(((3, 4, 1), (4, 4, 1)), resistor);
+        wiring.ports.insert((1, 1, 1), Port("A".into()));
+        wiring
+    }
+
+    #[test]
+    fn overlaps_shared_edges_and_ports_only() {
+        let wiring = sample_wiring();
+        let wire = |a, b| {
+            let mut other = Wiring3D::default();
+            other.insert((a, b), DEFAULT_WIRE);
+            other
+        };
+
+        // Crossing without sharing a unit edge, or only touching an endpoint
+        assert!(!wiring.overlaps(&wire((0, 2, 1), (2, 2, 1))));
+        assert!(!wiring.overlaps(&wire((1, 1, 1), (1, 1, 3))));
+        // Along part of a segment, and along the resistor
+        assert!(wiring.overlaps(&wire((1, 2, 1), (1, 3, 1))));
+        assert!(wiring.overlaps(&wire((4, 4, 1), (3, 4, 1))));
+
+        let mut port = Wiring3D::default();
+        port.ports.insert((1, 1, 1), Port("B".into()));
+        assert!(wiring.overlaps(&port));
+        port.ports = [((5, 5, 5), Port("A".into()))].into();
+        assert!(!wiring.overlaps(&port));
+    }
+
+    #[test]
+    fn new_wires_may_not_cover_components() {
+        let wiring = sample_wiring();
+        // A diagonal whose staircase runs along the resistor, and one beside it
+        assert!(wiring.covers_component(((2, 4, 1), (5, 5, 1))));
+        assert!(!wiring.covers_component(((2, 3, 1), (4, 3, 1))));
+    }
+
+    #[test]
+    fn translation_moves_components_and_ports_and_stays_on_the_grid() {
+        let wiring = sample_wiring();
+        let moved = wiring.translated(6, [1, 0, 2]).unwrap();
+        assert_eq!(
+            moved.components.keys().collect::<Vec<_>>(),
+            [&((4, 4, 3), (5, 4, 3))]
+        );
+        assert_eq!(moved.ports.keys().collect::<Vec<_>>(), [&(2, 1, 3)]);
+        assert!(!moved.overlaps(&wiring));
+
+        // The resistor would leave a grid of width 6, but not an unbounded one
+        assert!(wiring.translated(6, [2, 0, 0]).is_none());
+        assert!(wiring.translated_unbounded([2, 0, 0]).is_some());
+        assert!(wiring.translated_unbounded([0, -2, 0]).is_none());
+    }
+}